@@ -1,5 +1,7 @@
 use ringbuf::{HeapRb, HeapProd, HeapCons};
 use ringbuf::traits::{Observer, Split};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::Instant;
 
 pub type AudioProd = HeapProd<f32>;
 pub type AudioCons = HeapCons<f32>;
@@ -10,3 +12,137 @@ pub fn make_audio_ring(capacity_samples: usize) -> (AudioProd, AudioCons, usize)
     let (prod, cons) = rb.split();
     (prod, cons, cap.get())
 }
+
+
+/// Where the next queued track starts in the ring, for gapless hand-over.
+///
+/// The decoder stores the ring index (samples pushed since the ring was created) of the next
+/// track's first sample in `next_at`. The output callback counts samples popped; when it gets
+/// there it restarts `frames_played` from the boundary, clears `next_at` and bumps `crossings`
+/// so the engine can advance the queue. `next_speed` (f32 bits) is the playback speed of the
+/// next track, stored before `next_at`.
+pub struct TrackBoundary {
+    pub next_at: AtomicU64,
+    pub next_speed: AtomicU32,
+    pub crossings: AtomicU64,
+}
+
+impl TrackBoundary {
+    pub const NONE: u64 = u64::MAX;
+
+    pub fn new() -> Self {
+        Self { next_at: AtomicU64::new(Self::NONE), next_speed: AtomicU32::new(1.0f32.to_bits()), crossings: AtomicU64::new(0) }
+    }
+
+    /// Forget a pending boundary (the ring it referred to is being replaced).
+    pub fn clear(&self) { self.next_at.store(Self::NONE, Ordering::Release); }
+}
+
+/// Seeking in place: what's queued in the ring is dropped without replacing the ring.
+///
+/// Each seek is a generation. The engine bumps `requested`; the output callback fades out
+/// (if `fade_out` is set) and plays silence, leaving the ring alone. Once the decoder has
+/// repositioned it stores the ring index of its first new sample in `at`, the position that
+/// sample stands for in `frames`, its playback speed in `speed` (f32 bits), and the generation in
+/// `ready`. The callback then discards
+/// everything before `at`, fades the new audio in, and stores the generation in `done` along
+/// with the time it did so.
+pub struct SeekFlush {
+    pub requested: AtomicU64,
+    pub fade_out: AtomicBool,
+    pub at: AtomicU64,
+    pub frames: AtomicU64,
+    pub speed: AtomicU32,
+    pub ready: AtomicU64,
+    pub done: AtomicU64,
+    /// [`now_us`](Self::now_us) when `done` was last stored.
+    pub done_at_us: AtomicU64,
+    epoch: Instant,
+}
+
+impl SeekFlush {
+    pub fn new() -> Self {
+        Self {
+            requested: AtomicU64::new(0),
+            fade_out: AtomicBool::new(false),
+            at: AtomicU64::new(0),
+            frames: AtomicU64::new(0),
+            speed: AtomicU32::new(1.0f32.to_bits()),
+            ready: AtomicU64::new(0),
+            done: AtomicU64::new(0),
+            done_at_us: AtomicU64::new(0),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Microseconds on a clock shared by the engine and the output callback.
+    pub fn now_us(&self) -> u64 { self.epoch.elapsed().as_micros() as u64 }
+
+    /// Start a new generation; returns it.
+    pub fn request(&self, fade_out: bool) -> u64 {
+        self.fade_out.store(fade_out, Ordering::Relaxed);
+        self.requested.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Decoder side: new audio for `generation` starts at ring index `at`.
+    pub fn publish(&self, generation: u64, at: u64, frames: u64, speed: f32) {
+        self.at.store(at, Ordering::Relaxed);
+        self.frames.store(frames, Ordering::Relaxed);
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+        self.ready.store(generation, Ordering::Release);
+    }
+
+    /// The decoder has published the latest request but the output hasn't got there yet: what
+    /// is queued is partly stale.
+    pub fn in_progress(&self) -> bool {
+        self.done.load(Ordering::Acquire) != self.requested.load(Ordering::Acquire)
+    }
+
+    /// Drop any flush in progress (the ring it referred to is being replaced).
+    pub fn settle(&self) {
+        let generation = self.requested.load(Ordering::Acquire);
+        self.ready.store(generation, Ordering::Release);
+        self.done.store(generation, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seek_generations() {
+        let seek = SeekFlush::new();
+        assert!(!seek.in_progress());
+        let first = seek.request(true);
+        assert_eq!(first, 1);
+        assert!(seek.in_progress() && seek.fade_out.load(Ordering::Relaxed));
+
+        // back to back: the latest request decides the fade and stays pending until it's done
+        let second = seek.request(false);
+        assert_eq!(second, 2);
+        assert!(!seek.fade_out.load(Ordering::Relaxed));
+        seek.publish(first, 10, 100, 1.0);
+        seek.done.store(first, Ordering::Release);
+        assert!(seek.in_progress());
+        seek.publish(second, 20, 200, 1.5);
+        assert_eq!(seek.ready.load(Ordering::Acquire), second);
+        assert_eq!((seek.at.load(Ordering::Relaxed), seek.frames.load(Ordering::Relaxed)), (20, 200));
+        assert_eq!(f32::from_bits(seek.speed.load(Ordering::Relaxed)), 1.5);
+        seek.done.store(second, Ordering::Release);
+        assert!(!seek.in_progress());
+    }
+
+    #[test]
+    fn settle_drops_a_pending_seek() {
+        let seek = SeekFlush::new();
+        seek.request(true);
+        let latest = seek.request(true);
+        seek.settle();
+        assert!(!seek.in_progress());
+        assert_eq!(seek.ready.load(Ordering::Acquire), latest);
+        // the next request is a new generation again
+        assert_eq!(seek.request(true), latest + 1);
+        assert!(seek.in_progress());
+    }
+}
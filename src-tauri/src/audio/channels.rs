@@ -0,0 +1,308 @@
+//! Channel layout conversion between the source and the output.
+//!
+//! Sources describe their layout with symphonia's [`Channels`] mask; output devices only report
+//! a channel count, so they are assumed to use the WAVE/SMPTE order for that count. Downmixes
+//! follow ITU-R BS.775: centre and surrounds go to the front pair at −3 dB and the LFE is
+//! dropped. Upmixes place each source channel on its own speaker and leave the others silent.
+
+use serde::{Deserialize, Serialize};
+use symphonia::core::audio::Channels;
+
+const FL: Channels = Channels::FRONT_LEFT;
+const FR: Channels = Channels::FRONT_RIGHT;
+const FC: Channels = Channels::FRONT_CENTRE;
+const LFE: Channels = Channels::LFE1;
+const RL: Channels = Channels::REAR_LEFT;
+const RR: Channels = Channels::REAR_RIGHT;
+const RC: Channels = Channels::REAR_CENTRE;
+const SL: Channels = Channels::SIDE_LEFT;
+const SR: Channels = Channels::SIDE_RIGHT;
+
+/// −3 dB
+const HALF_POWER: f32 = std::f32::consts::FRAC_1_SQRT_2;
+const MAX_CHANNELS: usize = 8;
+
+/// How source channels are mapped onto the output's.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelMatrix {
+    /// BS.775 coefficients, scaled down where an output would otherwise add up past full scale.
+    #[default]
+    Standard,
+    /// BS.775 coefficients as they are: fronts keep their level, dense mixes can clip.
+    Unscaled,
+    /// Standard, with the LFE folded into the front pair at −3 dB (no subwoofer).
+    WithLfe,
+    /// `matrix[output][source]`, for sources and outputs of exactly that shape; others use
+    /// `Standard`.
+    Custom { matrix: Vec<Vec<f32>> },
+}
+
+impl ChannelMatrix {
+    pub fn from_json(s: &str) -> Option<Self> {
+        serde_json::from_str(s).ok()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let Self::Custom { matrix } = self else { return Ok(()) };
+        let cols = matrix.first().map_or(0, Vec::len);
+        if !(1..=MAX_CHANNELS).contains(&matrix.len()) || !(1..=MAX_CHANNELS).contains(&cols) {
+            return Err(format!("A custom matrix needs 1..={MAX_CHANNELS} rows and columns"));
+        }
+        if matrix.iter().any(|row| row.len() != cols) {
+            return Err("All rows of a custom matrix need the same length".into());
+        }
+        if !matrix.iter().flatten().all(|c| c.is_finite() && c.abs() <= 4.0) {
+            return Err("Matrix coefficients must be within ±4".into());
+        }
+        Ok(())
+    }
+}
+
+/// Layout assumed for `count` channels without a mask, and for an output of `count` channels.
+pub fn default_layout(count: usize) -> Channels {
+    match count {
+        1 => FC,
+        2 => FL | FR,
+        3 => FL | FR | FC,
+        4 => FL | FR | RL | RR,
+        5 => FL | FR | FC | RL | RR,
+        6 => FL | FR | FC | LFE | RL | RR,
+        7 => FL | FR | FC | LFE | RC | SL | SR,
+        8 => FL | FR | FC | LFE | RL | RR | SL | SR,
+        n => Channels::from_bits_truncate((1u32 << n.min(26)) - 1),
+    }
+}
+
+/// The source's layout from its mask, if it has one that matches the channel count. Mono is
+/// always centre (some containers call it front left).
+pub fn source_layout(mask: Option<Channels>, count: usize) -> Channels {
+    match mask {
+        Some(mask) if count > 1 && mask.count() == count => mask,
+        _ => default_layout(count),
+    }
+}
+
+/// The other surround on the same side (rear ↔ side).
+fn partner(ch: Channels) -> Option<Channels> {
+    match ch {
+        RL => Some(SL),
+        SL => Some(RL),
+        RR => Some(SR),
+        SR => Some(RR),
+        _ => None,
+    }
+}
+
+/// Where source channel `ch` goes on an output without that speaker.
+fn fold(ch: Channels, src: Channels, dst: Channels, with_lfe: bool) -> Vec<(Channels, f32)> {
+    let pair = |l, r, g| vec![(l, g), (r, g)];
+    // a surround without its speaker: onto the other surround pair, else into the front of its
+    // side at −3 dB
+    let surround = |other: Channels, front: Channels| {
+        if dst.contains(other) { vec![(other, 1.0)] } else { vec![(front, HALF_POWER)] }
+    };
+    match ch {
+        // mono on a pair: dual mono at full level
+        FC if src == FC => pair(FL, FR, 1.0),
+        FC => pair(FL, FR, HALF_POWER),
+        LFE if with_lfe => pair(FL, FR, HALF_POWER),
+        LFE => Vec::new(),
+        RL => surround(SL, FL),
+        RR => surround(SR, FR),
+        SL => surround(RL, FL),
+        SR => surround(RR, FR),
+        RC if dst.contains(RL | RR) => pair(RL, RR, HALF_POWER),
+        RC if dst.contains(SL | SR) => pair(SL, SR, HALF_POWER),
+        RC => pair(FL, FR, 0.5),
+        Channels::FRONT_LEFT_CENTRE | Channels::FRONT_LEFT_WIDE => vec![(FL, 1.0)],
+        Channels::FRONT_RIGHT_CENTRE | Channels::FRONT_RIGHT_WIDE => vec![(FR, 1.0)],
+        // height and rear-centre pairs: onto the nearest bed speaker, −3 dB
+        Channels::TOP_FRONT_LEFT | Channels::FRONT_LEFT_HIGH => vec![(FL, HALF_POWER)],
+        Channels::TOP_FRONT_RIGHT | Channels::FRONT_RIGHT_HIGH => vec![(FR, HALF_POWER)],
+        Channels::TOP_REAR_LEFT | Channels::REAR_LEFT_CENTRE => surround(SL, FL).into_iter().map(|(c, g)| (c, g * HALF_POWER)).collect(),
+        Channels::TOP_REAR_RIGHT | Channels::REAR_RIGHT_CENTRE => surround(SR, FR).into_iter().map(|(c, g)| (c, g * HALF_POWER)).collect(),
+        _ => pair(FL, FR, 0.5),
+    }
+}
+
+/// `matrix[output][source]` from `src` to an output of `dst_ch` channels.
+pub fn matrix_for(src: Channels, dst_ch: usize, mode: &ChannelMatrix) -> Vec<Vec<f32>> {
+    let src_ch = src.count();
+    if let ChannelMatrix::Custom { matrix } = mode {
+        if matrix.len() == dst_ch && matrix.iter().all(|row| row.len() == src_ch) { return matrix.clone(); }
+    }
+    let dst = default_layout(dst_ch);
+    if dst == FC && src != FC {
+        // mono output: the stereo fold-down, summed
+        let stereo = matrix_for(src, 2, mode);
+        return vec![stereo[0].iter().zip(&stereo[1]).map(|(l, r)| 0.5 * (l + r)).collect()];
+    }
+
+    let outputs: Vec<Channels> = dst.iter().collect();
+    let mut m = vec![vec![0.0f32; src_ch]; dst_ch];
+    for (s, ch) in src.iter().enumerate() {
+        let routes = if dst.contains(ch) { vec![(ch, 1.0)] } else { fold(ch, src, dst, *mode == ChannelMatrix::WithLfe) };
+        // rear and side surrounds that end up on the same speaker share it at −3 dB each
+        let shared = partner(ch).is_some_and(|p| src.contains(p) && !dst.contains(ch | p));
+        let scale = if shared { HALF_POWER } else { 1.0 };
+        for (to, gain) in routes {
+            if let Some(o) = outputs.iter().position(|c| *c == to) { m[o][s] += gain * scale; }
+        }
+    }
+    if *mode != ChannelMatrix::Unscaled {
+        let loudest = m.iter().map(|row| row.iter().map(|c| c.abs()).sum::<f32>()).fold(0.0, f32::max);
+        if loudest > 1.0 { m.iter_mut().flatten().for_each(|c| *c /= loudest); }
+    }
+    m
+}
+
+/// Applies a channel matrix to interleaved frames. Same channel counts pass through as they
+/// are unless a custom matrix of that shape is set.
+#[derive(Debug, Clone)]
+pub struct ChannelMixer {
+    src_ch: usize,
+    dst_ch: usize,
+    /// Row-major `dst_ch × src_ch`; `None` passes frames through.
+    coeffs: Option<Vec<f32>>,
+}
+
+impl ChannelMixer {
+    pub fn new(src: Channels, dst_ch: usize, mode: &ChannelMatrix) -> Self {
+        let src_ch = src.count();
+        let custom = matches!(mode, ChannelMatrix::Custom { matrix } if matrix.len() == dst_ch && matrix.iter().all(|r| r.len() == src_ch));
+        let coeffs = (src_ch != dst_ch || custom).then(|| matrix_for(src, dst_ch, mode).concat());
+        Self { src_ch, dst_ch, coeffs }
+    }
+
+    pub fn process(&self, input: &[f32]) -> Vec<f32> {
+        let Some(coeffs) = &self.coeffs else { return input.to_vec() };
+        let mut out = Vec::with_capacity(input.len() / self.src_ch * self.dst_ch);
+        for frame in input.chunks_exact(self.src_ch) {
+            for row in coeffs.chunks_exact(self.src_ch) {
+                out.push(row.iter().zip(frame).map(|(c, x)| c * x).sum());
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const H: f32 = HALF_POWER;
+    const SURROUND_5_1: Channels = Channels::from_bits_truncate(FL.bits() | FR.bits() | FC.bits() | LFE.bits() | RL.bits() | RR.bits());
+
+    fn assert_matrix(got: &[Vec<f32>], want: &[&[f32]]) {
+        assert_eq!(got.len(), want.len(), "{got:?}");
+        for (g, w) in got.iter().zip(want) {
+            assert_eq!(g.len(), w.len(), "{got:?}");
+            assert!(g.iter().zip(*w).all(|(a, b)| (a - b).abs() < 1e-6), "{got:?} vs {want:?}");
+        }
+    }
+
+    fn loudest_row(m: &[Vec<f32>]) -> f32 {
+        m.iter().map(|row| row.iter().map(|c| c.abs()).sum::<f32>()).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn five_one_to_stereo() {
+        // BS.775: centre and surrounds at −3 dB, LFE dropped
+        let m = matrix_for(SURROUND_5_1, 2, &ChannelMatrix::Unscaled);
+        assert_matrix(&m, &[&[1.0, 0.0, H, 0.0, H, 0.0], &[0.0, 1.0, H, 0.0, 0.0, H]]);
+
+        // Standard scales the same shape so no output can pass full scale
+        let s = matrix_for(SURROUND_5_1, 2, &ChannelMatrix::Standard);
+        let k = 1.0 / (1.0 + 2.0 * H);
+        assert_matrix(&s, &[&[k, 0.0, H * k, 0.0, H * k, 0.0], &[0.0, k, H * k, 0.0, 0.0, H * k]]);
+        assert!((loudest_row(&s) - 1.0).abs() < 1e-6);
+
+        let lfe = matrix_for(SURROUND_5_1, 2, &ChannelMatrix::WithLfe);
+        assert!(lfe[0][3] > 0.0 && lfe[1][3] > 0.0);
+        assert!((loudest_row(&lfe) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn seven_one_to_five_one() {
+        let src = default_layout(8);
+        let m = matrix_for(src, 6, &ChannelMatrix::Unscaled);
+        // rear and side surrounds share the rear speakers at −3 dB each
+        assert_matrix(&m, &[
+            &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0, H, 0.0, H, 0.0],
+            &[0.0, 0.0, 0.0, 0.0, 0.0, H, 0.0, H],
+        ]);
+    }
+
+    #[test]
+    fn quad_and_five_one() {
+        let quad = default_layout(4);
+        // up: each channel on its own speaker, centre and LFE silent
+        let up = matrix_for(quad, 6, &ChannelMatrix::Standard);
+        assert_matrix(&up, &[
+            &[1.0, 0.0, 0.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 1.0, 0.0],
+            &[0.0, 0.0, 0.0, 1.0],
+        ]);
+
+        // down: centre split onto the fronts, LFE dropped, surrounds kept
+        let down = matrix_for(SURROUND_5_1, 4, &ChannelMatrix::Unscaled);
+        assert_matrix(&down, &[
+            &[1.0, 0.0, H, 0.0, 0.0, 0.0],
+            &[0.0, 1.0, H, 0.0, 0.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+        ]);
+    }
+
+    #[test]
+    fn mono_and_stereo() {
+        let mono = source_layout(Some(FL), 1);
+        assert_eq!(mono, FC, "mono is centre whatever the mask says");
+        assert_matrix(&matrix_for(mono, 2, &ChannelMatrix::Standard), &[&[1.0], &[1.0]]);
+        assert_matrix(&matrix_for(FL | FR, 1, &ChannelMatrix::Standard), &[&[0.5, 0.5]]);
+
+        let up = ChannelMixer::new(mono, 2, &ChannelMatrix::Standard);
+        assert_eq!(up.process(&[0.25, -0.5]), [0.25, 0.25, -0.5, -0.5]);
+        let down = ChannelMixer::new(FL | FR, 1, &ChannelMatrix::Standard);
+        assert_eq!(down.process(&[1.0, 0.0, 0.5, 0.5]), [0.5, 0.5]);
+    }
+
+    #[test]
+    fn custom_matrix_only_for_its_shape() {
+        let swap = ChannelMatrix::Custom { matrix: vec![vec![0.0, 1.0], vec![1.0, 0.0]] };
+        let mixer = ChannelMixer::new(FL | FR, 2, &swap);
+        assert_eq!(mixer.process(&[0.1, 0.2]), [0.2, 0.1]);
+
+        // other shapes fall back to the standard matrices
+        assert_eq!(matrix_for(SURROUND_5_1, 2, &swap), matrix_for(SURROUND_5_1, 2, &ChannelMatrix::Standard));
+        assert_eq!(ChannelMixer::new(FL | FR, 2, &ChannelMatrix::Standard).process(&[0.1, 0.2]), [0.1, 0.2]);
+    }
+
+    #[test]
+    fn validate() {
+        let custom = |matrix: Vec<Vec<f32>>| ChannelMatrix::Custom { matrix };
+        assert!(ChannelMatrix::Standard.validate().is_ok());
+        assert!(custom(vec![vec![1.0, 0.0], vec![0.0, 1.0]]).validate().is_ok());
+        assert!(custom(vec![]).validate().is_err());
+        assert!(custom(vec![vec![]]).validate().is_err());
+        assert!(custom(vec![vec![1.0]; 9]).validate().is_err());
+        assert!(custom(vec![vec![1.0, 0.0], vec![1.0]]).validate().is_err(), "ragged");
+        assert!(custom(vec![vec![4.5]]).validate().is_err());
+        assert!(custom(vec![vec![f32::NAN]]).validate().is_err());
+
+        let json = custom(vec![vec![0.5, 0.5]]).to_json();
+        assert_eq!(ChannelMatrix::from_json(&json), Some(custom(vec![vec![0.5, 0.5]])));
+    }
+}
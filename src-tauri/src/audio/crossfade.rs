@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+
+/// Gain shape of a crossfade. The outgoing track gets the mirror image of the incoming one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FadeCurve {
+    /// sin/cos: constant total power, no dip for uncorrelated material.
+    EqualPower,
+    Linear,
+    /// Raised cosine: slow start and end, quick middle.
+    SCurve,
+}
+
+impl FadeCurve {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::EqualPower => "equal_power",
+            Self::Linear => "linear",
+            Self::SCurve => "s_curve",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "equal_power" => Some(Self::EqualPower),
+            "linear" => Some(Self::Linear),
+            "s_curve" => Some(Self::SCurve),
+            _ => None,
+        }
+    }
+
+    /// (outgoing, incoming) gains at progress `t` in `0.0..=1.0`.
+    pub fn gains(self, t: f32) -> (f32, f32) {
+        use std::f32::consts::PI;
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::EqualPower => ((t * PI * 0.5).cos(), (t * PI * 0.5).sin()),
+            Self::Linear => (1.0 - t, t),
+            Self::SCurve => {
+                let g = 0.5 - 0.5 * (t * PI).cos();
+                (1.0 - g, g)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CrossfadeConfig {
+    /// Overlap between consecutive tracks; 0 disables crossfading (plain gapless).
+    pub seconds: f32,
+    pub curve: FadeCurve,
+    /// Keep album transitions gapless instead of fading them.
+    pub skip_same_album: bool,
+}
+
+impl Default for CrossfadeConfig {
+    fn default() -> Self {
+        Self { seconds: 0.0, curve: FadeCurve::EqualPower, skip_same_album: false }
+    }
+}
+
+impl CrossfadeConfig {
+    pub const MAX_SECONDS: f32 = 12.0;
+
+    /// Overlap length in interleaved samples at the given output format.
+    pub fn samples(&self, sample_rate: u32, channels: u16) -> usize {
+        let secs = self.seconds.clamp(0.0, Self::MAX_SECONDS);
+        (secs * sample_rate as f32) as usize * channels as usize
+    }
+}
+
+/// Mix the buffered end of the outgoing track (`tail`, interleaved) with the start of the
+/// incoming one (`head`) into `out`. `done` frames of the fade have already been mixed and
+/// `total` is its full length in frames; returns how many frames of `head` were consumed.
+pub fn mix_overlap(
+    curve: FadeCurve,
+    tail: &mut std::collections::VecDeque<f32>,
+    head: &[f32],
+    channels: usize,
+    done: usize,
+    total: usize,
+    out: &mut Vec<f32>,
+) -> usize {
+    let frames = (tail.len() / channels).min(head.len() / channels);
+    for f in 0..frames {
+        let (g_out, g_in) = curve.gains((done + f) as f32 / total.max(1) as f32);
+        for c in 0..channels {
+            let a = tail.pop_front().unwrap_or(0.0);
+            out.push(a * g_out + head[f * channels + c] * g_in);
+        }
+    }
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const CURVES: [FadeCurve; 3] = [FadeCurve::EqualPower, FadeCurve::Linear, FadeCurve::SCurve];
+
+    #[test]
+    fn curves_run_from_outgoing_to_incoming() {
+        for curve in CURVES {
+            assert_eq!(curve.gains(0.0), (1.0, 0.0), "{curve:?}");
+            let (o, i) = curve.gains(1.0);
+            assert!(o.abs() < 1e-6 && (i - 1.0).abs() < 1e-6, "{curve:?}");
+            // out of range progress is clamped
+            assert_eq!(curve.gains(-1.0), curve.gains(0.0));
+            assert_eq!(curve.gains(2.0), curve.gains(1.0));
+        }
+    }
+
+    #[test]
+    fn equal_power_keeps_power_constant() {
+        for k in 0..=100 {
+            let (o, i) = FadeCurve::EqualPower.gains(k as f32 / 100.0);
+            assert!((o * o + i * i - 1.0).abs() < 1e-5);
+        }
+        // −3 dB each at the midpoint
+        let (o, i) = FadeCurve::EqualPower.gains(0.5);
+        assert!((o - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!((i - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn linear_and_s_curve_sum_to_unity() {
+        for curve in [FadeCurve::Linear, FadeCurve::SCurve] {
+            for k in 0..=100 {
+                let (o, i) = curve.gains(k as f32 / 100.0);
+                assert!((o + i - 1.0).abs() < 1e-6, "{curve:?}");
+            }
+            assert_eq!(curve.gains(0.5), (0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn overlap_accounting() {
+        // 10 stereo frames of tail, mixed against heads of 4 and then 8 frames
+        let mut tail: VecDeque<f32> = (0..20).map(|_| 1.0).collect();
+        let mut out = Vec::new();
+        let used = mix_overlap(FadeCurve::Linear, &mut tail, &[0.0; 8], 2, 0, 10, &mut out);
+        assert_eq!(used, 4);
+        assert_eq!((tail.len(), out.len()), (12, 8));
+        // the fade picks up where it left off
+        let used = mix_overlap(FadeCurve::Linear, &mut tail, &[0.0; 16], 2, 4, 10, &mut out);
+        assert_eq!(used, 6, "only what's left of the tail");
+        assert!(tail.is_empty());
+        assert_eq!(out.len(), 20);
+        for (f, frame) in out.chunks(2).enumerate() {
+            let g = 1.0 - f as f32 / 10.0;
+            assert!(frame.iter().all(|x| (x - g).abs() < 1e-6), "frame {f}: {frame:?}");
+        }
+    }
+
+    #[test]
+    fn equal_power_midpoint_of_correlated_signals() {
+        // the same signal on both sides sums to √2 at the midpoint (the price of no dip for
+        // uncorrelated material)
+        let mut tail: VecDeque<f32> = VecDeque::from(vec![0.5; 2]);
+        let mut out = Vec::new();
+        mix_overlap(FadeCurve::EqualPower, &mut tail, &[0.5; 2], 1, 1, 2, &mut out);
+        assert!((out[0] - 0.5 * std::f32::consts::SQRT_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn samples_are_clamped_and_interleaved() {
+        let cfg = |seconds| CrossfadeConfig { seconds, ..Default::default() };
+        assert_eq!(cfg(0.0).samples(48_000, 2), 0);
+        assert_eq!(cfg(1.5).samples(48_000, 2), 144_000);
+        assert_eq!(cfg(100.0).samples(1_000, 1), 12_000);
+        assert_eq!(cfg(-1.0).samples(48_000, 2), 0);
+    }
+}
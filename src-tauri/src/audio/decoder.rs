@@ -1,161 +1,572 @@
-use crate::audio::engine::{DecoderControl, EngineEvent};
+use crate::audio::buffer::{SeekFlush, TrackBoundary};
+use crate::audio::channels::{source_layout, ChannelMatrix, ChannelMixer};
+use crate::audio::crossfade::{mix_overlap, CrossfadeConfig};
+use crate::audio::dsp::{DspChain, DspConfig};
+use crate::audio::resample::{ResampleQuality, Resampler};
+use crate::audio::stretch::TimeStretch;
+use crate::audio::engine::{DecoderControl, EngineEvent, QueuedTrack};
+use crate::audio::latency::{ms_to_samples, LatencyProfile};
+use crate::audio::output::SampleDepth;
+use log::error;
 use ringbuf::{HeapProd};
 use ringbuf::traits::Producer;
-use std::sync::mpsc;
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::Ordering;
 use std::time::Duration;
 
+use symphonia::core::audio::{Channels, SampleBuffer};
+use symphonia::core::sample::SampleFormat;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+use symphonia::core::units::{Time, TimeBase};
+
+/// Encoder delay and real length of an iTunes-encoded AAC file, from its `iTunSMPB` tag.
+/// (MP3 delay/padding from the LAME/Xing header is stripped by symphonia itself.)
+#[derive(Clone, Copy, Debug)]
+struct GaplessTrim { delay: u64, total: u64 }
+
+/// `iTunSMPB` is a list of hex words: reserved, delay, padding, original length, ...
+fn parse_itunsmpb(value: &str) -> Option<GaplessTrim> {
+    let words: Vec<u64> = value.split_whitespace()
+        .map(|w| u64::from_str_radix(w, 16))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match words.as_slice() {
+        [_, delay, _padding, total, ..] if *total > 0 => Some(GaplessTrim { delay: *delay, total: *total }),
+        _ => None,
+    }
+}
+
+fn album_of(rev: &MetadataRevision) -> Option<String> {
+    rev.tags().iter()
+        .find(|t| t.std_key == Some(StandardTagKey::Album))
+        .map(|t| t.value.to_string().trim().to_lowercase())
+        .filter(|a| !a.is_empty())
+}
+
+/// One opened audio file: demuxer + decoder, yielding interleaved f32 at the source rate with
+/// encoder delay/padding and pre-seek frames already cut off.
+pub struct Source {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    pub sample_rate: u32,
+    pub channels: usize,
+    /// How the samples are stored; `None` for lossy codecs, which have no fixed depth.
+    pub depth: Option<SampleDepth>,
+    /// Speaker of each channel, in interleaving order.
+    pub layout: Channels,
+    /// Album tag, used to keep album transitions gapless when crossfading.
+    pub album: Option<String>,
+    /// Linear normalization gain (ReplayGain), applied to every decoded sample.
+    pub gain: f32,
+    time_base: Option<TimeBase>,
+    n_frames: Option<u64>,
+    trim: Option<GaplessTrim>,
+    /// Decoded frames before this (source-frame index, delay included) are dropped.
+    skip_until: u64,
+    sample_buf: Option<SampleBuffer<f32>>,
+}
+
+impl Source {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        use symphonia::core::io::MediaSourceStream;
+        use symphonia::core::meta::MetadataOptions;
+        use symphonia::core::probe::Hint;
+        use std::fs::File;
+        use std::path::Path;
+
+        let file = Box::new(File::open(path)?);
+        let mss = MediaSourceStream::new(file, Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = Path::new(path).extension().and_then(|e| e.to_str()) { hint.with_extension(ext); }
+        let opts = FormatOptions { enable_gapless: true, ..Default::default() };
+        let mut probed = symphonia::default::get_probe().format(&hint, mss, &opts, &MetadataOptions::default())?;
+        let probed_album = probed.metadata.get().and_then(|m| m.current().and_then(album_of));
+        let mut format = probed.format;
+
+        let track = format.tracks().iter().find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
+            .ok_or_else(|| anyhow::anyhow!("No supported audio tracks"))?;
+        let track_id = track.id;
+        let decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+        let sample_rate = track.codec_params.sample_rate.unwrap_or(44_100);
+        let channels = track.codec_params.channels.map(|c| c.count()).unwrap_or(2);
+        let depth = match track.codec_params.sample_format {
+            Some(SampleFormat::F32) => Some(SampleDepth::Float(32)),
+            Some(SampleFormat::F64) => Some(SampleDepth::Float(64)),
+            _ => track.codec_params.bits_per_sample.map(SampleDepth::Int),
+        };
+        let layout = source_layout(track.codec_params.channels, channels);
+        let time_base = track.codec_params.time_base;
+        let n_frames = track.codec_params.n_frames;
+
+        let trim = format.metadata().current()
+            .and_then(|rev| rev.tags().iter().find(|t| t.key.ends_with("iTunSMPB")))
+            .and_then(|t| parse_itunsmpb(&t.value.to_string()));
+        let album = probed_album.or_else(|| format.metadata().current().and_then(album_of));
+
+        Ok(Self {
+            format, decoder, track_id, sample_rate, channels, depth, layout, album, gain: 1.0, time_base, n_frames, trim,
+            skip_until: trim.map_or(0, |t| t.delay),
+            sample_buf: None,
+        })
+    }
+
+    /// Playable length, excluding encoder delay/padding when known.
+    pub fn duration_seconds(&mut self) -> anyhow::Result<f64> {
+        if let Some(t) = self.trim { return Ok(t.total as f64 / self.sample_rate as f64); }
+        let tb = self.time_base.ok_or_else(|| anyhow::anyhow!("no time_base"))?;
+        if let Some(nf) = self.n_frames { return Ok(nf as f64 * tb.numer as f64 / tb.denom as f64); }
+
+        // No frame count in the header: walk the packets.
+        let mut last_ts = 0u64;
+        loop {
+            match self.format.next_packet() {
+                Ok(p) => { if p.track_id() == self.track_id { last_ts = last_ts.max(p.ts()); } }
+                Err(Error::ResetRequired) => continue,
+                Err(_) => break, // EOF
+            }
+        }
+        Ok(last_ts as f64 * tb.numer as f64 / tb.denom as f64)
+    }
+
+    pub fn seek(&mut self, seconds: f64) {
+        let delay = self.trim.map_or(0, |t| t.delay);
+        let target = (seconds.max(0.0) * self.sample_rate as f64) as u64 + delay;
+        let raw = target as f64 / self.sample_rate as f64;
+        let secs_whole = raw.floor() as u64; let frac = raw - secs_whole as f64;
+        let to = SeekTo::Time { time: Time { seconds: secs_whole, frac }, track_id: Some(self.track_id) };
+        if self.format.seek(SeekMode::Accurate, to).is_ok() {
+            self.decoder.reset();
+            self.skip_until = target;
+        }
+    }
+
+    fn ts_to_frame(&self, ts: u64) -> u64 {
+        match self.time_base {
+            Some(tb) if tb.denom != self.sample_rate || tb.numer != 1 => {
+                (ts as u128 * tb.numer as u128 * self.sample_rate as u128 / tb.denom as u128) as u64
+            }
+            _ => ts,
+        }
+    }
+
+    /// Next block of interleaved samples; `Ok(None)` at the end of the track.
+    pub fn next_block(&mut self) -> anyhow::Result<Option<&[f32]>> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(p) => p,
+                Err(Error::ResetRequired) => { self.decoder.reset(); continue; }
+                Err(_) => return Ok(None), // natural end
+            };
+            if packet.track_id() != self.track_id { continue; }
+            let first = self.ts_to_frame(packet.ts());
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(d) => d,
+                Err(Error::DecodeError(_)) => continue,
+                Err(Error::ResetRequired) => { self.decoder.reset(); continue; }
+                Err(e) => return Err(e.into()),
+            };
+            let buf = self.sample_buf.get_or_insert_with(|| SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec()));
+            buf.copy_interleaved_ref(decoded);
+
+            // Keep only [skip_until, delay + total) of this packet's frames.
+            let frames = (buf.samples().len() / self.channels) as u64;
+            let end_frame = self.trim.map_or(u64::MAX, |t| t.delay + t.total);
+            if first >= end_frame { return Ok(None); }
+            let lo = self.skip_until.saturating_sub(first).min(frames);
+            let hi = end_frame.saturating_sub(first).min(frames);
+            if lo >= hi { continue; }
+            let ch = self.channels;
+            let out = &mut self.sample_buf.as_mut().unwrap().samples_mut()[lo as usize * ch..hi as usize * ch];
+            if self.gain != 1.0 {
+                out.iter_mut().for_each(|s| *s *= self.gain);
+            }
+            return Ok(Some(out));
+        }
+    }
+}
+
+/// Playback settings the decoder applies; changed at runtime through `DecoderControl`.
+#[derive(Debug, Clone, Default)]
+pub struct DecoderSettings {
+    pub crossfade: CrossfadeConfig,
+    /// Used for resamplers created from now on (next track with a different format, or seek).
+    pub resample: ResampleQuality,
+    /// Applied to everything written to the ring.
+    pub dsp: DspConfig,
+    /// The output runs at the source's own format: no DSP or crossfade, and only tracks of
+    /// that format are chained (the engine reopens the stream for the others).
+    pub bit_perfect: bool,
+    /// How far ahead of the output the decoder may fill the ring.
+    pub latency: LatencyProfile,
+    /// Down/upmix from the source's channel layout to the output's.
+    pub channel_matrix: ChannelMatrix,
+}
+
+impl DecoderSettings {
+    /// The matrix in effect: bit-perfect output only ever gets the source's own channels.
+    fn matrix(&self) -> ChannelMatrix {
+        if self.bit_perfect { ChannelMatrix::Standard } else { self.channel_matrix.clone() }
+    }
+}
+
+/// Control messages that arrived but haven't been acted on yet.
+struct Pending {
+    next_file: Option<QueuedTrack>,
+    /// Tracks chained into so far; hints sent before the engine heard of the last one are stale.
+    switches: u64,
+    /// New gain for the track being decoded.
+    gain: Option<f32>,
+    settings: DecoderSettings,
+    /// `settings.dsp` changed since the chain last picked it up.
+    dsp_changed: bool,
+    /// `settings.channel_matrix` changed since the plan last picked it up.
+    matrix_changed: bool,
+    /// Track, position and generation of the latest seek; what's being written is stale.
+    seek: Option<(QueuedTrack, f64, u64)>,
+    stop: bool,
+}
+
+impl Pending {
+    fn drain(&mut self, ctrl_rx: &mpsc::Receiver<DecoderControl>) {
+        loop {
+            match ctrl_rx.try_recv() {
+                Ok(DecoderControl::Stop) | Err(mpsc::TryRecvError::Disconnected) => { self.stop = true; return; }
+                Ok(DecoderControl::SwitchTo { track, after }) => if after == self.switches { self.next_file = track },
+                Ok(DecoderControl::SetGain(g)) => self.gain = Some(g),
+                Ok(DecoderControl::SetCrossfade(cfg)) => self.settings.crossfade = cfg,
+                Ok(DecoderControl::SetResampleQuality(q)) => self.settings.resample = q,
+                Ok(DecoderControl::SetDsp(cfg)) => { self.settings.dsp = cfg; self.dsp_changed = true; }
+                Ok(DecoderControl::SetChannelMatrix(m)) => { self.settings.channel_matrix = m; self.matrix_changed = true; }
+                Ok(DecoderControl::Seek { track, seconds, generation }) => self.seek = Some((track, seconds, generation)),
+                Err(mpsc::TryRecvError::Empty) => return,
+            }
+        }
+    }
+}
+
+/// Producer side of the ring, counting what it has written (boundaries use that count).
+/// Everything passes through the time-stretch and the DSP chain on the way in.
+struct RingWriter {
+    prod: HeapProd<f32>,
+    stretch: TimeStretch,
+    dsp: DspChain,
+    /// Reused for the DSP's copy of each block.
+    scratch: Vec<f32>,
+    written: u64,
+    queued: &'static std::sync::atomic::AtomicUsize,
+    /// Don't let more than this many samples pile up ahead of the output.
+    high_water: usize,
+    seek: Arc<SeekFlush>,
+}
+
+impl RingWriter {
+    /// Push all of `samples`, waiting for room. Returns `false` if told to stop meanwhile; the
+    /// rest of `samples` is dropped if a seek comes in.
+    fn push(&mut self, samples: &[f32], pending: &mut Pending, ctrl_rx: &mpsc::Receiver<DecoderControl>) -> bool {
+        if !self.stretch.is_active() {
+            return self.push_stretched(samples, pending, ctrl_rx);
+        }
+        let stretched = self.stretch.process(samples);
+        self.push_stretched(&stretched, pending, ctrl_rx)
+    }
+
+    /// Play out what the time-stretch holds back (end of stream).
+    fn finish(&mut self, pending: &mut Pending, ctrl_rx: &mpsc::Receiver<DecoderControl>) -> bool {
+        let rest = self.stretch.flush();
+        self.push_stretched(&rest, pending, ctrl_rx)
+    }
+
+    fn push_stretched(&mut self, samples: &[f32], pending: &mut Pending, ctrl_rx: &mpsc::Receiver<DecoderControl>) -> bool {
+        if std::mem::take(&mut pending.dsp_changed) && !pending.settings.bit_perfect {
+            self.dsp.set_config(pending.settings.dsp.clone());
+        }
+        if !self.dsp.is_active() {
+            return self.push_raw(samples, pending, ctrl_rx);
+        }
+        let mut processed = std::mem::take(&mut self.scratch);
+        processed.clear();
+        processed.extend_from_slice(samples);
+        self.dsp.process(&mut processed);
+        let ok = self.push_raw(&processed, pending, ctrl_rx);
+        self.scratch = processed;
+        ok
+    }
+
+    fn push_raw(&mut self, mut samples: &[f32], pending: &mut Pending, ctrl_rx: &mpsc::Receiver<DecoderControl>) -> bool {
+        while !samples.is_empty() && pending.seek.is_none() {
+            // backpressure; while a seek is being flushed the stale audio doesn't count
+            if self.queued.load(Ordering::Relaxed) > self.high_water && !self.seek.in_progress() {
+                pending.drain(ctrl_rx);
+                if pending.stop { return false; }
+                std::thread::sleep(Duration::from_millis(5));
+                continue;
+            }
+
+            let n = self.prod.push_slice(samples);
+            if n == 0 {
+                // ring is full — wait briefly and check for control messages
+                pending.drain(ctrl_rx);
+                if pending.stop { return false; }
+                std::thread::sleep(Duration::from_micros(500));
+                continue;
+            }
+            samples = &samples[n..];
+            self.written += n as u64;
+            self.queued.fetch_add(n, Ordering::Relaxed);
+        }
+        true
+    }
+
+    fn push_all(&mut self, samples: &mut VecDeque<f32>, pending: &mut Pending, ctrl_rx: &mpsc::Receiver<DecoderControl>) -> bool {
+        let (a, b) = samples.as_slices();
+        let ok = self.push(a, pending, ctrl_rx) && self.push(b, pending, ctrl_rx);
+        samples.clear();
+        ok
+    }
+}
 
 /// Decode/seek/queue loop running on a dedicated thread.
 /// Pushes **interleaved f32** samples at the **device sample rate** and channel count.
+///
+/// When the current track ends and a `SwitchTo` hint is pending, the next track is chained in
+/// without a gap and its first sample's ring index is published through `boundary`. With
+/// crossfading on, the last `crossfade.seconds` of output are held back in `tail` so they can
+/// be mixed with the next track's head once the current one runs out.
+///
+/// A `Seek` repositions in place: whatever is mid-write is dropped, and the ring index the new
+/// audio starts at is published through `seek` for the output to flush up to.
+///
+/// Each track plays at its `QueuedTrack::speed`, time-stretched at the output rate; the speed
+/// changes on a seek or where the next track starts.
 pub fn decode_audio_loop(
-    mut current_file: String,
-    mut prod: HeapProd<f32>,
+    current: QueuedTrack,
+    prod: HeapProd<f32>,
     out_sample_rate: u32,
     out_channels: u16,
-    mut initial_seek_secs: Option<f64>,
+    initial_seek_secs: Option<f64>,
+    settings: DecoderSettings,
     ctrl_rx: mpsc::Receiver<DecoderControl>,
     evt_tx: mpsc::Sender<EngineEvent>,
     queued_samples: &'static std::sync::atomic::AtomicUsize,
+    boundary: Arc<TrackBoundary>,
+    seek: Arc<SeekFlush>,
 ) -> anyhow::Result<()> {
-    use symphonia::core::audio::SampleBuffer;
-    use symphonia::core::codecs::DecoderOptions;
-    use symphonia::core::errors::Error;
-    use symphonia::core::formats::{FormatOptions, SeekMode, SeekTo};
-    use symphonia::core::io::MediaSourceStream;
-    use symphonia::core::meta::MetadataOptions;
-    use symphonia::core::probe::Hint;
-    use symphonia::core::units::Time;
-    use std::fs::File;
-    use std::path::Path;
-
-    let mut next_file: Option<String> = None;
-
-    'track: loop {
-        // open current_file
-        let file = Box::new(File::open(&current_file)?);
-        let mss = MediaSourceStream::new(file, Default::default());
-        let mut hint = Hint::new();
-        if let Some(ext) = Path::new(&current_file).extension().and_then(|e| e.to_str()) { hint.with_extension(ext); }
-        let probed = symphonia::default::get_probe().format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())?;
-        let mut format = probed.format;
-        let track = format.tracks().iter().find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-            .ok_or_else(|| anyhow::anyhow!("No supported audio tracks"))?;
-        let track_id = track.id;
-        let mut decoder = symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
-        let src_sr = track.codec_params.sample_rate.unwrap_or(44_100) as u32;
-        let src_ch = track.codec_params.channels.map(|c| c.count()).unwrap_or(2) as usize;
-
-        // seek if requested
-        if let Some(seek_seconds) = initial_seek_secs.take() {
-            let secs_whole = seek_seconds.floor() as u64; let frac = seek_seconds - secs_whole as f64;
-            let _ = format.seek(SeekMode::Accurate, SeekTo::Time { time: Time { seconds: secs_whole, frac }, track_id: Some(track_id) });
-        }
+    let ch = out_channels as usize;
+    let resample_quality = settings.resample;
+    let high_water = ms_to_samples(settings.latency.read_ahead_ms(), out_sample_rate, out_channels);
+    let dsp_config = if settings.bit_perfect { DspConfig::default() } else { settings.dsp.clone() };
+    let dsp = DspChain::new(out_sample_rate, ch, dsp_config);
+    let mut pending = Pending { next_file: None, switches: 0, gain: None, settings, dsp_changed: false, matrix_changed: false, seek: None, stop: false };
+    let mut ring = RingWriter {
+        prod,
+        stretch: TimeStretch::new(out_sample_rate, ch, current.speed),
+        dsp,
+        scratch: Vec::new(),
+        written: 0,
+        queued: queued_samples,
+        high_water,
+        seek: Arc::clone(&seek),
+    };
+    // held-back end of the current track, for crossfading
+    let mut tail: VecDeque<f32> = VecDeque::new();
 
-        let plan = ResamplePlan::new(src_sr, src_ch as u16, out_sample_rate, out_channels);
-        let mut sample_buf: Option<SampleBuffer<f32>> = None;
+    let mut current_file = current.path;
+    let mut source = Source::open(&current_file)?;
+    source.gain = current.gain;
+    if let Some(seek_seconds) = initial_seek_secs { source.seek(seek_seconds); }
+    let mut plan = ResamplePlan::new(source.sample_rate, source.layout, out_sample_rate, out_channels, resample_quality, &pending.settings.matrix());
 
+    loop {
         // inner decode loop
         loop {
             // control lane (non‑blocking)
-            while let Ok(msg) = ctrl_rx.try_recv() {
-                match msg {
-                    DecoderControl::Stop => return Ok(()),
-                    DecoderControl::SwitchTo(path) => { next_file = Some(path); },
+            pending.drain(&ctrl_rx);
+            if pending.stop { return Ok(()); }
+            if let Some(g) = pending.gain.take() { source.gain = g; }
+            if std::mem::take(&mut pending.matrix_changed) { plan.set_matrix(&pending.settings.matrix()); }
+            if let Some((track, seconds, generation)) = pending.seek.take() {
+                if track.path != current_file {
+                    // the output crossed into the next track after the engine last looked
+                    source = Source::open(&track.path)?;
+                    current_file = track.path;
                 }
+                source.gain = track.gain;
+                source.seek(seconds);
+                tail.clear();
+                ring.stretch.reset();
+                ring.stretch.set_speed(track.speed);
+                plan = ResamplePlan::new(source.sample_rate, source.layout, out_sample_rate, out_channels, pending.settings.resample, &pending.settings.matrix());
+                boundary.clear();
+                seek.publish(generation, ring.written, (seconds.max(0.0) * out_sample_rate as f64) as u64, track.speed);
             }
 
-            // backpressure
-            if queued_samples.load(std::sync::atomic::Ordering::Relaxed) > (out_sample_rate as usize * out_channels as usize * 2) {
-                std::thread::sleep(Duration::from_millis(5));
-                continue;
+            let block = match source.next_block() {
+                Ok(Some(b)) => b,
+                Ok(None) => break,
+                Err(e) => { error!("Decode error in {current_file}: {e}"); break; }
+            };
+            let mixed = plan.process(block);
+
+            let hold = if pending.settings.bit_perfect { 0 } else { pending.settings.crossfade.samples(out_sample_rate, out_channels) };
+            tail.extend(mixed);
+            let release = tail.len().saturating_sub(hold);
+            if release > 0 {
+                let out: Vec<f32> = tail.drain(..release).collect();
+                if !ring.push(&out, &mut pending, &ctrl_rx) { return Ok(()); }
             }
+        }
 
-            let packet = match format.next_packet() {
-                Ok(p) => p,
-                Err(Error::ResetRequired) => { decoder.reset(); continue; }
-                Err(_) => { // natural end
-                    if let Some(n) = next_file.take() { current_file = n; initial_seek_secs = None; continue 'track; }
-                    let _ = evt_tx.send(EngineEvent::EndOfStream);
-                    return Ok(());
+        // only one boundary in the ring at a time: a short track can end before the output has
+        // even reached its start
+        while boundary.next_at.load(Ordering::Acquire) != TrackBoundary::NONE && pending.seek.is_none() {
+            pending.drain(&ctrl_rx);
+            if pending.stop { return Ok(()); }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        if pending.seek.is_some() { continue; }
+
+        // end of track: chain into the hinted next one, or report end of stream
+        let next = match pending.next_file.take() {
+            None => None,
+            Some(QueuedTrack { path, gain, speed }) => match Source::open(&path) {
+                Ok(mut next) => { next.gain = gain; Some((path, speed, next)) }
+                Err(e) => {
+                    error!("Cannot open next track {path}: {e}");
+                    let _ = evt_tx.send(EngineEvent::NextFailed);
+                    None
                 }
-            };
-            if packet.track_id() != track_id { continue; }
-
-            match decoder.decode(&packet) {
-                Ok(decoded) => {
-                    if sample_buf.is_none() { let spec = *decoded.spec(); sample_buf = Some(SampleBuffer::<f32>::new(decoded.capacity() as u64, spec)); }
-                    if let Some(buf) = sample_buf.as_mut() {
-                        buf.copy_interleaved_ref(decoded);
-                        let mixed = plan.resample_and_mix(buf.samples(), src_sr, src_ch, out_sample_rate, out_channels);
-                        if !mixed.is_empty() {
-                            let mut off = 0;
-                            while off < mixed.len() {
-                                // try to push remaining samples
-                                let n = prod.push_slice(&mixed[off..]);
-
-                                if n == 0 {
-                                    // ring is full — wait briefly and check for control messages
-                                    match ctrl_rx.try_recv() {
-                                        Ok(DecoderControl::Stop) => return Ok(()),
-                                        Ok(DecoderControl::SwitchTo(path)) => {
-                                            // optionally hand off to next track here; for now just stop
-                                            // or set `next_file = Some(path); break;` if you support gapless
-                                            next_file = Some(path);
-                                        }
-                                        Err(std::sync::mpsc::TryRecvError::Empty) => {}
-                                        Err(std::sync::mpsc::TryRecvError::Disconnected) => return Ok(()),
-                                    }
-                                    std::thread::sleep(std::time::Duration::from_micros(500));
-                                    continue;
-                                }
-
-                                off += n;
-                                queued_samples.fetch_add(n, std::sync::atomic::Ordering::Relaxed);
-                            }
-                        }
-                    }
+            },
+        };
+        // a different format needs a new bit-perfect stream: end here, the engine reopens it
+        let next = next.filter(|(_, _, n)| {
+            !pending.settings.bit_perfect
+                || (n.sample_rate, n.channels, n.depth) == (source.sample_rate, source.channels, source.depth)
+        });
+        let Some((path, speed, mut next)) = next else {
+            tail.extend(plan.flush());
+            if !ring.push_all(&mut tail, &mut pending, &ctrl_rx) { return Ok(()); }
+            if pending.seek.is_some() { continue; }
+            if !ring.finish(&mut pending, &ctrl_rx) { return Ok(()); }
+            if pending.seek.is_some() { continue; }
+            let _ = evt_tx.send(EngineEvent::EndOfStream);
+            return Ok(());
+        };
+
+        let xf = pending.settings.crossfade;
+        let quality = pending.settings.resample;
+        let same_album = source.album.is_some() && source.album == next.album;
+        let fade = !pending.settings.bit_perfect
+            && xf.samples(out_sample_rate, out_channels) > 0
+            && !(xf.skip_same_album && same_album);
+
+        if !fade && plan.accepts(next.sample_rate, next.layout, quality) {
+            // gapless through the same filter: the next track starts after what it still holds
+            if !ring.push_all(&mut tail, &mut pending, &ctrl_rx) { return Ok(()); }
+            // (and the stretch, at this track's speed; what the filter holds goes in at the next's)
+            ring.stretch.set_speed_after(speed);
+            let held = ring.stretch.pending_frames() + (plan.pending_frames() as f64 / speed as f64).round() as usize;
+            publish_switch(&boundary, ring.written + (held * ch) as u64, speed, &mut pending, &evt_tx);
+        } else {
+            tail.extend(plan.flush());
+            plan = ResamplePlan::new(next.sample_rate, next.layout, out_sample_rate, out_channels, quality, &pending.settings.matrix());
+
+            if !fade || tail.is_empty() {
+                // gapless: play out what's held back, the next track starts right after it
+                if !ring.push_all(&mut tail, &mut pending, &ctrl_rx) { return Ok(()); }
+                publish_switch(&boundary, ring.written + (ring.stretch.pending_frames() * ch) as u64, speed, &mut pending, &evt_tx);
+                ring.stretch.set_speed_after(speed);
+            } else {
+                // crossfade: the next track starts where the overlap starts
+                publish_switch(&boundary, ring.written + (ring.stretch.pending_frames() * ch) as u64, speed, &mut pending, &evt_tx);
+                ring.stretch.set_speed_after(speed);
+                let mut fading = std::mem::take(&mut tail);
+                let total = fading.len() / ch;
+                let mut done = 0;
+                while !fading.is_empty() {
+                    pending.drain(&ctrl_rx);
+                    if pending.stop { return Ok(()); }
+
+                    let head = match next.next_block() {
+                        Ok(Some(b)) => plan.process(b),
+                        // next track shorter than the overlap: fade the rest out against silence
+                        Ok(None) | Err(_) => vec![0.0; fading.len()],
+                    };
+                    let mut out = Vec::with_capacity(head.len());
+                    let used = mix_overlap(xf.curve, &mut fading, &head, ch, done, total, &mut out);
+                    done += used;
+                    if !ring.push(&out, &mut pending, &ctrl_rx) { return Ok(()); }
+                    if pending.seek.is_some() { break; }
+                    // the rest of the head is regular output of the next track
+                    tail.extend(&head[used * ch..]);
                 }
-                Err(Error::DecodeError(_)) => continue,
-                Err(Error::ResetRequired) => decoder.reset(),
-                Err(_) => { let _ = evt_tx.send(EngineEvent::EndOfStream); return Ok(()); }
             }
         }
+
+        source = next;
+        current_file = path;
     }
 }
 
-// Lightweight linear resampler + channel mixer plan.
-#[derive(Clone, Copy)]
-pub struct ResamplePlan { src_ch: u16, dst_ch: u16 }
+/// The next track's audio starts at ring index `at`, played at `speed`. The engine hears of
+/// the switch first, so it can hint the track after and knows of it before the output crosses.
+fn publish_switch(boundary: &TrackBoundary, at: u64, speed: f32, pending: &mut Pending, evt_tx: &mpsc::Sender<EngineEvent>) {
+    pending.switches += 1;
+    let _ = evt_tx.send(EngineEvent::Switched);
+    boundary.next_speed.store(speed.to_bits(), Ordering::Relaxed);
+    boundary.next_at.store(at, Ordering::Release);
+}
+
+/// Channel mixer + band-limited resampler from one source format to the output format.
+/// It carries filter state, so keep using it for the next track when the format matches.
+pub struct ResamplePlan {
+    src_sr: u32,
+    src_layout: Channels,
+    dst_ch: usize,
+    quality: ResampleQuality,
+    mixer: ChannelMixer,
+    // `None` when the rates already match
+    resampler: Option<Resampler>,
+}
+
 impl ResamplePlan {
-    pub fn new(_src_sr: u32, src_ch: u16, _dst_sr: u32, dst_ch: u16) -> Self { Self { src_ch, dst_ch } }
-    pub fn resample_and_mix(&self, input: &[f32], src_sr: u32, src_ch: usize, dst_sr: u32, dst_ch: u16) -> Vec<f32> {
-        let frames = input.len() / src_ch; if frames == 0 { return Vec::new(); }
-        let mut interm = if dst_ch as usize == src_ch { input.to_vec() } else { mix_channels(input, src_ch, dst_ch as usize) };
-        if src_sr == dst_sr { return interm; }
-        linear_resample_interleaved(&interm, src_sr, dst_sr, dst_ch as usize)
+    pub fn new(src_sr: u32, src_layout: Channels, dst_sr: u32, dst_ch: u16, quality: ResampleQuality, matrix: &ChannelMatrix) -> Self {
+        let resampler = (src_sr != dst_sr).then(|| Resampler::new(src_sr, dst_sr, dst_ch as usize, quality));
+        let mixer = ChannelMixer::new(src_layout, dst_ch as usize, matrix);
+        Self { src_sr, src_layout, dst_ch: dst_ch as usize, quality, mixer, resampler }
     }
-}
 
-fn mix_channels(input: &[f32], src_ch: usize, dst_ch: usize) -> Vec<f32> {
-    let frames = input.len() / src_ch; let mut out = vec![0.0f32; frames * dst_ch];
-    match (src_ch, dst_ch) {
-        (1, 2) => { for i in 0..frames { let v = input[i]; out[i*2] = v; out[i*2+1] = v; } }
-        (2, 1) => { for i in 0..frames { out[i] = 0.5 * (input[i*2] + input[i*2+1]); } }
-        (s, d) if s == d => { out.copy_from_slice(input); }
-        _ => {
-            if dst_ch < src_ch {
-                let scale = 1.0 / src_ch as f32; for i in 0..frames { let mut acc = 0.0; for c in 0..src_ch { acc += input[i*src_ch + c]; } let v = acc * scale; for c in 0..dst_ch { out[i*dst_ch + c] = v; } }
-            } else {
-                for i in 0..frames { for c in 0..src_ch { out[i*dst_ch + c] = input[i*src_ch + c]; } let last = input[i*src_ch + (src_ch-1)]; for c in src_ch..dst_ch { out[i*dst_ch + c] = last; } }
-            }
+    /// Whether a track in this format can continue through this plan without a seam.
+    pub fn accepts(&self, src_sr: u32, src_layout: Channels, quality: ResampleQuality) -> bool {
+        self.src_sr == src_sr && self.src_layout == src_layout && self.quality == quality
+    }
+
+    /// The mixer has no state, so a new matrix applies from the next block.
+    pub fn set_matrix(&mut self, matrix: &ChannelMatrix) {
+        self.mixer = ChannelMixer::new(self.src_layout, self.dst_ch, matrix);
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if input.len() < self.src_layout.count() { return Vec::new(); }
+        let interm = self.mixer.process(input);
+        match self.resampler.as_mut() {
+            Some(r) => r.process(&interm),
+            None => interm,
         }
-    } out
-}
+    }
 
-fn linear_resample_interleaved(input: &[f32], src_sr: u32, dst_sr: u32, ch: usize) -> Vec<f32> {
-    let src_frames = input.len() / ch; if src_frames == 0 { return Vec::new(); }
-    let ratio = dst_sr as f64 / src_sr as f64; let dst_frames = (src_frames as f64 * ratio).round() as usize;
-    let mut out = vec![0.0f32; dst_frames * ch];
-    for c in 0..ch { let mut t = 0.0f64; for i in 0..dst_frames { let src_pos = t; let i0 = src_pos.floor() as usize; let i1 = (i0 + 1).min(src_frames - 1); let frac = (src_pos - i0 as f64) as f32; let a = input[i0*ch + c]; let b = input[i1*ch + c]; out[i*ch + c] = a + (b - a) * frac; t += 1.0/ratio; } }
-    out
-}
\ No newline at end of file
+    /// Output frames still held back by the filter.
+    pub fn pending_frames(&self) -> usize {
+        self.resampler.as_ref().map_or(0, Resampler::pending_frames)
+    }
+
+    /// Output what the filter still holds (end of stream / format change).
+    pub fn flush(&mut self) -> Vec<f32> {
+        self.resampler.as_mut().map_or_else(Vec::new, Resampler::flush)
+    }
+}
@@ -0,0 +1,122 @@
+//! Output device enumeration and lookup by name.
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde::Serialize;
+
+/// Why the engine moved to another output device (sent with `audio:device`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceChangeReason {
+    /// The user picked a device.
+    Selected,
+    /// Following the system default, which changed.
+    DefaultChanged,
+    /// The device in use disappeared.
+    Removed,
+    /// The chosen device is back after a fallback to the default.
+    Restored,
+    /// The stream reported an error and was rebuilt.
+    StreamError,
+}
+
+/// One entry of a device's supported output configurations.
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputConfigRange {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    /// cpal's name for it: `f32`, `i16`, `u16`, `i32`, ...
+    pub sample_format: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub configs: Vec<OutputConfigRange>,
+}
+
+pub fn list_output_devices() -> anyhow::Result<Vec<OutputDeviceInfo>> {
+    let host = cpal::default_host();
+    let default_name = host.default_output_device().and_then(|d| d.name().ok());
+    let mut out = Vec::new();
+    for device in host.output_devices()? {
+        let Ok(name) = device.name() else { continue };
+        let configs = match device.supported_output_configs() {
+            Ok(configs) => configs
+                .map(|c| OutputConfigRange {
+                    channels: c.channels(),
+                    min_sample_rate: c.min_sample_rate().0,
+                    max_sample_rate: c.max_sample_rate().0,
+                    sample_format: c.sample_format().to_string(),
+                })
+                .collect(),
+            // still list it; it may just be busy
+            Err(e) => { log::warn!("output configs of {name}: {e}"); Vec::new() }
+        };
+        out.push(OutputDeviceInfo { is_default: default_name.as_deref() == Some(name.as_str()), name, configs });
+    }
+    Ok(out)
+}
+
+/// The device called `name`, or the system default when `name` is `None` or no longer present.
+pub fn open_output_device(name: Option<&str>) -> anyhow::Result<cpal::Device> {
+    let host = cpal::default_host();
+    if let Some(name) = name {
+        let found = host.output_devices()?.find(|d| d.name().is_ok_and(|n| n == name));
+        if let Some(device) = found { return Ok(device); }
+        log::warn!("output device {name} not found, using the default");
+    }
+    host.default_output_device().ok_or_else(|| anyhow::anyhow!("No output device"))
+}
+
+/// What a change in the device list means for the stream on `active`: the device disappeared,
+/// the `preferred` one is back, or (with no preference) the system `default` moved. `None`
+/// when the stream can stay where it is.
+pub fn device_change(
+    active: Option<&str>,
+    preferred: Option<&str>,
+    default: Option<&str>,
+    devices: &[String],
+) -> Option<DeviceChangeReason> {
+    let present = |name: &str| devices.iter().any(|d| d == name);
+    match (active, preferred) {
+        (Some(a), _) if !present(a) => Some(DeviceChangeReason::Removed),
+        (a, Some(p)) if a != Some(p) && present(p) => Some(DeviceChangeReason::Restored),
+        (a, None) if default.is_some() && a != default => Some(DeviceChangeReason::DefaultChanged),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> { list.iter().map(|s| s.to_string()).collect() }
+
+    #[test]
+    fn active_device_missing_is_removed() {
+        let devices = names(&["default", "HDA Intel PCH"]);
+        let change = |preferred| device_change(Some("USB Audio DAC"), preferred, Some("default"), &devices);
+        assert_eq!(change(Some("USB Audio DAC")), Some(DeviceChangeReason::Removed));
+        assert_eq!(change(None), Some(DeviceChangeReason::Removed));
+        assert_eq!(device_change(Some("default"), None, Some("default"), &devices), None);
+    }
+
+    #[test]
+    fn preferred_and_default_devices() {
+        let devices = names(&["default", "USB Audio DAC"]);
+        // fell back to the default, and the chosen one is back
+        assert_eq!(
+            device_change(Some("default"), Some("USB Audio DAC"), Some("default"), &devices),
+            Some(DeviceChangeReason::Restored)
+        );
+        assert_eq!(device_change(Some("USB Audio DAC"), Some("USB Audio DAC"), Some("default"), &devices), None);
+        // following the default
+        assert_eq!(
+            device_change(Some("default"), None, Some("USB Audio DAC"), &devices),
+            Some(DeviceChangeReason::DefaultChanged)
+        );
+        assert_eq!(device_change(None, None, None, &devices), None);
+    }
+}
@@ -0,0 +1,559 @@
+//! Output DSP chain, run by the decoder thread on the final output format just before samples
+//! enter the ring: preamp → 10-band graphic EQ → parametric EQ → limiter.
+//!
+//! Parameters arrive as a whole [`DspConfig`] (`DecoderControl::SetDsp`); the chain recomputes
+//! its coefficients but keeps filter state, so adjusting a band while playing doesn't click.
+
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Centre frequencies of the graphic EQ bands (ISO octave bands).
+pub const GRAPHIC_BANDS_HZ: [f64; 10] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+/// One-octave bandwidth.
+const GRAPHIC_Q: f64 = std::f64::consts::SQRT_2;
+
+pub const MAX_GAIN_DB: f32 = 24.0;
+pub const MAX_PARAMETRIC_BANDS: usize = 16;
+
+/// Transposed direct form II biquad with f64 state.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Biquad { b0: f64, b1: f64, b2: f64, a1: f64, a2: f64, z1: f64, z2: f64 }
+
+impl Biquad {
+    pub(crate) fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        let mut f = Self { b0: 0.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0, z1: 0.0, z2: 0.0 };
+        f.set_coefficients(b, a);
+        f
+    }
+
+    fn set_coefficients(&mut self, b: [f64; 3], a: [f64; 3]) {
+        self.b0 = b[0] / a[0];
+        self.b1 = b[1] / a[0];
+        self.b2 = b[2] / a[0];
+        self.a1 = a[1] / a[0];
+        self.a2 = a[2] / a[0];
+    }
+
+    /// Passes the signal through unchanged, keeping a band's slot in the chain while it is flat.
+    fn unity() -> ([f64; 3], [f64; 3]) { ([1.0, 0.0, 0.0], [1.0, 0.0, 0.0]) }
+
+    fn is_unity(&self) -> bool {
+        self.b0 == 1.0 && self.b1 == self.a1 && self.b2 == self.a2
+    }
+
+    fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
+
+    #[inline]
+    pub(crate) fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.z1;
+        self.z1 = self.b1 * x - self.a1 * y + self.z2;
+        self.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+}
+
+impl FilterKind {
+    /// RBJ "Audio EQ Cookbook" coefficients `(b, a)`.
+    fn coefficients(self, sample_rate: u32, freq: f64, gain_db: f64, q: f64) -> ([f64; 3], [f64; 3]) {
+        let w0 = 2.0 * PI * (freq / sample_rate as f64).min(0.499);
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+        let a = 10f64.powf(gain_db / 40.0);
+        match self {
+            Self::Peaking => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            Self::LowShelf => {
+                let s = 2.0 * a.sqrt() * alpha;
+                (
+                    [a * ((a + 1.0) - (a - 1.0) * cos + s), 2.0 * a * ((a - 1.0) - (a + 1.0) * cos), a * ((a + 1.0) - (a - 1.0) * cos - s)],
+                    [(a + 1.0) + (a - 1.0) * cos + s, -2.0 * ((a - 1.0) + (a + 1.0) * cos), (a + 1.0) + (a - 1.0) * cos - s],
+                )
+            }
+            Self::HighShelf => {
+                let s = 2.0 * a.sqrt() * alpha;
+                (
+                    [a * ((a + 1.0) + (a - 1.0) * cos + s), -2.0 * a * ((a - 1.0) + (a + 1.0) * cos), a * ((a + 1.0) + (a - 1.0) * cos - s)],
+                    [(a + 1.0) - (a - 1.0) * cos + s, 2.0 * ((a - 1.0) - (a + 1.0) * cos), (a + 1.0) - (a - 1.0) * cos - s],
+                )
+            }
+            Self::LowPass => (
+                [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+            Self::HighPass => (
+                [(1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0],
+                [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EqBand {
+    pub kind: FilterKind,
+    pub freq_hz: f32,
+    /// Ignored by the pass filters.
+    pub gain_db: f32,
+    pub q: f32,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool { true }
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicEq {
+    pub enabled: bool,
+    /// Gain per band of [`GRAPHIC_BANDS_HZ`].
+    pub gains_db: [f32; 10],
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParametricEq {
+    pub enabled: bool,
+    pub bands: Vec<EqBand>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimiterConfig {
+    pub enabled: bool,
+    /// Output peaks are held at or below this level.
+    pub ceiling_db: f32,
+    pub release_ms: f32,
+}
+
+impl Default for LimiterConfig {
+    fn default() -> Self { Self { enabled: false, ceiling_db: -1.0, release_ms: 100.0 } }
+}
+
+/// The whole chain's parameters. Stored as JSON (global, per output device, and as presets).
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DspConfig {
+    pub enabled: bool,
+    pub preamp_db: f32,
+    pub graphic_eq: GraphicEq,
+    pub parametric_eq: ParametricEq,
+    pub limiter: LimiterConfig,
+}
+
+impl DspConfig {
+    pub fn from_json(s: &str) -> Option<Self> {
+        serde_json::from_str(s).ok()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let gain_ok = |g: f32| g.is_finite() && g.abs() <= MAX_GAIN_DB;
+        if !gain_ok(self.preamp_db) {
+            return Err(format!("Preamp must be within ±{MAX_GAIN_DB} dB"));
+        }
+        if !self.graphic_eq.gains_db.iter().all(|g| gain_ok(*g)) {
+            return Err(format!("Graphic EQ gains must be within ±{MAX_GAIN_DB} dB"));
+        }
+        if self.parametric_eq.bands.len() > MAX_PARAMETRIC_BANDS {
+            return Err(format!("At most {MAX_PARAMETRIC_BANDS} parametric bands"));
+        }
+        for b in &self.parametric_eq.bands {
+            if !(10.0..=24_000.0).contains(&b.freq_hz) { return Err("Band frequency must be 10 Hz – 24 kHz".into()); }
+            if !(0.1..=20.0).contains(&b.q) { return Err("Band Q must be 0.1 – 20".into()); }
+            if !gain_ok(b.gain_db) { return Err(format!("Band gain must be within ±{MAX_GAIN_DB} dB")); }
+        }
+        let l = &self.limiter;
+        if !(-24.0..=0.0).contains(&l.ceiling_db) { return Err("Limiter ceiling must be -24 – 0 dB".into()); }
+        if !(1.0..=2000.0).contains(&l.release_ms) { return Err("Limiter release must be 1 – 2000 ms".into()); }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DspPreset {
+    pub name: String,
+    /// Shipped with the app; can't be overwritten or deleted.
+    pub builtin: bool,
+    pub config: DspConfig,
+}
+
+/// Presets shipped with the app.
+pub fn builtin_presets() -> Vec<DspPreset> {
+    let graphic = |name: &str, preamp_db: f32, gains_db: [f32; 10]| DspPreset {
+        name: name.into(),
+        builtin: true,
+        config: DspConfig {
+            enabled: true,
+            preamp_db,
+            graphic_eq: GraphicEq { enabled: true, gains_db },
+            limiter: LimiterConfig { enabled: true, ..Default::default() },
+            ..Default::default()
+        },
+    };
+    let parametric = |name: &str, preamp_db: f32, bands: Vec<EqBand>| DspPreset {
+        name: name.into(),
+        builtin: true,
+        config: DspConfig {
+            enabled: true,
+            preamp_db,
+            parametric_eq: ParametricEq { enabled: true, bands },
+            limiter: LimiterConfig { enabled: true, ..Default::default() },
+            ..Default::default()
+        },
+    };
+    let band = |kind, freq_hz, gain_db, q| EqBand { kind, freq_hz, gain_db, q, enabled: true };
+
+    vec![
+        graphic("Flat", 0.0, [0.0; 10]),
+        graphic("Bass Boost", -6.0, [6.0, 5.0, 4.0, 2.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0]),
+        graphic("Treble Boost", -5.0, [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 2.0, 3.5, 4.5, 5.0]),
+        graphic("Vocal", -3.0, [-2.0, -2.0, -1.0, 0.5, 2.0, 3.0, 3.0, 2.0, 0.5, 0.0]),
+        graphic("Loudness", -5.0, [5.0, 4.0, 2.0, 0.0, -1.0, -1.0, 0.0, 1.5, 3.0, 4.0]),
+        graphic("Classical", -3.0, [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.5, -2.0, -2.0, -3.0]),
+        graphic("Rock", -4.0, [4.0, 3.0, 1.5, 0.0, -1.0, -0.5, 1.0, 2.5, 3.5, 4.0]),
+        parametric("Rumble Filter", 0.0, vec![band(FilterKind::HighPass, 30.0, 0.0, 0.707)]),
+        parametric("Warm", -3.0, vec![
+            band(FilterKind::LowShelf, 120.0, 3.0, 0.707),
+            band(FilterKind::HighShelf, 8000.0, -2.0, 0.707),
+        ]),
+        parametric("De-harsh", 0.0, vec![
+            band(FilterKind::Peaking, 3000.0, -2.5, 1.4),
+            band(FilterKind::Peaking, 7000.0, -3.0, 2.0),
+        ]),
+    ]
+}
+
+/// Width of the limiter's soft knee, centred on the ceiling.
+const LIMITER_KNEE_DB: f32 = 6.0;
+const LIMITER_ATTACK_MS: f32 = 1.0;
+
+/// Stereo-linked peak limiter. Gain reduction starts softly half a knee below the ceiling,
+/// moves in over a short attack and recovers along an exponential release, so it neither
+/// distorts the waveform nor pumps audibly. A transient faster than the attack is clamped at
+/// the ceiling, so the output never exceeds it.
+struct Limiter {
+    ceiling: f32,
+    ceiling_db: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    gain: f32,
+}
+
+impl Limiter {
+    fn new(cfg: &LimiterConfig, sample_rate: u32) -> Self {
+        let mut l = Self { ceiling: 1.0, ceiling_db: 0.0, attack_coef: 0.0, release_coef: 0.0, gain: 1.0 };
+        l.configure(cfg, sample_rate);
+        l
+    }
+
+    fn configure(&mut self, cfg: &LimiterConfig, sample_rate: u32) {
+        let coef = |ms: f32| (-1.0 / (ms.max(0.01) * 0.001 * sample_rate as f32)).exp();
+        self.ceiling_db = cfg.ceiling_db;
+        self.ceiling = 10f32.powf(cfg.ceiling_db / 20.0);
+        self.attack_coef = coef(LIMITER_ATTACK_MS);
+        self.release_coef = coef(cfg.release_ms.max(1.0));
+    }
+
+    /// Static curve: gain for a peak of `peak_db`, with an infinite ratio above the knee.
+    fn target_gain(&self, peak_db: f32) -> f32 {
+        let over = peak_db - self.ceiling_db;
+        let half = LIMITER_KNEE_DB / 2.0;
+        let reduction_db = if over <= -half {
+            0.0
+        } else if over < half {
+            (over + half).powi(2) / (2.0 * LIMITER_KNEE_DB)
+        } else {
+            over
+        };
+        10f32.powf(-reduction_db / 20.0)
+    }
+
+    fn process(&mut self, buf: &mut [f32], channels: usize) {
+        for frame in buf.chunks_exact_mut(channels) {
+            let peak = frame.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+            let target = if peak > 0.0 { self.target_gain(20.0 * peak.log10()) } else { 1.0 };
+            let coef = if target < self.gain { self.attack_coef } else { self.release_coef };
+            self.gain = target + (self.gain - target) * coef;
+            if self.gain < 1.0 || peak > self.ceiling {
+                let (gain, ceiling) = (self.gain, self.ceiling);
+                frame.iter_mut().for_each(|s| *s = (*s * gain).clamp(-ceiling, ceiling));
+            }
+        }
+    }
+}
+
+/// Runs a [`DspConfig`] on interleaved f32 at a fixed rate and channel count.
+pub struct DspChain {
+    sample_rate: u32,
+    channels: usize,
+    config: DspConfig,
+    preamp: f32,
+    /// Filters in processing order, one instance per channel each: a slot per graphic band,
+    /// then one per parametric band. Flat or disabled bands keep their slot with a unity design.
+    filters: Vec<Vec<Biquad>>,
+    /// Whether any filter changes the signal.
+    shaping: bool,
+    limiter: Limiter,
+}
+
+impl DspChain {
+    pub fn new(sample_rate: u32, channels: usize, config: DspConfig) -> Self {
+        let limiter = Limiter::new(&config.limiter, sample_rate);
+        let mut chain = Self {
+            sample_rate,
+            channels: channels.max(1),
+            config: DspConfig::default(),
+            preamp: 1.0,
+            filters: Vec::new(),
+            shaping: false,
+            limiter,
+        };
+        chain.set_config(config);
+        chain
+    }
+
+    /// Swap in new parameters. Each band keeps its filter (and state) as long as it exists.
+    pub fn set_config(&mut self, config: DspConfig) {
+        let sr = self.sample_rate;
+        let mut designs = Vec::new();
+        for (freq, gain) in GRAPHIC_BANDS_HZ.iter().zip(config.graphic_eq.gains_db) {
+            designs.push(if config.graphic_eq.enabled && gain != 0.0 && *freq < sr as f64 / 2.0 {
+                FilterKind::Peaking.coefficients(sr, *freq, gain as f64, GRAPHIC_Q)
+            } else {
+                Biquad::unity()
+            });
+        }
+        if config.parametric_eq.enabled {
+            for b in &config.parametric_eq.bands {
+                designs.push(if b.enabled {
+                    b.kind.coefficients(sr, b.freq_hz as f64, b.gain_db as f64, b.q as f64)
+                } else {
+                    Biquad::unity()
+                });
+            }
+        }
+
+        self.filters.truncate(designs.len());
+        for (i, (b, a)) in designs.into_iter().enumerate() {
+            match self.filters.get_mut(i) {
+                Some(per_channel) => per_channel.iter_mut().for_each(|f| f.set_coefficients(b, a)),
+                None => self.filters.push(vec![Biquad::new(b, a); self.channels]),
+            }
+        }
+        self.shaping = self.filters.iter().any(|f| !f[0].is_unity());
+        if !self.shaping {
+            // not run while flat; don't let old state ring out when a band comes back
+            self.filters.iter_mut().flatten().for_each(Biquad::reset);
+        }
+        self.preamp = 10f32.powf(config.preamp_db / 20.0);
+        self.limiter.configure(&config.limiter, sr);
+        self.config = config;
+    }
+
+    /// Whether [`process`](Self::process) would change anything.
+    pub fn is_active(&self) -> bool {
+        self.config.enabled && (self.preamp != 1.0 || self.shaping || self.config.limiter.enabled)
+    }
+
+    pub fn process(&mut self, buf: &mut [f32]) {
+        if !self.is_active() { return; }
+        let ch = self.channels;
+        if self.shaping {
+            for frame in buf.chunks_exact_mut(ch) {
+                for (c, s) in frame.iter_mut().enumerate() {
+                    let mut x = (*s * self.preamp) as f64;
+                    for f in &mut self.filters {
+                        x = f[c].process(x);
+                    }
+                    *s = x as f32;
+                }
+            }
+        } else if self.preamp != 1.0 {
+            buf.iter_mut().for_each(|s| *s *= self.preamp);
+        }
+        if self.config.limiter.enabled {
+            self.limiter.process(buf, ch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 48_000;
+
+    /// Magnitude response in dB of a design at `freq`.
+    fn response_db((b, a): ([f64; 3], [f64; 3]), freq: f64) -> f64 {
+        let w = 2.0 * PI * freq / SR as f64;
+        let eval = |c: [f64; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+            (re * re + im * im).sqrt()
+        };
+        20.0 * (eval(b) / eval(a)).log10()
+    }
+
+    fn sine(freq: f64, amp: f32, frames: usize, channels: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|n| std::iter::repeat((amp as f64 * (2.0 * PI * freq * n as f64 / SR as f64).sin()) as f32).take(channels))
+            .collect()
+    }
+
+    fn peak(buf: &[f32]) -> f32 {
+        buf.iter().fold(0.0f32, |m, s| m.max(s.abs()))
+    }
+
+    fn graphic(gains_db: [f32; 10]) -> DspConfig {
+        DspConfig { enabled: true, graphic_eq: GraphicEq { enabled: true, gains_db }, ..Default::default() }
+    }
+
+    #[test]
+    fn peaking_and_shelf_responses() {
+        let near = |got: f64, want: f64| assert!((got - want).abs() < 0.05, "{got} vs {want}");
+        let peak = FilterKind::Peaking.coefficients(SR, 1000.0, 6.0, 1.0);
+        near(response_db(peak, 1000.0), 6.0);
+        near(response_db(peak, 20.0), 0.0);
+        near(response_db(peak, 20_000.0), 0.0);
+
+        let low = FilterKind::LowShelf.coefficients(SR, 200.0, -4.0, 0.707);
+        near(response_db(low, 1.0), -4.0);
+        near(response_db(low, 200.0), -2.0);
+        near(response_db(low, 20_000.0), 0.0);
+
+        let high = FilterKind::HighShelf.coefficients(SR, 5000.0, 3.0, 0.707);
+        near(response_db(high, 20.0), 0.0);
+        near(response_db(high, 23_999.0), 3.0);
+    }
+
+    #[test]
+    fn pass_filters() {
+        let lp = FilterKind::LowPass.coefficients(SR, 1000.0, 0.0, std::f64::consts::FRAC_1_SQRT_2);
+        assert!(response_db(lp, 1.0).abs() < 0.01);
+        assert!((response_db(lp, 1000.0) + 3.01).abs() < 0.05);
+        assert!(response_db(lp, 10_000.0) < -38.0);
+
+        let hp = FilterKind::HighPass.coefficients(SR, 30.0, 0.0, std::f64::consts::FRAC_1_SQRT_2);
+        assert!((response_db(hp, 30.0) + 3.01).abs() < 0.05);
+        assert!(response_db(hp, 1000.0).abs() < 0.01);
+        assert!(response_db(hp, 3.0) < -38.0);
+    }
+
+    #[test]
+    fn filtered_sine_has_the_designed_gain() {
+        let mut chain = DspChain::new(SR, 2, graphic([0.0, 0.0, 0.0, 0.0, 0.0, 6.0, 0.0, 0.0, 0.0, 0.0]));
+        let mut buf = sine(1000.0, 0.25, SR as usize, 2);
+        chain.process(&mut buf);
+        let settled = peak(&buf[buf.len() / 2..]);
+        // neighbouring octaves overlap a little: 1 kHz sees slightly more than the band's own 6 dB
+        let db = 20.0 * (settled / 0.25).log10();
+        assert!((6.0..6.3).contains(&db), "{db}");
+    }
+
+    #[test]
+    fn flat_bands_keep_their_slot() {
+        let mut chain = DspChain::new(SR, 2, graphic([3.0; 10]));
+        assert_eq!(chain.filters.len(), 10);
+        chain.set_config(graphic([3.0, 0.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0]));
+        assert_eq!(chain.filters.len(), 10);
+        assert!(chain.filters[1][0].is_unity());
+        assert!(!chain.filters[2][0].is_unity());
+
+        // all flat: nothing to run
+        chain.set_config(graphic([0.0; 10]));
+        assert_eq!(chain.filters.len(), 10);
+        assert!(!chain.is_active());
+        let mut buf = sine(440.0, 0.5, 256, 2);
+        let before = buf.clone();
+        chain.process(&mut buf);
+        assert_eq!(buf, before);
+    }
+
+    #[test]
+    fn state_carries_over_set_config() {
+        let config = graphic([4.0, 0.0, -3.0, 0.0, 2.0, 0.0, 0.0, 5.0, 0.0, -2.0]);
+        let input = sine(300.0, 0.3, 4096, 2);
+        let mut reference = DspChain::new(SR, 2, config.clone());
+        let mut expected = input.clone();
+        reference.process(&mut expected);
+
+        // reapplying the same parameters midway changes nothing
+        let mut chain = DspChain::new(SR, 2, config.clone());
+        let (a, b) = input.split_at(2048);
+        let (mut a, mut b) = (a.to_vec(), b.to_vec());
+        chain.process(&mut a);
+        chain.set_config(config.clone());
+        chain.process(&mut b);
+        a.extend(b);
+        assert_eq!(a, expected);
+
+        // flattening one band leaves the others' state where it was
+        let mut chain = DspChain::new(SR, 2, config.clone());
+        let mut a = input[..2048].to_vec();
+        chain.process(&mut a);
+        let state = |c: &DspChain, i: usize| (c.filters[i][0].z1, c.filters[i][0].z2);
+        let kept = state(&chain, 7);
+        let mut flatter = config;
+        flatter.graphic_eq.gains_db[2] = 0.0;
+        chain.set_config(flatter);
+        assert_eq!(state(&chain, 7), kept);
+    }
+
+    #[test]
+    fn limiter_holds_the_ceiling_without_hard_steps() {
+        let cfg = LimiterConfig { enabled: true, ceiling_db: -1.0, release_ms: 100.0 };
+        let ceiling = 10f32.powf(-1.0 / 20.0);
+        let mut limiter = Limiter::new(&cfg, SR);
+
+        // 6 dB too hot
+        let mut buf = sine(1000.0, 2.0 * ceiling, SR as usize / 2, 2);
+        limiter.process(&mut buf, 2);
+        assert!(peak(&buf) <= ceiling);
+        assert!(peak(&buf[buf.len() / 2..]) > ceiling * 0.9, "settles near the ceiling");
+
+        // gain moves smoothly: no frame-to-frame jump of more than the attack allows
+        let mut limiter = Limiter::new(&cfg, SR);
+        let mut last = limiter.gain;
+        let mut loud = vec![0.9f32; 2000];
+        for frame in loud.chunks_mut(2) {
+            limiter.process(frame, 2);
+            assert!(last - limiter.gain < 0.03, "{last} -> {}", limiter.gain);
+            last = limiter.gain;
+        }
+    }
+
+    #[test]
+    fn limiter_knee() {
+        let limiter = Limiter::new(&LimiterConfig { enabled: true, ceiling_db: -1.0, release_ms: 100.0 }, SR);
+        let db = |peak_db: f32| 20.0 * limiter.target_gain(peak_db).log10();
+        assert_eq!(db(-10.0), 0.0, "below the knee");
+        let at = db(-1.0);
+        assert!(at < 0.0 && at > -1.0, "soft at the ceiling: {at}");
+        assert!((db(5.0) + 6.0).abs() < 1e-4, "brick wall above the knee");
+
+        // quiet material passes untouched
+        let mut limiter = limiter;
+        let mut buf = sine(1000.0, 0.5, 4800, 2);
+        let before = buf.clone();
+        limiter.process(&mut buf, 2);
+        assert_eq!(buf, before);
+    }
+}
@@ -1,20 +1,36 @@
 use crate::audio::{PlaybackState, f32_to_bits_atomic};
-use crate::audio::buffer::make_audio_ring;
-use crate::audio::decoder::decode_audio_loop;
-use crate::audio::output::{build_output_stream, BuiltOutput};
-use cpal::traits::StreamTrait;
-use tauri::Emitter;
+use crate::audio::buffer::{make_audio_ring, SeekFlush, TrackBoundary};
+use crate::audio::crossfade::CrossfadeConfig;
+use crate::audio::dsp::DspConfig;
+use crate::audio::channels::ChannelMatrix;
+use crate::audio::gain::Fader;
+use crate::audio::resample::ResampleQuality;
+use crate::audio::stretch::{MAX_SPEED, MIN_SPEED};
+use crate::audio::replaygain::{linear_gain, ReplayGainMode};
+use crate::audio::queue::{PlayMode, PlayQueue, QueueState, Remap};
+use crate::audio::decoder::{decode_audio_loop, DecoderSettings, Source};
+use crate::audio::output::{build_output_stream, BuiltOutput, SampleDepth, StreamRequest};
+use crate::audio::latency::{ms_to_samples, LatencyProfile, LatencyReport};
+use crate::audio::device::{device_change, open_output_device, DeviceChangeReason};
+use crate::db::{repo::{dsp as dsp_repo, session::{self as session_repo, Session, SessionItem}, speed as speed_repo, tracks}, DbPool};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use tauri::{Emitter, Manager};
 use ringbuf::HeapProd;
 
 use serde::Serialize;
-use std::sync::{Arc, mpsc};
-use std::sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering, AtomicUsize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, Ordering, AtomicUsize};
 use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 use log::error;
 
-pub const PREBUFFER_SAMPLES: usize = 96_000; // ~1 seconds @ 48kHz stereo
-pub const MAX_BUFFER_SAMPLES: usize = 2_000_000;
+/// Longest wait for the prebuffer to fill before starting anyway.
+const PREBUFFER_TIMEOUT: Duration = Duration::from_millis(1200);
+/// An in-place seek the decoder hasn't picked up by then is redone by reloading the track.
+const SEEK_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often the session is saved while playing (or after it changed).
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Debug)]
 pub enum AudioCommand {
@@ -29,11 +45,36 @@ pub enum AudioCommand {
     Prev,
 }
 
+/// A queue item as handed to the decoder: the file, its normalization gain and playback speed.
+#[derive(Debug, Clone)]
+pub struct QueuedTrack { pub path: String, pub gain: f32, pub speed: f32 }
+
 #[derive(Debug)]
-pub enum DecoderControl { Stop, SwitchTo(String) }
+pub enum DecoderControl {
+    Stop,
+    /// The track to chain into when the current one ends (`None`: end there). Only taken if
+    /// the decoder has made `after` switches so far; an older hint was meant for the track
+    /// before.
+    SwitchTo { track: Option<QueuedTrack>, after: u64 },
+    SetCrossfade(CrossfadeConfig),
+    SetResampleQuality(ResampleQuality),
+    /// Replace the gain of the track being decoded.
+    SetGain(f32),
+    SetDsp(DspConfig),
+    SetChannelMatrix(ChannelMatrix),
+    /// Reposition `track` (the one playing as far as the engine knows) at `seconds`, and
+    /// publish where its audio starts in the ring under `generation`.
+    Seek { track: QueuedTrack, seconds: f64, generation: u64 },
+}
 
 #[derive(Debug)]
-pub enum EngineEvent { EndOfStream }
+pub enum EngineEvent {
+    EndOfStream,
+    /// Chained into the track from the last `SwitchTo`; its boundary is about to be published.
+    Switched,
+    /// The track from the last `SwitchTo` couldn't be opened (end of stream follows).
+    NextFailed,
+}
 
 #[derive(Serialize, Clone)]
 struct StateEvent { state: &'static str }
@@ -42,12 +83,43 @@ struct PositionEvent { seconds: f64 }
 #[derive(Serialize, Clone)]
 struct DurationEvent { seconds: f64 }
 #[derive(Serialize, Clone)]
-struct DeviceEvent { name: String }
+struct DeviceEvent { name: String, reason: DeviceChangeReason }
 #[derive(Serialize, Clone)]
 struct PeakEvent { left: f32, right: f32, rms: f32 }
+#[derive(Serialize, Clone)]
+struct TrackEvent { index: usize, path: String }
+#[derive(Serialize, Clone)]
+struct SpeedEvent { speed: f32 }
+#[derive(Serialize, Clone)]
+struct ModeEvent { mode: PlayMode }
+#[derive(Serialize, Clone, PartialEq)]
+struct FormatEvent { sample_rate: u32, channels: u16, sample_format: String, bit_perfect: bool }
+
+/// A seek the output hasn't caught up with yet.
+struct PendingSeek {
+    generation: u64,
+    seconds: f64,
+    started: Instant,
+    /// [`SeekFlush::now_us`] at the request; `None` when paused (nothing to measure).
+    started_us: Option<u64>,
+}
 
 pub struct AudioEngine {
     device: cpal::Device,
+    // the user's choice (None = system default) and the name of the device actually in use
+    preferred_device: Option<String>,
+    device_name: Arc<Mutex<Option<String>>>,
+    // raised by the output callback's error handler; cleared when the stream is rebuilt
+    stream_error: Arc<AtomicBool>,
+    // read by the output callback when dithering to integer formats
+    noise_shaping: Arc<AtomicBool>,
+    // pause/resume/stop/skip fades, applied by the output callback
+    fader: Arc<Fader>,
+    // playback speed, optionally remembered per file; the output callback counts position at
+    // the speed of the audio it is playing (f32 bits)
+    speed: f32,
+    remember_speed: bool,
+    playing_speed: Arc<AtomicU32>,
     out_sr: u32,
     out_ch: u16,
 
@@ -70,9 +142,46 @@ pub struct AudioEngine {
     stop_tx: Option<mpsc::Sender<DecoderControl>>,
     evt_rx: Option<mpsc::Receiver<EngineEvent>>, // decoder → engine
 
-    // queue
-    queue: Vec<String>,
-    current_index: Option<usize>,
+    // queue; `queue_state` mirrors it for the UI
+    queue: PlayQueue,
+    queue_state: Arc<Mutex<QueueState>>,
+
+    // gapless hand-over: queue position the decoder was told to chain into, the items it has
+    // chained into that the output hasn't reached yet, how many switches the decoder has
+    // reported, and the last `boundary.crossings` value the engine acted on
+    boundary: Arc<TrackBoundary>,
+    pending_next: Option<usize>,
+    committed: VecDeque<usize>,
+    switches: u64,
+    crossings_seen: u64,
+    // decoder reported end of stream; "ended" is emitted once the ring has drained
+    decoder_done: bool,
+    // queue item the decoder couldn't open as the next track; skipped at end of stream
+    next_failed: Option<usize>,
+
+    // seeking in place (shared with the decoder and the output callback)
+    seek_flush: Arc<SeekFlush>,
+    seek_pending: Option<PendingSeek>,
+
+    // session persistence: `session_dirty` is set by changes worth saving while not playing
+    session_dirty: bool,
+    session_saved_at: Instant,
+    decoder_settings: DecoderSettings,
+
+    // bit-perfect mode: the format the stream was asked to open at (the current track's rate,
+    // channels and sample depth), and what the UI was last told about the stream
+    bit_perfect: bool,
+    bit_perfect_format: Option<(u32, u16, Option<SampleDepth>)>,
+    last_format: Option<FormatEvent>,
+
+    // what the stream was opened with (`audio:latency`), and the device's own delay as
+    // measured by the output callback (µs, 0 = not reported yet)
+    latency_report: Arc<Mutex<LatencyReport>>,
+    device_latency_us: Arc<AtomicU32>,
+
+    // loudness normalization; `queue_is_album` is only maintained in auto mode
+    replay_gain_mode: ReplayGainMode,
+    queue_is_album: bool,
 
     // duration tracking (in frames @ out_sr)
     duration_frames: Arc<AtomicU64>,
@@ -87,13 +196,8 @@ pub struct AudioEngine {
 
 impl AudioEngine {
     pub fn new_with_app(app: Option<tauri::AppHandle>) -> anyhow::Result<Self> {
-        use cpal::traits::HostTrait;
-        let host = cpal::default_host();
-        let device = host.default_output_device().ok_or_else(|| anyhow::anyhow!("No output device"))?;
-
-        let (prod, cons, _cap) = make_audio_ring(MAX_BUFFER_SAMPLES);
+        let device = open_output_device(None)?;
 
-        // Static atomics captured by callback
         // Atomics shared with the output callback
         let state = Arc::new(AtomicU8::new(PlaybackState::Stopped.into()));
         let vol_bits = Arc::new(AtomicU32::new(f32_to_bits_atomic(1.0)));
@@ -103,24 +207,21 @@ impl AudioEngine {
         let peak_r_bits = Arc::new(AtomicU32::new(0.0f32.to_bits()));
         let rms_bits = Arc::new(AtomicU32::new(0.0f32.to_bits()));
         let out_sr_atomic = Arc::new(AtomicU32::new(0));
-
-        let BuiltOutput { stream, sample_rate, channels } = build_output_stream(
-            &device,
-            cons,
-            Arc::clone(&vol_bits),
-            Arc::clone(&state),
-            Arc::clone(&frames_played),
-            Arc::clone(&peak_l_bits),
-            Arc::clone(&peak_r_bits),
-            Arc::clone(&rms_bits),
-            queued_samples,
-        )?;
-        out_sr_atomic.store(sample_rate, Ordering::Relaxed);
+        let boundary = Arc::new(TrackBoundary::new());
+        let stream_error = Arc::new(AtomicBool::new(false));
 
         let mut engine = Self {
+            device_name: Arc::new(Mutex::new(device.name().ok())),
             device,
-            out_sr: sample_rate,
-            out_ch: channels,
+            preferred_device: None,
+            stream_error,
+            noise_shaping: Arc::new(AtomicBool::new(false)),
+            fader: Arc::new(Fader::new()),
+            speed: 1.0,
+            remember_speed: false,
+            playing_speed: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            out_sr: 0,
+            out_ch: 0,
             state,
             vol_bits,
             frames_played,
@@ -128,21 +229,41 @@ impl AudioEngine {
             peak_r_bits,
             rms_bits,
             queued_samples,
-            prod: Some(prod),
-            stream: Some(stream),
+            prod: None,
+            stream: None,
             decoder: None,
             stop_tx: None,
             evt_rx: None,
-            queue: vec![],
-            current_index: None,
+            queue: PlayQueue::default(),
+            queue_state: Arc::new(Mutex::new(QueueState::default())),
+            boundary,
+            pending_next: None,
+            committed: VecDeque::new(),
+            switches: 0,
+            crossings_seen: 0,
+            decoder_done: false,
+            next_failed: None,
+            seek_flush: Arc::new(SeekFlush::new()),
+            seek_pending: None,
+            session_dirty: false,
+            session_saved_at: Instant::now(),
+            decoder_settings: DecoderSettings::default(),
+            bit_perfect: false,
+            bit_perfect_format: None,
+            last_format: None,
+            latency_report: Arc::new(Mutex::new(LatencyReport::default())),
+            device_latency_us: Arc::new(AtomicU32::new(0)),
+            replay_gain_mode: ReplayGainMode::Off,
+            queue_is_album: false,
             duration_frames: Arc::new(AtomicU64::new(0)),
             out_sr_atomic: Arc::clone(&out_sr_atomic),
             app,
             metrics_thread: None,
         };
+        engine.rebuild_output()?;
 
         // start periodic UI emits (position/peaks)
-        engine.start_metrics_thread(engine.app.clone(), engine.out_sr);
+        engine.start_metrics_thread(engine.app.clone());
 
         Ok(engine)
 
@@ -150,12 +271,288 @@ impl AudioEngine {
     }
 
     // ------------- Public API -------------
-    pub fn set_volume(&self, v: f32) { self.vol_bits.store(f32_to_bits_atomic(v.clamp(0.0, 1.0)), Ordering::Relaxed); }
+    /// `v` is the slider position; the output maps it to a gain on a dB scale and ramps to it.
+    pub fn set_volume(&mut self, v: f32) {
+        self.vol_bits.store(f32_to_bits_atomic(v.clamp(0.0, 1.0)), Ordering::Relaxed);
+        self.session_dirty = true;
+    }
+
+    /// Takes effect for the next transition, including in the running decoder.
+    pub fn set_crossfade(&mut self, cfg: CrossfadeConfig) {
+        self.decoder_settings.crossfade = cfg;
+        if let Some(tx) = &self.stop_tx { let _ = tx.send(DecoderControl::SetCrossfade(cfg)); }
+    }
+
+    /// Used from the next track with a different format, or the next seek.
+    pub fn set_resample_quality(&mut self, q: ResampleQuality) {
+        self.decoder_settings.resample = q;
+        if let Some(tx) = &self.stop_tx { let _ = tx.send(DecoderControl::SetResampleQuality(q)); }
+    }
+
+    /// Applied to output written from now on; the ring still holds up to ~2 s processed
+    /// with the previous parameters.
+    pub fn set_dsp(&mut self, cfg: DspConfig) {
+        self.decoder_settings.dsp = cfg.clone();
+        if let Some(tx) = &self.stop_tx { let _ = tx.send(DecoderControl::SetDsp(cfg)); }
+    }
+
+    /// Takes effect from the next decoded block of the running decoder.
+    pub fn set_channel_matrix(&mut self, matrix: ChannelMatrix) {
+        self.decoder_settings.channel_matrix = matrix.clone();
+        if let Some(tx) = &self.stop_tx { let _ = tx.send(DecoderControl::SetChannelMatrix(matrix)); }
+    }
+
+    /// Apply the stored DSP profile of the current output device, or the global one.
+    pub fn reload_dsp_profile(&mut self) {
+        let Some(pool) = self.app.as_ref().and_then(|a| a.try_state::<DbPool>()) else { return };
+        let Ok(conn) = pool.get() else { return };
+        let device = self.device.name().unwrap_or_default();
+        let json = match dsp_repo::device_profile(&conn, &device) {
+            Ok(Some(json)) => Some(json),
+            Ok(None) => dsp_repo::global_config(&conn).unwrap_or_else(|e| { log::warn!("dsp settings: {e}"); None }),
+            Err(e) => { log::warn!("dsp profile for {device}: {e}"); None }
+        };
+        self.set_dsp(json.as_deref().and_then(DspConfig::from_json).unwrap_or_default());
+    }
+
+    /// Move output to the device called `name` (`None`: the system default), falling back to
+    /// the default if it isn't there. Playback continues from the same position.
+    pub fn set_output_device(&mut self, name: Option<String>) -> anyhow::Result<()> {
+        self.preferred_device = name;
+        let device = open_output_device(self.preferred_device.as_deref())?;
+        if device.name().ok() == self.device.name().ok() { return Ok(()); }
+        self.reopen_output(device, DeviceChangeReason::Selected)
+    }
+
+    /// React to the device monitor: move off a device that disappeared, follow the system
+    /// default when no device was chosen, and return to the chosen one when it comes back.
+    pub fn devices_changed(&mut self, default: Option<String>, devices: Vec<String>) {
+        let active = self.device_name.lock().unwrap().clone();
+        let Some(reason) = device_change(active.as_deref(), self.preferred_device.as_deref(), default.as_deref(), &devices)
+        else { return };
+        log::info!("output devices changed ({reason:?})");
+        if let Err(e) = self.recover_output(reason) { log::warn!("no usable output device: {e}"); }
+    }
+
+    /// Rebuild the stream on the preferred device (or the default) after `reason`.
+    fn recover_output(&mut self, reason: DeviceChangeReason) -> anyhow::Result<()> {
+        // release the device first: a hw device we still hold can't be found (or opened) again
+        self.stop_decoder();
+        self.stream = None;
+        let device = open_output_device(self.preferred_device.as_deref())?;
+        self.reopen_output(device, reason)
+    }
+
+    /// Switch to `device`, falling back to the system default if it can't be opened.
+    fn reopen_output(&mut self, device: cpal::Device, reason: DeviceChangeReason) -> anyhow::Result<()> {
+        if let Err(e) = self.switch_device(device, reason) {
+            log::warn!("cannot use output device {:?}: {e}", self.preferred_device);
+            let default = open_output_device(None)?;
+            return self.switch_device(default, reason);
+        }
+        Ok(())
+    }
+
+    fn switch_device(&mut self, device: cpal::Device, reason: DeviceChangeReason) -> anyhow::Result<()> {
+        let state = PlaybackState::from(self.state.load(Ordering::Relaxed));
+        let position = self.frames_played.load(Ordering::Relaxed) as f64 / self.out_sr as f64;
+
+        // release the old device before opening the new one
+        self.stop_decoder();
+        self.stream = None;
+        self.device = device;
+        let name = self.device.name().ok();
+        *self.device_name.lock().unwrap() = name.clone();
+        log::info!("output device: {}", name.as_deref().unwrap_or("?"));
+
+        match (state, self.queue.current()) {
+            // reload rebuilds the stream on the new device, at its rate, and keeps play/pause
+            (PlaybackState::Playing | PlaybackState::Paused, Some(i)) => {
+                self.reload(position)?;
+                self.kick_duration_scan(self.queue.path(i).to_string());
+            }
+            _ => {
+                self.queued_samples.store(0, Ordering::Relaxed);
+                self.reset_boundary();
+                self.rebuild_output()?;
+            }
+        }
+
+        self.reload_dsp_profile();
+        if let (Some(app), Some(name)) = (&self.app, name) { let _ = app.emit("audio:device", DeviceEvent { name, reason }); }
+        Ok(())
+    }
+
+    /// Play at each track's own sample rate and channel count where the device allows it,
+    /// untouched by resampling, volume, DSP, normalization and crossfades.
+    pub fn set_bit_perfect(&mut self, on: bool) -> anyhow::Result<()> {
+        if on == self.bit_perfect { return Ok(()); }
+        self.bit_perfect = on;
+        match PlaybackState::from(self.state.load(Ordering::Relaxed)) {
+            PlaybackState::Playing | PlaybackState::Paused if self.queue.current().is_some() => self.restart_output(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Noise-shaped dither for integer output formats; applies from the next callback.
+    pub fn set_noise_shaping(&mut self, on: bool) {
+        self.noise_shaping.store(on, Ordering::Relaxed);
+    }
+
+    /// Length of the fades on pause, resume, stop and skip; from the next one.
+    pub fn set_transport_fade(&mut self, ms: u32) {
+        self.fader.set_fade_ms(ms);
+    }
+
+    /// Playback speed without a change in pitch. With per-file memory on it is saved for the
+    /// current file; a playing or paused track changes speed in place (a seek to where it is).
+    pub fn set_speed(&mut self, speed: f32) -> anyhow::Result<()> {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        let Some(idx) = self.queue.current() else { return Ok(()) };
+        if self.remember_speed { self.save_track_speed(idx); }
+        self.emit_speed(idx);
+
+        let state = PlaybackState::from(self.state.load(Ordering::Relaxed));
+        let playing_at = f32::from_bits(self.playing_speed.load(Ordering::Relaxed));
+        if state == PlaybackState::Stopped || self.stop_tx.is_none() || self.track_speed(idx) == playing_at {
+            // the hinted next track may still be at the old speed
+            if self.stop_tx.is_some() { self.hint_next(); }
+            return Ok(());
+        }
+        self.seek(self.position_seconds(self.out_sr, self.out_ch))
+    }
+
+    /// Whether each file keeps the speed it was last played at; takes effect from the next track.
+    pub fn set_remember_speed(&mut self, on: bool) {
+        self.remember_speed = on;
+    }
+
+    /// Device buffer, prebuffer and decoder read-ahead. The stream is reopened right away.
+    pub fn set_latency(&mut self, profile: LatencyProfile) -> anyhow::Result<()> {
+        if profile == self.decoder_settings.latency { return Ok(()); }
+        self.decoder_settings.latency = profile;
+        self.restart_output()
+    }
+
+    /// Re-resolves the gain of the playing and the upcoming track.
+    pub fn set_replay_gain_mode(&mut self, mode: ReplayGainMode) {
+        self.replay_gain_mode = mode;
+        self.update_queue_is_album();
+        if let (Some(i), Some(tx)) = (self.queue.current(), &self.stop_tx) {
+            let _ = tx.send(DecoderControl::SetGain(self.track_gain(i)));
+            self.hint_next();
+        }
+    }
+
+    /// Takes effect from the next track; a shuffled order is rebuilt around the current one.
+    pub fn set_play_mode(&mut self, mode: PlayMode) {
+        let albums = self.album_ids_for(mode);
+        self.queue.set_mode(mode, &albums);
+        if self.stop_tx.is_some() { self.hint_next(); }
+        if let Some(app) = &self.app { let _ = app.emit("audio:mode", ModeEvent { mode }); }
+        self.emit_queue();
+    }
+
+    pub fn queue_append(&mut self, paths: Vec<String>) {
+        self.edit_queue(|q| Some(q.append(paths)));
+    }
+
+    /// Insert after the last track whose audio is already playing or decoded, so the items
+    /// still come next when the decoder has chained into the following track.
+    pub fn queue_play_next(&mut self, paths: Vec<String>) {
+        let after = self.playing_items().last().copied().or(self.queue.current());
+        self.edit_queue(|q| Some(q.insert_after(after, paths)));
+    }
+
+    /// The playing track (and the next one once the decoder has moved on to it) can't be removed.
+    pub fn queue_remove(&mut self, index: usize) -> anyhow::Result<()> {
+        if index >= self.queue.len() { return Err(anyhow::anyhow!("No queue item {index}")); }
+        if self.playing_items().contains(&index) { return Err(anyhow::anyhow!("Queue item {index} is playing")); }
+        self.edit_queue(|q| Some(q.retain(|i| i != index)));
+        Ok(())
+    }
+
+    pub fn queue_move(&mut self, from: usize, to: usize) {
+        self.edit_queue(|q| q.move_item(from, to));
+    }
+
+    /// Remove everything after the current track in play order.
+    pub fn queue_clear_upcoming(&mut self) {
+        let keep = self.playing_items();
+        let upcoming = self.queue.upcoming().to_vec();
+        self.edit_queue(|q| Some(q.retain(|i| keep.contains(&i) || !upcoming.contains(&i))));
+    }
+
+    pub fn queue_jump(&mut self, index: usize) -> anyhow::Result<()> {
+        let Some(pos) = self.queue.pos_of(index) else { return Err(anyhow::anyhow!("No queue item {index}")) };
+        self.jump_to(pos)
+    }
+
+    /// Write the queue, position, volume and play mode to the database.
+    pub fn save_session(&mut self) {
+        self.session_saved_at = Instant::now();
+        self.session_dirty = false;
+        let Some(pool) = self.app.as_ref().and_then(|a| a.try_state::<DbPool>()) else { return };
+        let order = self.queue.order();
+        let mut play_pos = vec![0; order.len()];
+        for (p, &i) in order.iter().enumerate() { play_pos[i] = p; }
+        let session = Session {
+            items: self.queue.items().iter().zip(play_pos)
+                .map(|(path, play_pos)| SessionItem { file_path: path.clone(), play_pos })
+                .collect(),
+            current: self.queue.current(),
+            position_secs: self.frames_played.load(Ordering::Relaxed) as f64 / self.out_sr as f64,
+            volume: f32::from_bits(self.vol_bits.load(Ordering::Relaxed)) as f64,
+            play_mode: self.queue.mode().as_str().to_string(),
+        };
+        let saved = pool.get().map_err(|e| e.to_string()).and_then(|mut conn| {
+            let tx = conn.transaction().map_err(|e| e.to_string())?;
+            session_repo::save(&tx, &session).map_err(|e| e.to_string())?;
+            tx.commit().map_err(|e| e.to_string())
+        });
+        if let Err(e) = saved { log::warn!("saving playback session failed: {e}"); }
+    }
+
+    /// Bring back the saved session, paused at the saved position. Files that have disappeared
+    /// are left out; if the current one is among them, the next track is loaded from its start.
+    pub fn restore_session(&mut self) -> anyhow::Result<()> {
+        let Some(pool) = self.app.as_ref().and_then(|a| a.try_state::<DbPool>()) else { return Ok(()) };
+        let s = session_repo::load(&*pool.get()?)?;
+        self.set_volume(s.volume as f32);
+        let mode = PlayMode::parse(&s.play_mode).unwrap_or_default();
+
+        let kept: Vec<usize> = (0..s.items.len())
+            .filter(|&i| std::path::Path::new(&s.items[i].file_path).exists())
+            .collect();
+        if kept.len() < s.items.len() {
+            log::info!("restoring session without {} missing file(s)", s.items.len() - kept.len());
+        }
+        let items = kept.iter().map(|&i| s.items[i].file_path.clone()).collect();
+        let mut order: Vec<usize> = (0..kept.len()).collect();
+        order.sort_by_key(|&n| s.items[kept[n]].play_pos);
+        // the saved current track, or whatever would have played after it
+        let current_pos = s.current.and_then(|c| s.items.get(c)).map(|c| c.play_pos);
+        let pos = current_pos.and_then(|cp| order.iter().position(|&n| s.items[kept[n]].play_pos >= cp));
+        let resumes_current = s.current.is_some_and(|c| kept.contains(&c));
+
+        self.queue.restore(items, order, pos, mode);
+        self.update_queue_is_album();
+        if let Some(app) = &self.app { let _ = app.emit("audio:mode", ModeEvent { mode }); }
+        self.emit_queue();
+        self.session_dirty = false;
+
+        let Some(i) = self.queue.current() else { return Ok(()) };
+        self.kick_duration_scan(self.queue.path(i).to_string());
+        self.emit_track();
+        self.seek(if resumes_current { s.position_secs } else { 0.0 })
+    }
 
     pub fn load(&mut self, path: String) -> anyhow::Result<()> {
-        self.queue = vec![path.clone()];
-        self.current_index = Some(0);
+        self.queue.set_items(vec![path.clone()], Some(0), &[]);
+        self.update_queue_is_album();
+        self.emit_queue();
         self.stop_decoder();
+        self.reset_boundary();
         self.frames_played.store(0, Ordering::Relaxed);
         self.queued_samples.store(0, Ordering::Relaxed);
         self.duration_frames.store(0, Ordering::Relaxed);
@@ -165,65 +562,56 @@ impl AudioEngine {
     }
 
     pub fn set_queue(&mut self, items: Vec<String>, start_at: usize) -> anyhow::Result<()> {
-        self.queue = items; self.current_index = None; self.stop_decoder();
+        self.stop_decoder();
+        let start_at = (start_at < items.len()).then_some(start_at);
+        let albums = self.album_ids_for_paths(self.queue.mode(), &items);
+        self.queue.set_items(items, start_at, &albums);
+        self.update_queue_is_album();
+        self.emit_queue();
+        self.reset_boundary();
         self.frames_played.store(0, Ordering::Relaxed);
         self.queued_samples.store(0, Ordering::Relaxed);
         self.duration_frames.store(0, Ordering::Relaxed);
-        if start_at < self.queue.len() { self.current_index = Some(start_at); self.kick_duration_scan(self.queue[start_at].clone()); }
+        if let Some(i) = start_at { self.kick_duration_scan(self.queue.path(i).to_string()); }
         Ok(())
     }
 
-    pub fn next(&mut self) -> anyhow::Result<()> { self.advance(1) }
-    pub fn prev(&mut self) -> anyhow::Result<()> { self.advance_back(1) }
+    pub fn next(&mut self) -> anyhow::Result<()> { self.advance() }
+    pub fn prev(&mut self) -> anyhow::Result<()> { self.advance_back() }
 
     pub fn play(&mut self) -> anyhow::Result<()> {
         match PlaybackState::from(self.state.load(Ordering::Relaxed)) {
             PlaybackState::Playing => return Ok(()),
-            PlaybackState::Paused => { self.state.store(PlaybackState::Playing.into(), Ordering::Relaxed); if let Some(s) = &self.stream { s.play()?; } self.emit_state("playing"); return Ok(()); }
+            PlaybackState::Paused => { self.state.store(PlaybackState::Playing.into(), Ordering::Relaxed); self.fader.open(); if let Some(s) = &self.stream { s.play()?; } self.emit_state("playing"); return Ok(()); }
             PlaybackState::Stopped => {}
         }
-        let idx = self.current_index.unwrap_or(0);
-        if idx >= self.queue.len() { return Err(anyhow::anyhow!("Queue empty")); }
-        let file = self.queue[idx].clone();
-
-        // channels for decoder events
-        let (evtx, evrx) = mpsc::channel();
-        self.evt_rx = Some(evrx);
-
-        // control to decoder
-        let (tx, rx) = mpsc::channel();
-        self.stop_tx = Some(tx.clone());
-
-        // take producer for decoder thread
-        let prod = self.prod.take().expect("producer already taken");
-        let out_sr = self.out_sr; let out_ch = self.out_ch; let queued = self.queued_samples;
-
-        // pre-inform decoder about the next track (gapless)
-        if let Some(next_path) = self.peek_next_path() { let _ = tx.send(DecoderControl::SwitchTo(next_path)); }
-
-        let evtx2 = evtx.clone();
-        let handle = thread::spawn(move || {
-            if let Err(e) = decode_audio_loop(file, prod, out_sr, out_ch, None, rx, evtx2, queued) { error!("Decoder error: {e}"); }
-        });
-        self.decoder = Some(handle);
+        if self.queue.is_empty() { return Err(anyhow::anyhow!("Queue empty")); }
+        if self.queue.pos().is_none() { self.queue.set_pos(0); }
+        let idx = self.queue.current().unwrap_or(0);
+        if self.negotiate_format(idx) {
+            self.stream = None;
+            self.queued_samples.store(0, Ordering::Relaxed);
+            self.reset_boundary();
+            self.rebuild_output()?;
+        }
+        self.spawn_decoder(idx, None)?;
+        self.emit_track();
 
-        // Warm up
-        let start = Instant::now();
-        while self.queued_samples.load(Ordering::Relaxed) < PREBUFFER_SAMPLES && start.elapsed() < Duration::from_millis(1200) { thread::sleep(Duration::from_millis(5)); }
+        self.wait_prebuffer();
+        // the track starts at its first sample: nothing to fade in
+        self.fader.open_at_once();
         if let Some(s) = &self.stream { s.play()?; }
         self.state.store(PlaybackState::Playing.into(), Ordering::Relaxed);
         self.emit_state("playing");
 
-        // detach EOS watcher
-        self.spawn_eos_watcher();
-
-        log::info!("Engine::play starting {:?}", self.current_index);
+        log::info!("Engine::play starting {:?}", self.queue.current());
 
         Ok(())
     }
 
     pub fn pause(&self) {
-        // actually pause the output stream
+        // fade out, then actually pause the output stream
+        self.fade_out();
         if let Some(s) = &self.stream {
             // needs: use cpal::traits::StreamTrait;
             let _ = s.pause();
@@ -235,6 +623,13 @@ impl AudioEngine {
     }
 
     pub fn stop(&mut self) {
+        self.fade_out();
+        self.halt();
+        self.emit_state("stopped");
+    }
+
+    /// [`stop`](Self::stop) without telling the UI.
+    fn halt(&mut self) {
         use cpal::traits::StreamTrait;
 
         // mark state & stop the decoder thread
@@ -250,34 +645,45 @@ impl AudioEngine {
         // reset counters
         self.frames_played.store(0, Ordering::Relaxed);
         self.queued_samples.store(0, Ordering::Relaxed);
+        self.reset_boundary();
 
         // build a fresh ring and output stream (kept paused until next Play)
-        let (prod, cons, _cap) = make_audio_ring(MAX_BUFFER_SAMPLES);
-        self.prod = Some(prod);
+        let _ = self.rebuild_output();
+    }
 
-        if let Ok(BuiltOutput { stream, sample_rate, channels }) =
-            build_output_stream(
-                &self.device,
-                cons,
-                Arc::clone(&self.vol_bits),
-                Arc::clone(&self.state),
-                Arc::clone(&self.frames_played),
-                Arc::clone(&self.peak_l_bits),
-                Arc::clone(&self.peak_r_bits),
-                Arc::clone(&self.rms_bits),
-                self.queued_samples,
-            )
-        {
-            self.out_sr_atomic.store(sample_rate, Ordering::Relaxed);
-            self.out_sr = sample_rate;
-            self.out_ch = channels;
-            self.stream = Some(stream);
-        }
+    /// Seek within the current track. While its decoder is running this happens in place: the
+    /// stream keeps running, fades out, drops what's queued and fades back in at the new
+    /// position once the decoder gets there. Otherwise the track is [reloaded](Self::reload).
+    pub fn seek(&mut self, seconds: f64) -> anyhow::Result<()> {
+        let state = PlaybackState::from(self.state.load(Ordering::Relaxed));
+        let live = state != PlaybackState::Stopped
+            && self.stream.is_some()
+            && self.decoder.as_ref().is_some_and(|h| !h.is_finished())
+            && !self.decoder_done
+            && !self.next_committed();
+        let (Some(idx), Some(tx), true) = (self.queue.current(), &self.stop_tx, live) else { return self.reload(seconds) };
+
+        let playing = state == PlaybackState::Playing;
+        let generation = self.seek_flush.request(playing);
+        let _ = tx.send(DecoderControl::Seek { track: self.queued_track(idx), seconds, generation });
+        self.seek_pending = Some(PendingSeek {
+            generation,
+            seconds,
+            started: Instant::now(),
+            started_us: playing.then(|| self.seek_flush.now_us()),
+        });
 
-        self.emit_state("stopped");
+        // show the target time right away; anything the output crossed meanwhile is dropped
+        self.frames_played.store((seconds.max(0.0) * self.out_sr as f64) as u64, Ordering::Relaxed);
+        self.crossings_seen = self.boundary.crossings.load(Ordering::Acquire);
+        self.hint_next();
+        self.session_dirty = true;
+        Ok(())
     }
 
-    pub fn seek(&mut self, seconds: f64) -> anyhow::Result<()> {
+    /// Restart the current track at `seconds` on a fresh decoder, ring and stream, keeping
+    /// play/pause.
+    fn reload(&mut self, seconds: f64) -> anyhow::Result<()> {
         use cpal::traits::StreamTrait;
 
         // remember previous state
@@ -285,61 +691,34 @@ impl AudioEngine {
         PlaybackState::from(self.state.load(Ordering::Relaxed)),
         PlaybackState::Playing
     );
+        self.fade_out();
 
         // go paused while we rebuild the pipeline
         self.state.store(PlaybackState::Paused.into(), Ordering::Relaxed);
         self.stop_decoder();
 
-        // fresh ring + stream
-        let (prod, cons, _cap) = make_audio_ring(MAX_BUFFER_SAMPLES);
-        self.prod = Some(prod);
+        // fresh ring + stream (drop the old stream first so it can't cross a stale boundary)
+        self.stream = None;
+        if let Some(i) = self.queue.current() { self.negotiate_format(i); }
         self.queued_samples.store(0, Ordering::Relaxed);
+        self.reset_boundary();
+        self.rebuild_output()?;
 
-        let BuiltOutput { stream, sample_rate, channels } = build_output_stream(
-            &self.device,
-            cons,
-            Arc::clone(&self.vol_bits),
-            Arc::clone(&self.state),
-            Arc::clone(&self.frames_played),
-            Arc::clone(&self.peak_l_bits),
-            Arc::clone(&self.peak_r_bits),
-            Arc::clone(&self.rms_bits),
-            self.queued_samples,
-        )?;
-        self.out_sr_atomic.store(sample_rate, Ordering::Relaxed);
-        self.stream = Some(stream);
-        self.out_sr = sample_rate;
-        self.out_ch = channels;
+        self.session_dirty = true;
 
         // seed position so UI shows the target time immediately
         self.frames_played
-            .store((seconds * sample_rate as f64) as u64, Ordering::Relaxed);
+            .store((seconds * self.out_sr as f64) as u64, Ordering::Relaxed);
 
         // (re)start decoder from the seek position
-        let idx   = self.current_index.unwrap_or(0);
-        let file  = self.queue.get(idx).cloned().ok_or_else(|| anyhow::anyhow!("No file"))?;
-        let (evtx, evrx) = mpsc::channel(); self.evt_rx = Some(evrx);
-        let (tx,   rx)   = mpsc::channel(); self.stop_tx = Some(tx.clone());
-        if let Some(next_path) = self.peek_next_path() { let _ = tx.send(DecoderControl::SwitchTo(next_path)); }
-        let prod  = self.prod.take().expect("producer taken");
-        let out_sr = self.out_sr; let out_ch = self.out_ch; let queued = self.queued_samples;
-        let handle = thread::spawn(move || {
-            if let Err(e) = decode_audio_loop(file, prod, out_sr, out_ch, Some(seconds), rx, evtx, queued) {
-                error!("Decoder error: {e}");
-            }
-        });
-        self.decoder = Some(handle);
+        let Some(idx) = self.queue.current() else { return Err(anyhow::anyhow!("No file")) };
+        self.spawn_decoder(idx, Some(seconds))?;
 
-        // allow prebuffer to fill
-        let start = Instant::now();
-        while self.queued_samples.load(Ordering::Relaxed) < PREBUFFER_SAMPLES
-            && start.elapsed() < Duration::from_millis(1200)
-        {
-            thread::sleep(Duration::from_millis(5));
-        }
+        self.wait_prebuffer();
 
-        // resume only if we were playing before
+        // resume only if we were playing before, fading in mid-track
         if was_playing {
+            self.fader.open();
             if let Some(s) = &self.stream { s.play()?; }
             self.state.store(PlaybackState::Playing.into(), Ordering::Relaxed);
             self.emit_state("playing");
@@ -349,7 +728,6 @@ impl AudioEngine {
             self.emit_state("paused");
         }
 
-        self.spawn_eos_watcher();
         Ok(())
     }
 
@@ -371,6 +749,12 @@ impl AudioEngine {
     }
 
     pub fn sample_rate_arc(&self) -> Arc<AtomicU32> { Arc::clone(&self.out_sr_atomic) }
+    pub fn device_name_arc(&self) -> Arc<Mutex<Option<String>>> { Arc::clone(&self.device_name) }
+    pub fn volume_arc(&self) -> Arc<AtomicU32> { Arc::clone(&self.vol_bits) }
+    pub fn queue_state_arc(&self) -> Arc<Mutex<QueueState>> { Arc::clone(&self.queue_state) }
+    pub fn latency_arcs(&self) -> (Arc<Mutex<LatencyReport>>, Arc<AtomicU32>) {
+        (Arc::clone(&self.latency_report), Arc::clone(&self.device_latency_us))
+    }
     // ------------- Internals -------------
     fn stop_decoder(&mut self) {
         if let Some(tx) = self.stop_tx.take() {
@@ -381,60 +765,430 @@ impl AudioEngine {
         }
     }
 
-    fn peek_next_path(&self) -> Option<String> { let idx = self.current_index?; let next = idx + 1; self.queue.get(next).cloned() }
+    /// Called regularly by the runtime: reacts to gapless track changes and end of stream.
+    pub fn poll(&mut self) {
+        if self.stream.is_some() && self.stream_error.load(Ordering::Acquire) {
+            self.stream_error.store(false, Ordering::Release);
+            if let Err(e) = self.recover_output(DeviceChangeReason::StreamError) {
+                log::warn!("rebuilding the output stream failed: {e}");
+            }
+        }
 
-    fn advance(&mut self, n: usize) -> anyhow::Result<()> {
-        if self.queue.is_empty() { return Ok(()); }
-        let len = self.queue.len();
-        let idx = self.current_index.unwrap_or(0) % len;
-        let next = (idx + (n % len)) % len;
+        let playing = PlaybackState::from(self.state.load(Ordering::Relaxed)) == PlaybackState::Playing;
+        if (playing || self.session_dirty) && self.session_saved_at.elapsed() >= SESSION_SAVE_INTERVAL {
+            self.save_session();
+        }
 
-        if next == idx { return Ok(()); }
-        self.current_index = Some(next);
-        self.stop();
-        self.kick_duration_scan(self.queue[next].clone());
-        self.play()
+        // crossings first: the decoder reports a switch before the output can cross it
+        let crossings = self.boundary.crossings.load(Ordering::Acquire);
+        while let Some(evt) = self.evt_rx.as_ref().and_then(|rx| rx.try_recv().ok()) {
+            match evt {
+                EngineEvent::EndOfStream => self.decoder_done = true,
+                EngineEvent::NextFailed => self.next_failed = self.pending_next.and_then(|p| self.queue.item_at(p)),
+                EngineEvent::Switched => {
+                    // hint the track after it right away: it may be shorter than the read-ahead
+                    self.switches += 1;
+                    if let Some(item) = self.pending_next.take().and_then(|p| self.queue.item_at(p)) {
+                        self.committed.push_back(item);
+                    }
+                    self.hint_next();
+                }
+            }
+        }
+
+        self.poll_seek();
+
+        for _ in self.crossings_seen..crossings {
+            let Some(item) = self.committed.pop_front() else { break };
+            if let Some(pos) = self.queue.pos_of(item) { self.queue.set_pos(pos); }
+            self.duration_frames.store(0, Ordering::Relaxed);
+            self.kick_duration_scan(self.queue.path(item).to_string());
+            self.emit_track();
+        }
+        self.crossings_seen = crossings;
+
+        if self.decoder_done && self.queued_samples.load(Ordering::Relaxed) == 0 {
+            self.decoder_done = false;
+            // Normally the next track is chained gaplessly before this point; getting here
+            // means there is none, or the hinted one couldn't be opened and is skipped.
+            // A hint sent after the failure (e.g. by a queue edit) came too late to be used.
+            self.pending_next = None;
+            let next = match self.next_failed.take().and_then(|i| self.queue.pos_of(i)) {
+                Some(failed) => { self.queue.set_pos(failed); self.queue.next_pos(false) }
+                None => self.queue.next_pos(true),
+            };
+            let restarted = next.is_some_and(|p| self.jump_to(p).is_ok());
+            if !restarted {
+                self.halt();
+                self.emit_state("ended");
+            }
+        }
     }
 
-    fn advance_back(&mut self, n: usize) -> anyhow::Result<()> {
-        if self.queue.is_empty() { return Ok(()); }
-        let len = self.queue.len();
-        let idx = self.current_index.unwrap_or(0) % len;
-        let step = n % len;
-        let prev = (idx + len - step) % len;
+    /// Record how long the last in-place seek took once the output has played its first new
+    /// sample, or reload if the decoder never got to it.
+    fn poll_seek(&mut self) {
+        let Some(p) = &self.seek_pending else { return };
+        if self.seek_flush.done.load(Ordering::Acquire) >= p.generation {
+            if let Some(started) = p.started_us {
+                let ms = self.seek_flush.done_at_us.load(Ordering::Relaxed).saturating_sub(started) as f64 / 1000.0;
+                log::debug!("seek to {:.1}s took {ms:.1} ms", p.seconds);
+                let mut report = self.latency_report.lock().unwrap();
+                report.seek_ms = Some(ms);
+                if let Some(app) = &self.app { let _ = app.emit("audio:latency", report.clone()); }
+            }
+            self.seek_pending = None;
+            return;
+        }
+        let stalled = self.decoder.as_ref().map_or(true, |h| h.is_finished()) || p.started.elapsed() >= SEEK_TIMEOUT;
+        if self.seek_flush.ready.load(Ordering::Acquire) < p.generation && stalled {
+            let seconds = p.seconds;
+            log::warn!("seek to {seconds:.1}s didn't reach the decoder, reloading");
+            if let Err(e) = self.reload(seconds) { log::warn!("reloading for seek failed: {e}"); }
+        }
+    }
 
-        if prev == idx { return Ok(()); }
-        self.current_index = Some(prev);
-        self.stop();
-        self.kick_duration_scan(self.queue[prev].clone());
-        self.play()
+    /// Start the decoder on queue item `idx` with a fresh event/control channel pair.
+    /// The ring producer must be available (i.e. the ring was just (re)built).
+    fn spawn_decoder(&mut self, idx: usize, seek: Option<f64>) -> anyhow::Result<()> {
+        let file = self.queued_track(idx);
+        self.playing_speed.store(file.speed.to_bits(), Ordering::Relaxed);
+
+        // channels for decoder events
+        let (evtx, evrx) = mpsc::channel();
+        self.evt_rx = Some(evrx);
+        self.decoder_done = false;
+        self.next_failed = None;
+        self.committed.clear();
+        self.switches = 0;
+
+        // control to decoder
+        let (tx, rx) = mpsc::channel();
+        self.stop_tx = Some(tx);
+
+        // take producer for decoder thread
+        let prod = self.prod.take().ok_or_else(|| anyhow::anyhow!("producer already taken"))?;
+        let out_sr = self.out_sr; let out_ch = self.out_ch; let queued = self.queued_samples;
+        let boundary = Arc::clone(&self.boundary);
+        let flush = Arc::clone(&self.seek_flush);
+        let settings = self.decoder_settings.clone();
+
+        let handle = thread::spawn(move || {
+            if let Err(e) = decode_audio_loop(file, prod, out_sr, out_ch, seek, settings, rx, evtx, queued, boundary, flush) { error!("Decoder error: {e}"); }
+        });
+        self.decoder = Some(handle);
+
+        // pre-inform decoder about the next track (gapless)
+        self.hint_next();
+        Ok(())
     }
 
-    fn spawn_eos_watcher(&mut self) {
-        if let Some(rx) = self.evt_rx.take() {
-            let app = self.app.clone();
-            std::thread::spawn(move || {
-                if let Ok(EngineEvent::EndOfStream) = rx.recv() {
-                    if let Some(app) = app {
-                        let _ = app.emit("audio:state", StateEvent { state: "ended" });
-                    }
-                }
-            });
+    /// Tell the running decoder which queue item follows the last one it has chained into (the
+    /// current one if none), or that none does.
+    fn hint_next(&mut self) {
+        let from = self.committed.back().and_then(|&i| self.queue.pos_of(i)).or(self.queue.pos());
+        let next = from.and_then(|p| self.queue.next_after(p, true));
+        self.pending_next = next;
+        if let Some(tx) = &self.stop_tx {
+            let track = next.and_then(|p| self.queue.item_at(p)).map(|i| self.queued_track(i));
+            let _ = tx.send(DecoderControl::SwitchTo { track, after: self.switches });
+        }
+    }
+
+    fn queued_track(&self, idx: usize) -> QueuedTrack {
+        QueuedTrack { path: self.queue.path(idx).to_string(), gain: self.track_gain(idx), speed: self.track_speed(idx) }
+    }
+
+    /// Speed for queue item `idx`: its remembered one if there is one, else the current setting.
+    /// Bit-perfect playback is never time-stretched.
+    fn track_speed(&self, idx: usize) -> f32 {
+        if self.decoder_settings.bit_perfect { return 1.0; }
+        if !self.remember_speed { return self.speed; }
+        let Some(pool) = self.app.as_ref().and_then(|a| a.try_state::<DbPool>()) else { return self.speed };
+        let Ok(conn) = pool.get() else { return self.speed };
+        match speed_repo::track_speed(&conn, self.queue.path(idx)) {
+            Ok(Some(speed)) => (speed as f32).clamp(MIN_SPEED, MAX_SPEED),
+            Ok(None) => self.speed,
+            Err(e) => { log::warn!("track speed lookup failed: {e}"); self.speed }
+        }
+    }
+
+    fn save_track_speed(&self, idx: usize) {
+        let Some(pool) = self.app.as_ref().and_then(|a| a.try_state::<DbPool>()) else { return };
+        let Ok(conn) = pool.get() else { return };
+        if let Err(e) = speed_repo::set_track_speed(&conn, self.queue.path(idx), self.speed as f64) {
+            log::warn!("saving track speed failed: {e}");
+        }
+    }
+
+    /// Linear normalization gain for queue item `idx` under the current mode. Files that
+    /// aren't in the library or carry no ReplayGain tags play at unity gain.
+    fn track_gain(&self, idx: usize) -> f32 {
+        if self.decoder_settings.bit_perfect { return 1.0; }
+        let album = match self.replay_gain_mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => false,
+            ReplayGainMode::Album => true,
+            ReplayGainMode::Auto => self.queue_is_album,
+        };
+        let Some(pool) = self.app.as_ref().and_then(|a| a.try_state::<DbPool>()) else { return 1.0 };
+        let Ok(conn) = pool.get() else { return 1.0 };
+        match tracks::replay_gain_by_path(&conn, self.queue.path(idx)) {
+            Ok(Some((rg, _))) => linear_gain(&rg, album),
+            Ok(None) => 1.0,
+            Err(e) => { log::warn!("replaygain lookup failed: {e}"); 1.0 }
+        }
+    }
+
+    /// Apply a queue edit without interrupting the playing track, then point the decoder's
+    /// gapless hint at whatever follows now.
+    fn edit_queue(&mut self, edit: impl FnOnce(&mut PlayQueue) -> Option<Remap>) {
+        let pending = self.pending_next.and_then(|p| self.queue.item_at(p));
+        let Some(remap) = edit(&mut self.queue) else { return };
+        self.pending_next = pending.and_then(|i| remap[i]).and_then(|i| self.queue.pos_of(i));
+        self.committed = self.committed.iter().filter_map(|&i| remap[i]).collect();
+        self.next_failed = self.next_failed.and_then(|i| remap[i]);
+        if self.stop_tx.is_some() { self.hint_next(); }
+        self.update_queue_is_album();
+        self.emit_queue();
+    }
+
+    /// The decoder has already moved on to a later track (its audio is in the ring).
+    fn next_committed(&self) -> bool {
+        !self.committed.is_empty()
+            || self.boundary.next_at.load(Ordering::Acquire) != TrackBoundary::NONE
+            || self.boundary.crossings.load(Ordering::Acquire) != self.crossings_seen
+    }
+
+    /// Items whose audio is being played or already decoded.
+    fn playing_items(&self) -> Vec<usize> {
+        if self.stop_tx.is_none() { return Vec::new(); }
+        let mut items: Vec<usize> = self.queue.current().into_iter().chain(self.committed.iter().copied()).collect();
+        // a switch the engine hasn't heard of yet
+        if self.next_committed() {
+            items.extend(self.pending_next.and_then(|p| self.queue.item_at(p)));
         }
+        items
     }
 
-    fn start_metrics_thread(
-        &mut self,
-        app: Option<tauri::AppHandle>,
-        sample_rate: u32,
-    ) {
+    /// Auto mode uses album gain when every queue item belongs to the same album.
+    fn update_queue_is_album(&mut self) {
+        self.queue_is_album = false;
+        if self.replay_gain_mode != ReplayGainMode::Auto || self.queue.len() < 2 { return; }
+        let albums = self.album_ids(self.queue.items());
+        if let Some(Some(first)) = albums.first() {
+            self.queue_is_album = albums.len() == self.queue.len() && albums.iter().all(|a| *a == Some(*first));
+        }
+    }
+
+    /// Library album of each path; `None` for files that aren't in the library or have no album.
+    /// Empty if the database is unavailable.
+    fn album_ids(&self, paths: &[String]) -> Vec<Option<i64>> {
+        let Some(pool) = self.app.as_ref().and_then(|a| a.try_state::<DbPool>()) else { return Vec::new() };
+        let Ok(conn) = pool.get() else { return Vec::new() };
+        paths.iter()
+            .map(|p| tracks::replay_gain_by_path(&conn, p).ok().flatten().and_then(|(_, album)| album))
+            .collect()
+    }
+
+    /// Albums of the queue items, looked up only when `mode` needs them.
+    fn album_ids_for(&self, mode: PlayMode) -> Vec<Option<i64>> {
+        self.album_ids_for_paths(mode, self.queue.items())
+    }
+
+    fn album_ids_for_paths(&self, mode: PlayMode, paths: &[String]) -> Vec<Option<i64>> {
+        if mode == PlayMode::AlbumShuffle { self.album_ids(paths) } else { Vec::new() }
+    }
+
+    /// The ring is being replaced: any boundary in it is meaningless now.
+    fn reset_boundary(&mut self) {
+        self.boundary.clear();
+        self.pending_next = None;
+        self.committed.clear();
+        self.crossings_seen = self.boundary.crossings.load(Ordering::Acquire);
+    }
+
+    /// Fade the output out ahead of pausing or dropping the stream. Returns once it is silent,
+    /// right away if nothing is playing, or after twice the fade time if the callback isn't
+    /// getting there.
+    fn fade_out(&self) {
+        self.fader.close();
+        let playing = PlaybackState::from(self.state.load(Ordering::Relaxed)) == PlaybackState::Playing;
+        if !playing || self.stream.is_none() || self.queued_samples.load(Ordering::Relaxed) == 0 { return; }
+        let limit = Duration::from_millis(self.fader.fade_ms() as u64 * 2 + 50);
+        let start = Instant::now();
+        while !self.fader.silent() && start.elapsed() < limit {
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    /// Give the decoder a head start of the profile's prebuffer (or until it has had
+    /// [`PREBUFFER_TIMEOUT`]).
+    fn wait_prebuffer(&self) {
+        let target = ms_to_samples(self.decoder_settings.latency.prebuffer_ms(), self.out_sr, self.out_ch);
+        let start = Instant::now();
+        while self.queued_samples.load(Ordering::Relaxed) < target && start.elapsed() < PREBUFFER_TIMEOUT {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// Reopen the stream with the current settings. The current track, if any, resumes where
+    /// it was (paused or playing as before).
+    fn restart_output(&mut self) -> anyhow::Result<()> {
+        match PlaybackState::from(self.state.load(Ordering::Relaxed)) {
+            PlaybackState::Playing | PlaybackState::Paused if self.queue.current().is_some() => {
+                let position = self.frames_played.load(Ordering::Relaxed) as f64 / self.out_sr as f64;
+                self.reload(position)
+            }
+            _ => {
+                self.stream = None;
+                self.queued_samples.store(0, Ordering::Relaxed);
+                self.reset_boundary();
+                self.rebuild_output()
+            }
+        }
+    }
+
+    /// Fresh ring + output stream on the current device. The stream starts paused.
+    ///
+    /// The device buffer comes from the latency profile, clamped to what the device reports it
+    /// supports; if the device still refuses it, it gets to pick its own.
+    fn rebuild_output(&mut self) -> anyhow::Result<()> {
+        let latency = self.decoder_settings.latency;
+        let period = latency.period_frames();
+        let built = match self.open_stream(Some(period)) {
+            Ok(built) => built,
+            Err(e) => {
+                log::warn!("output with a {period}-frame buffer failed ({e}), using the device default");
+                self.open_stream(None)?
+            }
+        };
+        let BuiltOutput { stream, sample_rate, channels, sample_format, bit_perfect, buffer_frames } = built;
+        self.stream_error.store(false, Ordering::Release);
+        if sample_rate != self.out_sr {
+            // durations are counted in output frames
+            let d = self.duration_frames.load(Ordering::Relaxed);
+            self.duration_frames.store(d * sample_rate as u64 / self.out_sr.max(1) as u64, Ordering::Relaxed);
+        }
+        self.out_sr_atomic.store(sample_rate, Ordering::Relaxed);
+        self.out_sr = sample_rate;
+        self.out_ch = channels;
+        self.stream = Some(stream);
+        self.decoder_settings.bit_perfect = bit_perfect;
+
+        let format = FormatEvent { sample_rate, channels, sample_format: sample_format.to_string(), bit_perfect };
+        if self.last_format.as_ref() != Some(&format) {
+            if let Some(app) = &self.app { let _ = app.emit("audio:format", format.clone()); }
+            self.last_format = Some(format);
+        }
+
+        let mut current = self.latency_report.lock().unwrap();
+        let report = LatencyReport {
+            profile: latency,
+            sample_rate,
+            buffer_frames,
+            buffer_ms: buffer_frames.map(|f| f as f64 * 1000.0 / sample_rate as f64),
+            prebuffer_ms: latency.prebuffer_ms(),
+            read_ahead_ms: latency.read_ahead_ms(),
+            device_ms: None,
+            seek_ms: current.seek_ms,
+        };
+        if *current != report {
+            if let Some(app) = &self.app { let _ = app.emit("audio:latency", report.clone()); }
+            *current = report;
+        }
+        Ok(())
+    }
+
+    /// New ring sized for the profile's read-ahead, and a stream reading from it. A seek still
+    /// being flushed from the old ring is forgotten.
+    fn open_stream(&mut self, buffer_frames: Option<u32>) -> anyhow::Result<BuiltOutput> {
+        self.seek_flush.settle();
+        self.seek_pending = None;
+        // room for twice the read-ahead at the rate the stream is likely to open at
+        let (sr, ch) = self.bit_perfect_format.map_or((self.out_sr, self.out_ch), |(sr, ch, _)| (sr, ch));
+        let ms = self.decoder_settings.latency.read_ahead_ms() * 2;
+        let (prod, cons, _cap) = make_audio_ring(ms_to_samples(ms, sr.max(96_000), ch.max(2)));
+        self.prod = Some(prod);
+        self.device_latency_us.store(0, Ordering::Relaxed);
+
+        build_output_stream(
+            &self.device,
+            StreamRequest {
+                bit_perfect: self.bit_perfect_format.map(|(sr, ch, _)| (sr, ch)),
+                source_depth: self.bit_perfect_format.and_then(|(_, _, depth)| depth),
+                buffer_frames,
+            },
+            cons,
+            Arc::clone(&self.vol_bits),
+            Arc::clone(&self.state),
+            Arc::clone(&self.frames_played),
+            Arc::clone(&self.peak_l_bits),
+            Arc::clone(&self.peak_r_bits),
+            Arc::clone(&self.rms_bits),
+            self.queued_samples,
+            Arc::clone(&self.boundary),
+            Arc::clone(&self.stream_error),
+            Arc::clone(&self.device_latency_us),
+            Arc::clone(&self.noise_shaping),
+            Arc::clone(&self.seek_flush),
+            Arc::clone(&self.fader),
+            Arc::clone(&self.playing_speed),
+        )
+    }
+
+    /// In bit-perfect mode the stream follows track `idx`'s format. Returns whether that differs
+    /// from what the current stream was opened for.
+    fn negotiate_format(&mut self, idx: usize) -> bool {
+        let wanted = if self.bit_perfect {
+            match Source::open(self.queue.path(idx)) {
+                Ok(s) => Some((s.sample_rate, s.channels as u16, s.depth)),
+                Err(e) => { log::warn!("bit-perfect: cannot probe {}: {e}", self.queue.path(idx)); None }
+            }
+        } else {
+            None
+        };
+        let changed = wanted != self.bit_perfect_format;
+        self.bit_perfect_format = wanted;
+        changed
+    }
+
+    /// Manual Next: never repeats the current track, stops nowhere (no-op at the end).
+    fn advance(&mut self) -> anyhow::Result<()> {
+        match self.queue.next_pos(false) {
+            Some(p) => self.jump_to(p),
+            None => Ok(()),
+        }
+    }
+
+    /// Prev: back along the play order, so shuffle retraces what was played.
+    fn advance_back(&mut self) -> anyhow::Result<()> {
+        match self.queue.prev_pos() {
+            Some(p) => self.jump_to(p),
+            None => Ok(()),
+        }
+    }
+
+    /// Restart playback at play-order position `pos`.
+    fn jump_to(&mut self, pos: usize) -> anyhow::Result<()> {
+        self.queue.set_pos(pos);
+        self.fade_out();
+        self.halt();
+        self.duration_frames.store(0, Ordering::Relaxed);
+        if let Some(i) = self.queue.current() { self.kick_duration_scan(self.queue.path(i).to_string()); }
+        self.play()
+    }
+
+    fn start_metrics_thread(&mut self, app: Option<tauri::AppHandle>) {
         let frames = Arc::clone(&self.frames_played);
+        let sample_rate = Arc::clone(&self.out_sr_atomic);
         let peak_l = Arc::clone(&self.peak_l_bits);
         let peak_r = Arc::clone(&self.peak_r_bits);
 
         self.metrics_thread = Some(std::thread::spawn(move || {
             loop {
-                let pos = frames.load(Ordering::Relaxed) as f64 / sample_rate as f64;
+                let sr = sample_rate.load(Ordering::Relaxed).max(1);
+                let pos = frames.load(Ordering::Relaxed) as f64 / sr as f64;
                 if let Some(app) = &app {
                     let _ = app.emit("audio:position", PositionEvent { seconds: pos });
                 }
@@ -453,10 +1207,31 @@ impl AudioEngine {
 
     fn emit_state(&self, s: &'static str) { if let Some(app) = &self.app { let _ = app.emit("audio:state", StateEvent { state: s }); } }
 
+    fn emit_track(&self) {
+        let Some(index) = self.queue.current() else { return };
+        self.queue_state.lock().unwrap().current = Some(index);
+        if let (Some(app), Some(path)) = (&self.app, self.queue.items().get(index)) {
+            let _ = app.emit("audio:track", TrackEvent { index, path: path.clone() });
+        }
+        if self.remember_speed { self.emit_speed(index); }
+    }
+
+    /// The speed queue item `idx` plays at (`audio:speed`).
+    fn emit_speed(&self, idx: usize) {
+        if let Some(app) = &self.app { let _ = app.emit("audio:speed", SpeedEvent { speed: self.track_speed(idx) }); }
+    }
+
+    fn emit_queue(&mut self) {
+        self.session_dirty = true;
+        let state = self.queue.state();
+        *self.queue_state.lock().unwrap() = state.clone();
+        if let Some(app) = &self.app { let _ = app.emit("audio:queue", state); }
+    }
+
     fn kick_duration_scan(&self, path: String) {
-        let dur = self.duration_frames.clone(); let app = self.app.clone(); let sr = self.out_sr;
+        let dur = self.duration_frames.clone(); let app = self.app.clone(); let sr = Arc::clone(&self.out_sr_atomic);
         thread::spawn(move || {
-            if let Ok(seconds) = precise_duration_seconds(&path) { dur.store((seconds * sr as f64) as u64, Ordering::Relaxed); if let Some(app) = app { let _ = app.emit("audio:duration", DurationEvent { seconds }); } }
+            if let Ok(seconds) = precise_duration_seconds(&path) { dur.store((seconds * sr.load(Ordering::Relaxed) as f64) as u64, Ordering::Relaxed); if let Some(app) = app { let _ = app.emit("audio:duration", DurationEvent { seconds }); } }
         });
     }
 }
@@ -465,49 +1240,5 @@ impl Drop for AudioEngine { fn drop(&mut self) { self.stop(); } }
 
 // ---- Precise duration scan (packet timestamps fall-back) ----
 fn precise_duration_seconds(file_path: &str) -> anyhow::Result<f64> {
-    use symphonia::core::io::MediaSourceStream;
-    use symphonia::core::probe::Hint;
-    use symphonia::core::formats::FormatOptions;
-    use symphonia::core::meta::MetadataOptions;
-    use symphonia::core::errors::Error;
-    use std::fs::File;
-    use std::path::Path;
-
-    let file = Box::new(File::open(file_path)?);
-    let mss = MediaSourceStream::new(file, Default::default());
-    let mut hint = Hint::new();
-    if let Some(ext) = Path::new(file_path).extension().and_then(|e| e.to_str()) {
-        hint.with_extension(ext);
-    }
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())?;
-    let mut format = probed.format;
-
-    let track = format.tracks()
-        .iter()
-        .find(|t| t.codec_params.codec != symphonia::core::codecs::CODEC_TYPE_NULL)
-        .ok_or_else(|| anyhow::anyhow!("No supported audio tracks"))?;
-
-    let track_id   = track.id;
-    let time_base  = track.codec_params.time_base;
-    let n_frames   = track.codec_params.n_frames;
-    drop(track); // release immutable borrow before next_packet()
-
-    if let (Some(tb), Some(nf)) = (time_base, n_frames) {
-        return Ok((nf as f64) * (tb.numer as f64) / (tb.denom as f64));
-    }
-
-    let tb = time_base.ok_or_else(|| anyhow::anyhow!("no time_base"))?;
-    let mut last_ts = 0u64;
-    loop {
-        match format.next_packet() {
-            Ok(p) => {
-                if p.track_id() != track_id { continue; }
-                last_ts = last_ts.max(p.ts());
-            }
-            Err(Error::ResetRequired) => continue,
-            Err(_) => break, // EOF
-        }
-    }
-    Ok((last_ts as f64) * (tb.numer as f64) / (tb.denom as f64))
+    Source::open(file_path)?.duration_seconds()
 }
@@ -0,0 +1,198 @@
+//! The output's gain stage: volume and transport fades, ramped per sample so neither a volume
+//! change nor a pause cuts the waveform mid-cycle (clicks) or steps it once per block (zipper
+//! noise).
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Range of the volume slider: its bottom end sits this far below full scale, then mutes.
+const VOLUME_RANGE_DB: f32 = 60.0;
+/// How long a volume change takes to settle.
+pub const VOLUME_RAMP_MS: u32 = 20;
+/// Default length of the fades on pause, resume, stop and skip.
+pub const DEFAULT_FADE_MS: u32 = 30;
+pub const MAX_FADE_MS: u32 = 500;
+
+/// Linear gain for a volume slider position in `0.0..=1.0`: even steps in dB, so the slider
+/// sounds even along its length, and silence at 0.
+pub fn volume_gain(position: f32) -> f32 {
+    if position <= 0.0 { return 0.0; }
+    let db = (position.min(1.0) - 1.0) * VOLUME_RANGE_DB;
+    10f32.powf(db / 20.0)
+}
+
+/// A gain that moves linearly to its target over a given number of frames.
+#[derive(Debug, Clone, Copy)]
+pub struct Ramp {
+    current: f32,
+    target: f32,
+    step: f32,
+    /// Frames until `target`; the last one lands on it exactly, whatever the rounding.
+    left: usize,
+}
+
+impl Ramp {
+    pub fn new(value: f32) -> Self { Self { current: value, target: value, step: 0.0, left: 0 } }
+
+    pub fn current(&self) -> f32 { self.current }
+
+    pub fn settled(&self) -> bool { self.current == self.target }
+
+    /// Head for `target`, getting there in `frames` (at once for 0).
+    pub fn set(&mut self, target: f32, frames: usize) {
+        if target == self.target { return; }
+        self.target = target;
+        if frames == 0 { *self = Self::new(target); }
+        else { self.step = (target - self.current) / frames as f32; self.left = frames; }
+    }
+
+    /// Jump to `value`.
+    pub fn snap(&mut self, value: f32) { *self = Self::new(value); }
+
+    /// Gain for the next frame.
+    pub fn next(&mut self) -> f32 {
+        if self.current != self.target {
+            self.left = self.left.saturating_sub(1);
+            self.current += self.step;
+            let past = if self.step > 0.0 { self.current >= self.target } else { self.current <= self.target };
+            if past || self.left == 0 { self.current = self.target; }
+        }
+        self.current
+    }
+}
+
+/// Fades the output in and out around pause, resume, stop and skip.
+///
+/// The engine closes it and waits for `silent` before pausing or dropping the stream, and opens
+/// it again on resume; the output callback ramps towards open (1) or closed (0) over `fade_ms`.
+pub struct Fader {
+    open: AtomicBool,
+    /// Take the next target without a ramp (a track starting from its first sample).
+    snap: AtomicBool,
+    /// Set by the callback once closed and fully faded out.
+    silent: AtomicBool,
+    fade_ms: AtomicU32,
+}
+
+impl Fader {
+    pub fn new() -> Self {
+        Self {
+            open: AtomicBool::new(false),
+            snap: AtomicBool::new(false),
+            silent: AtomicBool::new(true),
+            fade_ms: AtomicU32::new(DEFAULT_FADE_MS),
+        }
+    }
+
+    pub fn fade_ms(&self) -> u32 { self.fade_ms.load(Ordering::Relaxed) }
+
+    pub fn set_fade_ms(&self, ms: u32) { self.fade_ms.store(ms.min(MAX_FADE_MS), Ordering::Relaxed); }
+
+    /// Fade in (from wherever the level is).
+    pub fn open(&self) {
+        self.silent.store(false, Ordering::Relaxed);
+        self.open.store(true, Ordering::Release);
+    }
+
+    /// Start at full level, without fading in.
+    pub fn open_at_once(&self) {
+        self.snap.store(true, Ordering::Relaxed);
+        self.open();
+    }
+
+    /// Fade out; [`silent`](Self::silent) once done.
+    pub fn close(&self) {
+        self.silent.store(false, Ordering::Relaxed);
+        self.open.store(false, Ordering::Release);
+    }
+
+    pub fn silent(&self) -> bool { self.silent.load(Ordering::Acquire) }
+
+    // output callback side
+
+    pub fn is_open(&self) -> bool { self.open.load(Ordering::Acquire) }
+
+    pub fn take_snap(&self) -> bool { self.snap.swap(false, Ordering::Relaxed) }
+
+    pub fn set_silent(&self) { self.silent.store(true, Ordering::Release); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(ramp: &mut Ramp, frames: usize) -> Vec<f32> { (0..frames).map(|_| ramp.next()).collect() }
+
+    #[test]
+    fn volume_curve_end_points() {
+        assert_eq!(volume_gain(0.0), 0.0, "bottom of the slider mutes");
+        assert_eq!(volume_gain(-0.5), 0.0);
+        assert_eq!(volume_gain(1.0), 1.0, "top is 0 dB");
+        assert_eq!(volume_gain(2.0), 1.0);
+        // just above the bottom is the end of the dB range, not silence
+        let db = 20.0 * volume_gain(1e-6).log10();
+        assert!((db + VOLUME_RANGE_DB).abs() < 0.01, "{db}");
+        // even steps in dB
+        assert!((20.0 * volume_gain(0.5).log10() + VOLUME_RANGE_DB / 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn volume_curve_is_monotonic() {
+        let gains: Vec<f32> = (0..=1000).map(|k| volume_gain(k as f32 / 1000.0)).collect();
+        assert!(gains.windows(2).all(|w| w[0] < w[1]), "strictly increasing");
+    }
+
+    #[test]
+    fn ramp_takes_exactly_its_length() {
+        let mut ramp = Ramp::new(0.0);
+        ramp.set(1.0, 4);
+        assert_eq!(run(&mut ramp, 5), [0.25, 0.5, 0.75, 1.0, 1.0]);
+        assert!(ramp.settled());
+
+        // lengths whose steps don't add up exactly in f32 still land on time
+        for (from, to) in [(0.0, 1.0), (1.0, 0.0), (1.0, 0.1), (0.3, 0.7)] {
+            for frames in 1..500 {
+                let mut ramp = Ramp::new(from);
+                ramp.set(to, frames);
+                let out = run(&mut ramp, frames);
+                assert!(out[..frames - 1].iter().all(|&g| g != to), "{from}->{to} in {frames}: early");
+                assert_eq!(out[frames - 1], to, "{from}->{to} in {frames}: late");
+            }
+        }
+    }
+
+    #[test]
+    fn ramp_never_overshoots() {
+        for (from, to) in [(0.0, 1.0), (1.0, 0.0), (0.5, 0.5000001)] {
+            let mut ramp = Ramp::new(from);
+            ramp.set(to, 7);
+            let (lo, hi) = if from < to { (from, to) } else { (to, from) };
+            assert!(run(&mut ramp, 20).iter().all(|&g| (lo..=hi).contains(&g)), "{from}->{to}");
+            assert_eq!(ramp.current(), to);
+        }
+    }
+
+    #[test]
+    fn ramp_retargets_and_snaps() {
+        let mut ramp = Ramp::new(0.0);
+        ramp.set(1.0, 4);
+        run(&mut ramp, 2);
+        // turning back mid-ramp starts from where it is
+        ramp.set(0.0, 2);
+        assert_eq!(run(&mut ramp, 3), [0.25, 0.0, 0.0]);
+
+        // the same target again doesn't restart the ramp
+        ramp.set(1.0, 4);
+        run(&mut ramp, 3);
+        ramp.set(1.0, 100);
+        assert_eq!(ramp.next(), 1.0);
+
+        // 0 frames, or a snap, jumps
+        ramp.set(0.5, 0);
+        assert!(ramp.settled() && ramp.current() == 0.5);
+        ramp.set(0.0, 10);
+        ramp.next();
+        ramp.snap(1.0);
+        assert!(ramp.settled());
+        assert_eq!(ramp.next(), 1.0);
+    }
+}
@@ -0,0 +1,86 @@
+//! Watches the host's output devices and tells the runtime when they change.
+//!
+//! cpal has no portable device-change notification, so the device list and the default device
+//! are polled. The engine decides whether a change affects the stream it is playing on.
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+
+use super::runtime::Cmd;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Current default output device and the names of all output devices.
+///
+/// The device in use (`active`) counts as present while it is still there: ALSA leaves a hw
+/// device it can't open because we hold it out of the list, so its card is looked up instead.
+fn snapshot(host: &cpal::Host, active: &Mutex<Option<String>>) -> (Option<String>, Vec<String>) {
+    let default = host.default_output_device().and_then(|d| d.name().ok());
+    let mut devices: Vec<String> = host
+        .output_devices()
+        .map(|it| it.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default();
+    if let Some(active) = active.lock().unwrap().clone() {
+        if !devices.contains(&active) && held_but_present(&active) { devices.push(active); }
+    }
+    devices.sort();
+    (default, devices)
+}
+
+/// Whether `name`, missing from the output devices, is a sound card that is still plugged in.
+#[cfg(target_os = "linux")]
+fn held_but_present(name: &str) -> bool {
+    std::fs::read_to_string("/proc/asound/cards").is_ok_and(|cards| card_names(&cards).any(|card| card == name))
+}
+
+/// Other hosts list a device whether or not it is in use.
+#[cfg(not(target_os = "linux"))]
+fn held_but_present(_name: &str) -> bool { false }
+
+/// Card names (what cpal calls the device) in `/proc/asound/cards`, whose entries look like
+/// ` 1 [DAC            ]: USB-Audio - USB Audio DAC` followed by an indented long name.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn card_names(cards: &str) -> impl Iterator<Item = &str> {
+    cards
+        .lines()
+        .filter(|line| line.trim_start().starts_with(|c: char| c.is_ascii_digit()))
+        .filter_map(|line| line.split_once("]: ")?.1.split_once(" - ").map(|(_, name)| name.trim()))
+}
+
+/// Poll until the runtime goes away, sending [`Cmd::DevicesChanged`] on every change.
+/// `active` is the name of the device the stream is playing on.
+pub fn start_monitor(tx: mpsc::Sender<Cmd>, active: Arc<Mutex<Option<String>>>) {
+    let spawned = thread::Builder::new().name("audio-hotplug".into()).spawn(move || {
+        let host = cpal::default_host();
+        let mut last = snapshot(&host, &active);
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let cur = snapshot(&host, &active);
+            if cur == last { continue; }
+            last = cur.clone();
+            let (default, devices) = cur;
+            if tx.send(Cmd::DevicesChanged { default, devices }).is_err() { break; }
+        }
+    });
+    if let Err(e) = spawned { log::warn!("device monitor not started: {e}"); }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_names_from_proc() {
+        let cards = concat!(
+            " 0 [PCH            ]: HDA-Intel - HDA Intel PCH\n",
+            "                      HDA Intel PCH at 0xf7f10000 irq 33\n",
+            " 1 [DAC            ]: USB-Audio - USB Audio DAC\n",
+            "                      Some Vendor USB Audio DAC at usb-0000:00:14.0-2, high speed\n",
+        );
+        assert_eq!(card_names(cards).collect::<Vec<_>>(), ["HDA Intel PCH", "USB Audio DAC"]);
+        assert_eq!(card_names("--- no soundcards ---\n").count(), 0);
+    }
+}
@@ -0,0 +1,109 @@
+//! Latency profiles: device buffer size, how much is buffered before playback starts, and how
+//! far the decoder may run ahead of the output.
+//!
+//! Smaller values make starting, seeking and skipping feel immediate but leave less room for
+//! the decoder (or a busy system) to fall behind before the output underruns.
+
+use serde::{Deserialize, Serialize};
+
+/// Device buffer sizes are clamped to this range before the device's own limits apply.
+const MIN_PERIOD_FRAMES: u32 = 64;
+const MAX_PERIOD_FRAMES: u32 = 16_384;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyProfile {
+    /// 256-frame device buffer, 50 ms prebuffer, 250 ms read-ahead.
+    Low,
+    /// 1024-frame device buffer, 250 ms prebuffer, 1 s read-ahead.
+    Balanced,
+    /// 4096-frame device buffer, 1 s prebuffer, 2 s read-ahead.
+    #[default]
+    Safe,
+    /// Device buffer of `frames`; prebuffer and read-ahead scale with it.
+    Custom { frames: u32 },
+}
+
+impl LatencyProfile {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Balanced => "balanced",
+            Self::Safe => "safe",
+            Self::Custom { .. } => "custom",
+        }
+    }
+
+    /// `frames` is only used for `custom`.
+    pub fn parse(s: &str, frames: Option<u32>) -> Option<Self> {
+        match s {
+            "low" => Some(Self::Low),
+            "balanced" => Some(Self::Balanced),
+            "safe" => Some(Self::Safe),
+            "custom" => frames.map(|frames| Self::Custom { frames }),
+            _ => None,
+        }
+    }
+
+    pub fn validate(self) -> Result<Self, String> {
+        match self {
+            Self::Custom { frames } if !(MIN_PERIOD_FRAMES..=MAX_PERIOD_FRAMES).contains(&frames) => {
+                Err(format!("buffer size must be {MIN_PERIOD_FRAMES}..={MAX_PERIOD_FRAMES} frames"))
+            }
+            p => Ok(p),
+        }
+    }
+
+    /// Requested device buffer in frames.
+    pub fn period_frames(self) -> u32 {
+        match self {
+            Self::Low => 256,
+            Self::Balanced => 1024,
+            Self::Safe => 4096,
+            Self::Custom { frames } => frames.clamp(MIN_PERIOD_FRAMES, MAX_PERIOD_FRAMES),
+        }
+    }
+
+    /// Audio buffered before the stream starts (play, seek, skip).
+    pub fn prebuffer_ms(self) -> u32 {
+        match self {
+            Self::Low => 50,
+            Self::Balanced => 250,
+            Self::Safe => 1000,
+            // a few device periods at 48 kHz, at least 50 ms
+            Self::Custom { frames } => (frames * 4 / 48).max(50),
+        }
+    }
+
+    /// How far the decoder runs ahead of the output.
+    pub fn read_ahead_ms(self) -> u32 {
+        match self {
+            Self::Low => 250,
+            Self::Balanced => 1000,
+            Self::Safe => 2000,
+            Self::Custom { .. } => (self.prebuffer_ms() * 4).clamp(250, 2000),
+        }
+    }
+}
+
+/// Milliseconds of interleaved audio as a sample count.
+pub fn ms_to_samples(ms: u32, sample_rate: u32, channels: u16) -> usize {
+    sample_rate as usize * channels as usize * ms as usize / 1000
+}
+
+/// What the output is actually running with (`get_output_latency`, `audio:latency`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LatencyReport {
+    pub profile: LatencyProfile,
+    pub sample_rate: u32,
+    /// Device buffer in frames; `None` when the device picked its own.
+    pub buffer_frames: Option<u32>,
+    pub buffer_ms: Option<f64>,
+    pub prebuffer_ms: u32,
+    pub read_ahead_ms: u32,
+    /// Callback-to-playback delay as reported by the device, once the stream has run.
+    pub device_ms: Option<f64>,
+    /// Time from the last in-place seek until the output callback wrote its first new sample;
+    /// add `device_ms` for when it is heard.
+    pub seek_ms: Option<f64>,
+}
@@ -0,0 +1,386 @@
+//! EBU R128 / ITU-R BS.1770-4 loudness measurement.
+//!
+//! Samples are K-weighted and summed per channel into 100 ms sub-blocks. 400 ms momentary
+//! blocks (75 % overlap) feed the integrated loudness with its -70 LUFS absolute and -10 LU
+//! relative gates; 3 s short-term blocks feed the loudness range (EBU Tech 3342). True peak is
+//! taken from a 4x oversampled signal (2x at 96 kHz and up, none at 192 kHz).
+//!
+//! Gated blocks are kept as 0.1 LU histograms rather than lists, so that the measurements of
+//! several tracks can be merged into an album value without decoding them again.
+
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use super::dsp::Biquad;
+
+const ABS_GATE_LUFS: f64 = -70.0;
+const HIST_MAX_LUFS: f64 = 5.0;
+const HIST_STEP: f64 = 0.1;
+const HIST_BINS: usize = ((HIST_MAX_LUFS - ABS_GATE_LUFS) / HIST_STEP) as usize;
+
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+const SHORT_TERM_SUB_BLOCKS: usize = 30;
+
+/// Taps per polyphase branch of the true-peak interpolator.
+const TRUE_PEAK_TAPS: usize = 12;
+
+fn energy_to_lufs(e: f64) -> f64 { -0.691 + 10.0 * e.log10() }
+fn lufs_to_energy(l: f64) -> f64 { 10f64.powf((l + 0.691) / 10.0) }
+
+/// Count of gated blocks per 0.1 LU between -70 and +5 LUFS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram { counts: Vec<u32> }
+
+impl Default for Histogram {
+    fn default() -> Self { Self { counts: vec![0; HIST_BINS] } }
+}
+
+impl Histogram {
+    fn add(&mut self, energy: f64) {
+        let l = energy_to_lufs(energy);
+        if l.is_nan() || l < ABS_GATE_LUFS { return; }
+        let i = ((l - ABS_GATE_LUFS) / HIST_STEP) as usize;
+        self.counts[i.min(HIST_BINS - 1)] += 1;
+    }
+
+    fn bin_lufs(i: usize) -> f64 { ABS_GATE_LUFS + (i as f64 + 0.5) * HIST_STEP }
+
+    /// First bin whose centre is at or above `lufs`.
+    fn bin_at(lufs: f64) -> usize {
+        (((lufs - ABS_GATE_LUFS) / HIST_STEP - 0.5).ceil().max(0.0) as usize).min(HIST_BINS)
+    }
+
+    /// Mean loudness (energy domain) of the blocks in bins `from..`, with their count.
+    fn mean_from(&self, from: usize) -> Option<(f64, u64)> {
+        let (mut n, mut e) = (0u64, 0.0);
+        for (i, &c) in self.counts.iter().enumerate().skip(from) {
+            n += c as u64;
+            e += c as f64 * lufs_to_energy(Self::bin_lufs(i));
+        }
+        (n > 0).then(|| (energy_to_lufs(e / n as f64), n))
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) { *a += b; }
+    }
+
+    /// Gated integrated loudness in LUFS; `None` if nothing is above the absolute gate.
+    pub fn integrated(&self) -> Option<f64> {
+        let (ungated, _) = self.mean_from(0)?;
+        self.mean_from(Self::bin_at(ungated - 10.0)).map(|(l, _)| l)
+    }
+
+    /// Loudness range in LU: spread between the 10th and 95th percentile of the short-term
+    /// loudness after a -20 LU relative gate.
+    pub fn range(&self) -> Option<f64> {
+        let (ungated, _) = self.mean_from(0)?;
+        let from = Self::bin_at(ungated - 20.0);
+        let (_, n) = self.mean_from(from)?;
+        let percentile = |p: f64| {
+            let rank = (p * (n - 1) as f64).round() as u64;
+            let mut seen = 0u64;
+            for (i, &c) in self.counts.iter().enumerate().skip(from) {
+                seen += c as u64;
+                if seen > rank { return Self::bin_lufs(i); }
+            }
+            Self::bin_lufs(HIST_BINS - 1)
+        };
+        Some(percentile(0.95) - percentile(0.10))
+    }
+
+    /// Sparse little-endian `(bin: u16, count: u32)` pairs, for storage.
+    pub fn to_blob(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (i, &c) in self.counts.iter().enumerate().filter(|(_, c)| **c > 0) {
+            out.extend_from_slice(&(i as u16).to_le_bytes());
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_blob(blob: &[u8]) -> Self {
+        let mut h = Self::default();
+        for pair in blob.chunks_exact(6) {
+            let i = u16::from_le_bytes([pair[0], pair[1]]) as usize;
+            let c = u32::from_le_bytes([pair[2], pair[3], pair[4], pair[5]]);
+            if let Some(slot) = h.counts.get_mut(i) { *slot += c; }
+        }
+        h
+    }
+}
+
+/// BS.1770 K-weighting (high-shelf "pre-filter" followed by the RLB high-pass), derived for
+/// any sample rate from the analog prototypes behind the 48 kHz coefficients in the standard.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let sr = sample_rate as f64;
+
+    let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+    let k = (PI * f0 / sr).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = Biquad::new(
+        [vh + vb * k / q + k * k, 2.0 * (k * k - vh), vh - vb * k / q + k * k],
+        [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+
+    let (f0, q) = (38.13547087602444, 0.5003270373238773);
+    let k = (PI * f0 / sr).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad::new(
+        [a0, -2.0 * a0, a0],
+        [a0, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+    );
+
+    [shelf, highpass]
+}
+
+/// BS.1770 channel weight, assuming the usual WAVE/Vorbis channel order: LFE is ignored and
+/// surround channels count +1.5 dB.
+fn channel_weight(idx: usize, channels: usize) -> f64 {
+    match (channels, idx) {
+        (4, 2 | 3) | (5, 3 | 4) => 1.41,
+        (c, 3) if c >= 6 => 0.0,
+        (c, i) if c >= 6 && i >= 4 => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// Polyphase interpolator used to estimate inter-sample peaks.
+struct TruePeak {
+    /// `phases[p][k]`: tap `k` of branch `p`.
+    phases: Vec<[f64; TRUE_PEAK_TAPS]>,
+    /// Last `TRUE_PEAK_TAPS` input samples per channel, newest first.
+    history: Vec<[f64; TRUE_PEAK_TAPS]>,
+    peak: f64,
+}
+
+impl TruePeak {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let factor = if sample_rate < 96_000 { 4 } else if sample_rate < 192_000 { 2 } else { 1 };
+        let len = factor * TRUE_PEAK_TAPS;
+        let centre = (len - 1) as f64 / 2.0;
+        let mut phases = vec![[0.0; TRUE_PEAK_TAPS]; factor];
+        for n in 0..len {
+            let x = (n as f64 - centre) / factor as f64;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let window = 0.5 - 0.5 * (2.0 * PI * (n as f64 + 0.5) / len as f64).cos();
+            phases[n % factor][n / factor] = sinc * window;
+        }
+        // unity DC gain per branch
+        for p in &mut phases {
+            let sum: f64 = p.iter().sum();
+            p.iter_mut().for_each(|c| *c /= sum);
+        }
+        Self { phases, history: vec![[0.0; TRUE_PEAK_TAPS]; channels], peak: 0.0 }
+    }
+
+    #[inline]
+    fn push(&mut self, ch: usize, x: f64) {
+        let h = &mut self.history[ch];
+        h.copy_within(0..TRUE_PEAK_TAPS - 1, 1);
+        h[0] = x;
+        self.peak = self.peak.max(x.abs());
+        if self.phases.len() == 1 { return; }
+        for p in &self.phases {
+            let y: f64 = p.iter().zip(h.iter()).map(|(c, s)| c * s).sum();
+            self.peak = self.peak.max(y.abs());
+        }
+    }
+}
+
+/// Result of measuring one track (or, merged, an album).
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    /// Integrated loudness in LUFS; `None` for silence.
+    pub integrated: Option<f64>,
+    /// Loudness range in LU; `None` when shorter than one short-term block.
+    pub range: Option<f64>,
+    /// Linear true peak, 1.0 = 0 dBTP.
+    pub true_peak: f64,
+    pub momentary: Histogram,
+    pub short_term: Histogram,
+}
+
+impl Measurement {
+    /// Recompute loudness and range from the histograms (after merging several tracks).
+    pub fn from_histograms(momentary: Histogram, short_term: Histogram, true_peak: f64) -> Self {
+        Self { integrated: momentary.integrated(), range: short_term.range(), true_peak, momentary, short_term }
+    }
+}
+
+/// Feed interleaved f32 at a fixed rate and channel count, then [`finish`](Self::finish).
+pub struct LoudnessMeter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    sub_block_len: usize,
+    /// Frames and weighted sum of squares in the current sub-block.
+    sub_frames: usize,
+    sub_sum: f64,
+    /// Mean-square energy of the most recent sub-blocks.
+    recent: VecDeque<f64>,
+    momentary: Histogram,
+    short_term: Histogram,
+    true_peak: TruePeak,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        Self {
+            channels,
+            weights: (0..channels).map(|c| channel_weight(c, channels)).collect(),
+            filters: vec![k_weighting(sample_rate); channels],
+            sub_block_len: (sample_rate as usize / 10).max(1),
+            sub_frames: 0,
+            sub_sum: 0.0,
+            recent: VecDeque::with_capacity(SHORT_TERM_SUB_BLOCKS),
+            momentary: Histogram::default(),
+            short_term: Histogram::default(),
+            true_peak: TruePeak::new(sample_rate, channels),
+        }
+    }
+
+    pub fn add(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (c, &s) in frame.iter().enumerate() {
+                let x = s as f64;
+                self.true_peak.push(c, x);
+                let [shelf, highpass] = &mut self.filters[c];
+                let y = highpass.process(shelf.process(x));
+                self.sub_sum += self.weights[c] * y * y;
+            }
+            self.sub_frames += 1;
+            if self.sub_frames == self.sub_block_len { self.end_sub_block(); }
+        }
+    }
+
+    fn end_sub_block(&mut self) {
+        if self.recent.len() == SHORT_TERM_SUB_BLOCKS { self.recent.pop_front(); }
+        self.recent.push_back(self.sub_sum / self.sub_frames as f64);
+        self.sub_sum = 0.0;
+        self.sub_frames = 0;
+
+        let n = self.recent.len();
+        if n >= MOMENTARY_SUB_BLOCKS {
+            let e: f64 = self.recent.range(n - MOMENTARY_SUB_BLOCKS..).sum();
+            self.momentary.add(e / MOMENTARY_SUB_BLOCKS as f64);
+        }
+        if n == SHORT_TERM_SUB_BLOCKS {
+            let e: f64 = self.recent.iter().sum();
+            self.short_term.add(e / SHORT_TERM_SUB_BLOCKS as f64);
+        }
+    }
+
+    /// The trailing partial sub-block is dropped, as the standard's block grid does.
+    pub fn finish(self) -> Measurement {
+        Measurement::from_histograms(self.momentary, self.short_term, self.true_peak.peak)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interleaved sine at `hz` and peak amplitude `dbfs`, identical on every channel.
+    fn sine(rate: u32, channels: usize, hz: f64, dbfs: f64, secs: f64, phase: f64) -> Vec<f32> {
+        let amp = 10f64.powf(dbfs / 20.0);
+        let frames = (rate as f64 * secs) as usize;
+        (0..frames)
+            .flat_map(|n| {
+                let x = (amp * (2.0 * PI * hz * n as f64 / rate as f64 + phase).sin()) as f32;
+                std::iter::repeat(x).take(channels)
+            })
+            .collect()
+    }
+
+    fn measure(rate: u32, channels: usize, blocks: &[Vec<f32>]) -> Measurement {
+        let mut meter = LoudnessMeter::new(rate, channels);
+        for b in blocks {
+            // uneven chunks, as the decoder hands them in
+            for chunk in b.chunks(channels * 1021) { meter.add(chunk); }
+        }
+        meter.finish()
+    }
+
+    #[test]
+    fn ebu_1khz_at_minus_23() {
+        // EBU Tech 3341 case 1: stereo 1 kHz at -23 dBFS reads -23 LUFS (±0.1 LU)
+        for rate in [44_100, 48_000, 96_000] {
+            let m = measure(rate, 2, &[sine(rate, 2, 1000.0, -23.0, 10.0, 0.0)]);
+            let i = m.integrated.unwrap();
+            assert!((i + 23.0).abs() < 0.1, "{rate} Hz: {i}");
+        }
+    }
+
+    #[test]
+    fn relative_gate_drops_quiet_passages() {
+        // after Tech 3341 case 3: -23 dBFS between passages at -36 dBFS still reads -23 LUFS
+        let rate = 48_000;
+        let quiet = sine(rate, 2, 1000.0, -36.0, 5.0, 0.0);
+        let m = measure(rate, 2, &[quiet.clone(), sine(rate, 2, 1000.0, -23.0, 20.0, 0.0), quiet]);
+        assert!((m.integrated.unwrap() + 23.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let m = measure(48_000, 2, &[vec![0.0; 48_000 * 2 * 5]]);
+        assert_eq!(m.integrated, None);
+        assert_eq!(m.range, None);
+        assert_eq!(m.true_peak, 0.0);
+    }
+
+    #[test]
+    fn loudness_range() {
+        // EBU Tech 3342 case 1: 20 s at -20 then 20 s at -30 dBFS has an LRA of 10 LU (±1)
+        let rate = 48_000;
+        let m = measure(rate, 2, &[sine(rate, 2, 1000.0, -20.0, 20.0, 0.0), sine(rate, 2, 1000.0, -30.0, 20.0, 0.0)]);
+        let lra = m.range.unwrap();
+        assert!((lra - 10.0).abs() < 1.0, "{lra}");
+
+        // a steady tone has next to none
+        let m = measure(rate, 2, &[sine(rate, 2, 1000.0, -20.0, 20.0, 0.0)]);
+        assert!(m.range.unwrap() < 0.2);
+    }
+
+    #[test]
+    fn true_peak_finds_inter_sample_peaks() {
+        // fs/4 at 45°: every sample is at -3 dB of the actual peak
+        let rate = 48_000;
+        let m = measure(rate, 1, &[sine(rate, 1, 12_000.0, -6.0, 1.0, PI / 4.0)]);
+        let peak_db = 20.0 * m.true_peak.log10();
+        // Tech 3341 tolerance: +0.2 / -0.4 dB
+        assert!((-6.4..=-5.8).contains(&peak_db), "{peak_db}");
+
+        let sample_peak = sine(rate, 1, 12_000.0, -6.0, 1.0, PI / 4.0).iter().fold(0f32, |a, s| a.max(s.abs()));
+        assert!(20.0 * (sample_peak as f64).log10() < -8.9);
+    }
+
+    #[test]
+    fn album_from_merged_histograms() {
+        let rate = 48_000;
+        let a = measure(rate, 2, &[sine(rate, 2, 1000.0, -20.0, 10.0, 0.0)]);
+        let b = measure(rate, 2, &[sine(rate, 2, 1000.0, -20.0, 10.0, 0.0)]);
+
+        let (mut momentary, mut short_term) = (a.momentary.clone(), a.short_term.clone());
+        momentary.merge(&Histogram::from_blob(&b.momentary.to_blob()));
+        short_term.merge(&b.short_term);
+        let album = Measurement::from_histograms(momentary, short_term, a.true_peak.max(b.true_peak));
+        assert!((album.integrated.unwrap() - a.integrated.unwrap()).abs() < 0.05);
+    }
+
+    #[test]
+    fn histogram_blob_round_trip() {
+        let m = measure(48_000, 2, &[sine(48_000, 2, 440.0, -14.0, 5.0, 0.0)]);
+        assert_eq!(Histogram::from_blob(&m.momentary.to_blob()), m.momentary);
+        assert!(Histogram::from_blob(&[]).integrated().is_none());
+    }
+
+    #[test]
+    fn surround_weights() {
+        assert_eq!(channel_weight(0, 2), 1.0);
+        assert_eq!(channel_weight(3, 6), 0.0, "LFE");
+        assert_eq!(channel_weight(4, 6), 1.41);
+        assert_eq!(channel_weight(2, 4), 1.41);
+    }
+}
@@ -3,6 +3,19 @@ pub mod decoder;
 pub mod output;
 pub mod buffer;
 pub mod runtime;
+pub mod crossfade;
+pub mod resample;
+pub mod replaygain;
+pub mod loudness;
+pub mod dsp;
+pub mod queue;
+pub mod device;
+pub mod hotplug;
+pub mod latency;
+pub mod quantize;
+pub mod channels;
+pub mod gain;
+pub mod stretch;
 
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
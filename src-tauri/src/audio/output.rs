@@ -1,21 +1,123 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use ringbuf::{HeapCons};
 use ringbuf::traits::Consumer;
-use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
 use log::error;
 
+use crate::audio::buffer::{SeekFlush, TrackBoundary};
+use crate::audio::gain::{volume_gain, Fader, Ramp, VOLUME_RAMP_MS};
+use crate::audio::quantize::{dither_for, OutputSample, Quantizer};
+
 pub struct BuiltOutput {
     pub stream: cpal::Stream,
     pub sample_rate: u32,
     pub channels: u16,
+    pub sample_format: cpal::SampleFormat,
+    /// The requested bit-perfect format was available (volume is not applied).
+    pub bit_perfect: bool,
+    /// Device buffer actually requested; `None` when left to the device.
+    pub buffer_frames: Option<u32>,
+}
+
+/// What the engine asks of a new stream.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamRequest {
+    /// Open at exactly this `(sample_rate, channels)` and pass samples through untouched.
+    pub bit_perfect: Option<(u32, u16)>,
+    /// How the bit-perfect source stores its samples: only formats that hold them exactly
+    /// count. `None` (lossy sources) accepts any format.
+    pub source_depth: Option<SampleDepth>,
+    /// Device buffer size, clamped to what the device supports; `None`: the device's default.
+    pub buffer_frames: Option<u32>,
+}
+
+/// Sample formats the callback can write, most precise first.
+const SAMPLE_FORMATS: [cpal::SampleFormat; 5] = [
+    cpal::SampleFormat::F32,
+    cpal::SampleFormat::I32,
+    cpal::SampleFormat::I24,
+    cpal::SampleFormat::I16,
+    cpal::SampleFormat::U16,
+];
+
+fn format_rank(format: cpal::SampleFormat) -> Option<usize> {
+    SAMPLE_FORMATS.iter().position(|f| *f == format)
+}
+
+/// How a source stores its samples: integers of so many bits, or floats of so many bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleDepth {
+    Int(u32),
+    Float(u32),
+}
+
+impl SampleDepth {
+    /// Whether every sample survives conversion to `format` unchanged. Integers need a format
+    /// at least as wide (f32 holds 24 bits exactly); floats only pass through f32 untouched,
+    /// an integer format would round them.
+    fn fits(self, format: cpal::SampleFormat) -> bool {
+        use cpal::SampleFormat as F;
+        match self {
+            Self::Int(bits) => {
+                let width = match format { F::I32 => 32, F::F32 | F::I24 => 24, _ => 16 };
+                bits <= width
+            }
+            Self::Float(bits) => format == F::F32 && bits <= 32,
+        }
+    }
+}
+
+/// The most precise config at exactly `sample_rate` and `channels` that holds samples of
+/// `depth` exactly (any format for `None`), if the device has one.
+fn exact_config(
+    device: &cpal::Device,
+    sample_rate: u32,
+    channels: u16,
+    depth: Option<SampleDepth>,
+) -> Option<cpal::SupportedStreamConfig> {
+    use cpal::traits::DeviceTrait;
+    device
+        .supported_output_configs()
+        .ok()?
+        .filter(|c| c.channels() == channels && format_rank(c.sample_format()).is_some())
+        .filter(|c| depth.map_or(true, |d| d.fits(c.sample_format())))
+        .filter(|c| c.min_sample_rate().0 <= sample_rate && sample_rate <= c.max_sample_rate().0)
+        .min_by_key(|c| format_rank(c.sample_format()))
+        .map(|c| c.with_sample_rate(cpal::SampleRate(sample_rate)))
+}
+
+/// The device's default config, or the same rate and channels in a format we can write if
+/// the default one isn't.
+fn default_config(device: &cpal::Device) -> anyhow::Result<cpal::SupportedStreamConfig> {
+    use cpal::traits::DeviceTrait;
+    let config = device.default_output_config()?;
+    if format_rank(config.sample_format()).is_some() { return Ok(config); }
+    exact_config(device, config.sample_rate().0, config.channels(), None)
+        .ok_or_else(|| anyhow::anyhow!("unsupported output sample format {}", config.sample_format()))
 }
 
 /// Build an output stream. The callback pulls **f32** from the consumer and writes
-/// device samples (f32/i16/u16) with volume applied. No locking in the callback.
-/// Also updates peak meters and frames_played.
+/// device samples (f32/i32/i24/i16/u16) with volume applied, dithering integer formats of 24
+/// bits or less (noise shaped while `noise_shaping` is set). No locking in the callback.
+/// Volume (on a dB curve, see [`volume_gain`]) and the `fader`'s pause/stop fades are ramped
+/// per sample.
+/// Also updates peak meters and frames_played, restarting the latter when a gapless track
+/// boundary (see [`TrackBoundary`]) is crossed. frames_played counts source frames: each
+/// frame played advances it by the `speed` (f32 bits) the audio was time-stretched at, which
+/// the callback updates at boundaries and seeks. Seeks flush the ring in place (see
+/// [`SeekFlush`]) with a short fade on either side. A lost device, or stream errors that keep
+/// coming, raise `stream_error` for the engine to act on, and the device's own latency is kept
+/// in `device_latency_us`.
+///
+/// Without a bit-perfect format in `request`, or if the device can't do it, its default config
+/// is used. Bit-perfect streams are neither scaled nor dithered and only use formats that hold
+/// the source's samples exactly (see [`SampleDepth`]). A device that has the rate and channels
+/// only in other formats gets a normal (scaled, dithered) stream at them.
 pub fn build_output_stream(
     device: &cpal::Device,
-    mut cons: HeapCons<f32>,
+    request: StreamRequest,
+    cons: HeapCons<f32>,
     vol_bits: Arc<AtomicU32>,
     state: Arc<AtomicU8>,
     frames_played: Arc<AtomicU64>,
@@ -23,6 +125,13 @@ pub fn build_output_stream(
     peak_r_bits: Arc<AtomicU32>,
     out_rms_bits: Arc<AtomicU32>,
     queued_samples: &'static std::sync::atomic::AtomicUsize,
+    boundary: Arc<TrackBoundary>,
+    stream_error: Arc<AtomicBool>,
+    device_latency_us: Arc<AtomicU32>,
+    noise_shaping: Arc<AtomicBool>,
+    seek: Arc<SeekFlush>,
+    fader: Arc<Fader>,
+    speed: Arc<AtomicU32>,
 ) -> anyhow::Result<BuiltOutput> {
     let vol_c   = Arc::clone(&vol_bits);
     let fr_c    = Arc::clone(&frames_played);
@@ -30,11 +139,22 @@ pub fn build_output_stream(
     let pk_r_c  = Arc::clone(&peak_r_bits);
     let rms_c   = Arc::clone(&out_rms_bits);
 
-    use cpal::traits::DeviceTrait;
-
-    let config = device.default_output_config()?;
+    let exact = request.bit_perfect.and_then(|(sr, ch)| exact_config(device, sr, ch, request.source_depth));
+    let bypass_volume = exact.is_some();
+    let narrower = || request.bit_perfect.and_then(|(sr, ch)| exact_config(device, sr, ch, None));
+    let config = match exact.or_else(narrower) {
+        Some(config) => config,
+        None => default_config(device)?,
+    };
     let mut stream_config: cpal::StreamConfig = config.clone().into();
-    stream_config.buffer_size = cpal::BufferSize::Fixed(4096);
+    let buffer_frames = request.buffer_frames.map(|frames| match *config.buffer_size() {
+        cpal::SupportedBufferSize::Range { min, max } => frames.clamp(min, max),
+        cpal::SupportedBufferSize::Unknown => frames,
+    });
+    stream_config.buffer_size = match buffer_frames {
+        Some(frames) => cpal::BufferSize::Fixed(frames),
+        None => cpal::BufferSize::Default,
+    };
 
     let out_ch = stream_config.channels;
     let out_sr = stream_config.sample_rate.0;
@@ -61,47 +181,463 @@ pub fn build_output_stream(
         }
     }}}
 
-    let channels = stream_config.channels as usize;
+    let cb = OutputCallback {
+        cons,
+        channels: stream_config.channels as usize,
+        read: 0,
+        bypass_volume,
+        vol: vol_c,
+        frames_played: fr_c,
+        speed,
+        frac_frames: 0.0,
+        peak_l: pk_l_c,
+        peak_r: pk_r_c,
+        rms: rms_c,
+        queued_samples,
+        boundary,
+        device_latency_us,
+        noise_shaping,
+        seek_seen: seek.requested.load(Ordering::Acquire),
+        seek,
+        flushing: false,
+        ramp_frames: (out_sr / 1000 * SEEK_RAMP_MS).max(1) as usize,
+        fade_out_left: 0,
+        fade_in_left: 0,
+        sample_rate: out_sr,
+        volume: Ramp::new(if bypass_volume { 1.0 } else { volume_gain(f32::from_bits(vol_bits.load(Ordering::Relaxed))) }),
+        transport: Ramp::new(0.0),
+        fader,
+        mix: vec![0.0; buffer_frames.unwrap_or(4096) as usize * out_ch as usize],
+    };
+    // a lost device needs a new stream; so do backend errors that keep coming (ALSA reports an
+    // unplugged device that way), but not a one-off
+    let mut errors = ErrorRun::default();
+    let error_cb = move |err| match err {
+        cpal::StreamError::DeviceNotAvailable => {
+            error!("output device no longer available");
+            stream_error.store(true, Ordering::Release);
+        }
+        other => {
+            let (first, failing) = errors.error(Instant::now());
+            if first { log::warn!("cpal output error: {other:?}"); }
+            if failing && !stream_error.swap(true, Ordering::AcqRel) {
+                error!("output stream keeps failing ({other:?})");
+            }
+        }
+    };
+
+    let sample_format = config.sample_format();
+    let stream = match sample_format {
+        cpal::SampleFormat::F32 => build_typed::<f32>(device, &stream_config, cb, error_cb)?,
+        cpal::SampleFormat::I16 => build_typed::<i16>(device, &stream_config, cb, error_cb)?,
+        cpal::SampleFormat::U16 => build_typed::<u16>(device, &stream_config, cb, error_cb)?,
+        cpal::SampleFormat::I24 => build_typed::<cpal::I24>(device, &stream_config, cb, error_cb)?,
+        cpal::SampleFormat::I32 => build_typed::<i32>(device, &stream_config, cb, error_cb)?,
+        other => anyhow::bail!("unsupported output sample format {other}"),
+    };
 
-    let stream = device.build_output_stream(
-        &stream_config,
-        move |data: &mut [f32], _| {
-            // pull from ringbuf
-            let got = cons.pop_slice(data);
-            let _ = queued_samples.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some(v.saturating_sub(got)));
-            if got < data.len() {
-                data[got..].fill(0.0);
+    Ok(BuiltOutput { stream, sample_rate: out_sr, channels: out_ch, sample_format, bit_perfect: bypass_volume, buffer_frames })
+}
+
+/// How long backend errors have to keep coming before the stream is given up on.
+const ERROR_RUN: Duration = Duration::from_millis(250);
+
+/// A run of stream errors: errors less than [`ERROR_RUN`] apart.
+#[derive(Default)]
+struct ErrorRun {
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl ErrorRun {
+    /// Count an error at `now`; returns whether it starts a run, and whether the run has lasted
+    /// [`ERROR_RUN`].
+    fn error(&mut self, now: Instant) -> (bool, bool) {
+        let starts = self.last.map_or(true, |last| now.duration_since(last) >= ERROR_RUN);
+        if starts { self.first = Some(now); }
+        self.last = Some(now);
+        (starts, self.first.is_some_and(|first| now.duration_since(first) >= ERROR_RUN))
+    }
+}
+
+/// Length of the fades around a seek.
+const SEEK_RAMP_MS: u32 = 5;
+
+/// State of the output callback, independent of the device's sample type.
+struct OutputCallback {
+    cons: HeapCons<f32>,
+    channels: usize,
+    // samples popped from this ring so far (same count the decoder uses for boundaries)
+    read: u64,
+    bypass_volume: bool,
+    vol: Arc<AtomicU32>,
+    frames_played: Arc<AtomicU64>,
+    // speed of the audio being read, and the fraction of a source frame not yet counted
+    speed: Arc<AtomicU32>,
+    frac_frames: f64,
+    peak_l: Arc<AtomicU32>,
+    peak_r: Arc<AtomicU32>,
+    rms: Arc<AtomicU32>,
+    queued_samples: &'static std::sync::atomic::AtomicUsize,
+    boundary: Arc<TrackBoundary>,
+    device_latency_us: Arc<AtomicU32>,
+    noise_shaping: Arc<AtomicBool>,
+    seek: Arc<SeekFlush>,
+    // last seek generation seen, and whether its stale audio is still in the ring
+    seek_seen: u64,
+    flushing: bool,
+    // fade lengths, in frames: the whole ramp and what's left of the current fade
+    ramp_frames: usize,
+    fade_out_left: usize,
+    fade_in_left: usize,
+    sample_rate: u32,
+    // smoothed volume gain and pause/stop fade level
+    volume: Ramp,
+    transport: Ramp,
+    fader: Arc<Fader>,
+    // the f32 mix before conversion; only grows if the device asks for more than expected
+    mix: Vec<f32>,
+}
+
+impl OutputCallback {
+    /// Fill `self.mix[..len]` from the ring with volume applied, and update the counters and
+    /// meters.
+    fn render(&mut self, len: usize, info: &cpal::OutputCallbackInfo) {
+        let ts = info.timestamp();
+        if let Some(d) = ts.playback.duration_since(&ts.callback) {
+            self.device_latency_us.store(d.as_micros().min(u32::MAX as u128) as u32, Ordering::Relaxed);
+        }
+        let got = self.render_block(len);
+
+        // simple peak/rms metering
+        let channels = self.channels;
+        let data = &self.mix[..len];
+        let mut lpk = 0f32;
+        let mut rpk = 0f32;
+        for frame in data[..got].chunks_exact(channels) {
+            let l = frame[0].abs();
+            let r = frame.get(1).copied().unwrap_or(l).abs();
+            if l > lpk { lpk = l; }
+            if r > rpk { rpk = r; }
+        }
+        self.peak_l.store(lpk.to_bits(), Ordering::Relaxed);
+        self.peak_r.store(rpk.to_bits(), Ordering::Relaxed);
+        let rms = ((lpk*lpk + rpk*rpk) * 0.5).sqrt();
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Everything but the metering: pick up a new seek, fill the mix and apply the gains.
+    /// Returns how many samples came from the ring.
+    fn render_block(&mut self, len: usize) -> usize {
+        if self.mix.len() < len { self.mix.resize(len, 0.0); }
+
+        let requested = self.seek.requested.load(Ordering::Acquire);
+        if requested != self.seek_seen {
+            // a seek (or another one while still flushing): fade out what's playing, once
+            if !self.flushing && self.seek.fade_out.load(Ordering::Relaxed) {
+                self.fade_out_left = self.ramp_frames;
+            }
+            self.seek_seen = requested;
+            self.flushing = true;
+            self.fade_in_left = 0;
+        }
+        let got = if self.flushing { self.render_flush(len) } else { self.render_playing(len) };
+        self.apply_gain(len);
+        got
+    }
+
+    /// Volume and fader level, ramped per frame towards their current targets.
+    fn apply_gain(&mut self, len: usize) {
+        let vol = if self.bypass_volume { 1.0 } else { volume_gain(f32::from_bits(self.vol.load(Ordering::Relaxed))) };
+        self.volume.set(vol, self.ms_to_frames(VOLUME_RAMP_MS));
+        let open = self.fader.is_open();
+        let level = if open { 1.0 } else { 0.0 };
+        if self.fader.take_snap() { self.transport.snap(level); }
+        else { self.transport.set(level, self.ms_to_frames(self.fader.fade_ms())); }
+
+        if self.volume.settled() && self.transport.settled() {
+            let g = self.volume.current() * self.transport.current();
+            if g != 1.0 { self.mix[..len].iter_mut().for_each(|x| *x *= g); }
+        } else {
+            for frame in self.mix[..len].chunks_exact_mut(self.channels) {
+                let g = self.volume.next() * self.transport.next();
+                frame.iter_mut().for_each(|x| *x *= g);
             }
+        }
+        if !open && self.transport.current() == 0.0 { self.fader.set_silent(); }
+    }
+
+    fn ms_to_frames(&self, ms: u32) -> usize { (self.sample_rate as u64 * ms as u64 / 1000) as usize }
+
+    /// Regular playback: returns how many samples came from the ring.
+    fn render_playing(&mut self, len: usize) -> usize {
+        let got = self.pop(len);
+        let data = &mut self.mix[..len];
+
+        // first block after a seek
+        let channels = self.channels;
+        for frame in data[..got].chunks_exact_mut(channels) {
+            if self.fade_in_left == 0 { break; }
+            let g = 1.0 - self.fade_in_left as f32 / self.ramp_frames as f32;
+            frame.iter_mut().for_each(|x| *x *= g);
+            self.fade_in_left -= 1;
+        }
+
+        // update frames (count frames, not samples); restart them at a track boundary
+        let at = self.boundary.next_at.load(Ordering::Acquire);
+        if at != TrackBoundary::NONE && self.read >= at {
+            self.speed.store(self.boundary.next_speed.load(Ordering::Relaxed), Ordering::Relaxed);
+            self.frames_played.store(0, Ordering::Relaxed);
+            self.frac_frames = 0.0;
+            self.advance(((self.read - at) / channels as u64) as usize);
+            // counted before it's cleared: the decoder publishes the next boundary once it is
+            self.boundary.crossings.fetch_add(1, Ordering::Release);
+            self.boundary.next_at.store(TrackBoundary::NONE, Ordering::Release);
+        } else {
+            self.advance(got / channels);
+        }
+        got
+    }
+
+    /// Count `frames` played frames as source frames at the current speed.
+    fn advance(&mut self, frames: usize) {
+        let speed = f32::from_bits(self.speed.load(Ordering::Relaxed));
+        if speed == 1.0 {
+            self.frames_played.fetch_add(frames as u64, Ordering::Relaxed);
+            return;
+        }
+        self.frac_frames += frames as f64 * speed as f64;
+        let whole = self.frac_frames.floor();
+        self.frac_frames -= whole;
+        self.frames_played.fetch_add(whole as u64, Ordering::Relaxed);
+    }
 
-            // apply volume
-            let vol = f32::from_bits(vol_c.load(Ordering::Relaxed));
-            if vol != 1.0 {
-                for x in &mut data[..got] { *x *= vol; }
+    /// Seeking: fade out the old audio, then silence until the decoder has published where the
+    /// new audio starts; drop everything before it. Position and boundaries are left alone (the
+    /// engine has already set the target position).
+    fn render_flush(&mut self, len: usize) -> usize {
+        let channels = self.channels;
+        let mut got = 0;
+        if self.fade_out_left > 0 {
+            let wanted = (self.fade_out_left * channels).min(len);
+            got = self.pop(wanted);
+            for frame in self.mix[..got].chunks_exact_mut(channels) {
+                let g = self.fade_out_left as f32 / self.ramp_frames as f32;
+                frame.iter_mut().for_each(|x| *x *= g);
+                self.fade_out_left = self.fade_out_left.saturating_sub(1);
             }
+            // ran dry: nothing left to fade
+            if got < wanted { self.fade_out_left = 0; }
+        }
+        self.mix[got..len].fill(0.0);
 
-            // update frames (count frames, not samples)
-            fr_c.fetch_add((got / channels) as u64, Ordering::Relaxed);
-
-            // simple peak/rms metering
-            let mut lpk = 0f32;
-            let mut rpk = 0f32;
-            for frame in data[..got].chunks_exact(channels) {
-                let l = frame[0].abs();
-                let r = frame.get(1).copied().unwrap_or(l).abs();
-                if l > lpk { lpk = l; }
-                if r > rpk { rpk = r; }
+        if self.fade_out_left == 0 && self.seek.ready.load(Ordering::Acquire) == self.seek_seen {
+            let at = self.seek.at.load(Ordering::Relaxed);
+            if self.read < at {
+                let skipped = self.cons.skip((at - self.read) as usize);
+                self.read += skipped as u64;
+                let _ = self.queued_samples.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some(v.saturating_sub(skipped)));
+            }
+            if self.read >= at {
+                self.frames_played.store(self.seek.frames.load(Ordering::Relaxed), Ordering::Relaxed);
+                self.speed.store(self.seek.speed.load(Ordering::Relaxed), Ordering::Relaxed);
+                self.frac_frames = 0.0;
+                self.seek.done_at_us.store(self.seek.now_us(), Ordering::Relaxed);
+                self.seek.done.store(self.seek_seen, Ordering::Release);
+                self.flushing = false;
+                self.fade_in_left = self.ramp_frames;
+            }
+        }
+        got
+    }
+
+    /// Pop up to `len` samples into the mix, zero the rest; returns how many were popped.
+    fn pop(&mut self, len: usize) -> usize {
+        let data = &mut self.mix[..len];
+        let got = self.cons.pop_slice(data);
+        let _ = self.queued_samples.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some(v.saturating_sub(got)));
+        data[got..].fill(0.0);
+        self.read += got as u64;
+        got
+    }
+}
+
+fn build_typed<T: OutputSample + cpal::SizedSample>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut cb: OutputCallback,
+    error_cb: impl FnMut(cpal::StreamError) + Send + 'static,
+) -> anyhow::Result<cpal::Stream> {
+    use cpal::traits::DeviceTrait;
+
+    let dither = |cb: &OutputCallback| dither_for(T::BITS, cb.bypass_volume, cb.noise_shaping.load(Ordering::Relaxed));
+    let mut q = Quantizer::new(T::BITS, cb.channels, dither(&cb));
+    let stream = device.build_output_stream(
+        config,
+        move |data: &mut [T], info: &cpal::OutputCallbackInfo| {
+            cb.render(data.len(), info);
+            q.set_dither(dither(&cb));
+            let channels = cb.channels;
+            for (i, (out, &x)) in data.iter_mut().zip(&cb.mix).enumerate() {
+                *out = T::convert(x, &mut q, i % channels);
             }
-            pk_l_c.store(lpk.to_bits(), Ordering::Relaxed);
-            pk_r_c.store(rpk.to_bits(), Ordering::Relaxed);
-            let rms = ((lpk*lpk + rpk*rpk) * 0.5).sqrt();
-            rms_c.store(rms.to_bits(), Ordering::Relaxed);
-        },
-        move |err| {
-            error!("cpal output error: {err:?}");
         },
+        error_cb,
         None, // <— CPAL 0.16 requires this 4th argument
     )?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::buffer::{make_audio_ring, AudioProd};
+    use ringbuf::traits::Producer;
+    use std::sync::atomic::AtomicUsize;
+
+    const RAMP: usize = 4;
+
+    /// A stereo callback at 48 kHz with 4-frame seek fades, unity volume and the fader as given.
+    fn callback(seek: &Arc<SeekFlush>, fader: Fader) -> (AudioProd, OutputCallback) {
+        let (prod, cons, _) = make_audio_ring(1024);
+        let cb = OutputCallback {
+            cons,
+            channels: 2,
+            read: 0,
+            bypass_volume: true,
+            vol: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            frames_played: Arc::new(AtomicU64::new(0)),
+            speed: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            frac_frames: 0.0,
+            peak_l: Arc::new(AtomicU32::new(0)),
+            peak_r: Arc::new(AtomicU32::new(0)),
+            rms: Arc::new(AtomicU32::new(0)),
+            queued_samples: Box::leak(Box::new(AtomicUsize::new(0))),
+            boundary: Arc::new(TrackBoundary::new()),
+            device_latency_us: Arc::new(AtomicU32::new(0)),
+            noise_shaping: Arc::new(AtomicBool::new(false)),
+            seek_seen: seek.requested.load(Ordering::Acquire),
+            seek: Arc::clone(seek),
+            flushing: false,
+            ramp_frames: RAMP,
+            fade_out_left: 0,
+            fade_in_left: 0,
+            sample_rate: 48_000,
+            volume: Ramp::new(1.0),
+            transport: Ramp::new(0.0),
+            fader: Arc::new(fader),
+            mix: Vec::new(),
+        };
+        (prod, cb)
+    }
+
+    fn playing() -> Fader {
+        let fader = Fader::new();
+        fader.open_at_once();
+        fader
+    }
+
+    fn push(prod: &mut AudioProd, cb: &OutputCallback, value: f32, samples: usize) {
+        assert_eq!(prod.push_slice(&vec![value; samples]), samples);
+        cb.queued_samples.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    /// Left channel of the last block.
+    fn left(cb: &OutputCallback, len: usize) -> Vec<f32> { cb.mix[..len].iter().step_by(2).copied().collect() }
 
+    #[test]
+    fn back_to_back_seeks_fade_out_once_and_land_on_the_latest() {
+        let seek = Arc::new(SeekFlush::new());
+        let (mut prod, mut cb) = callback(&seek, playing());
+        push(&mut prod, &cb, 1.0, 200); // stale audio, ring indices 0..200
 
-    Ok(BuiltOutput { stream, sample_rate: out_sr, channels: out_ch })
-}
\ No newline at end of file
+        let first = seek.request(true);
+        assert_eq!(cb.render_block(16), 8, "only the fade-out comes from the ring");
+        assert_eq!(left(&cb, 16), [1.0, 0.75, 0.5, 0.25, 0.0, 0.0, 0.0, 0.0]);
+
+        // a second seek before the first one landed: no second fade, the first is never played
+        let second = seek.request(true);
+        seek.publish(first, 100, 1_000, 1.0);
+        assert_eq!(cb.render_block(16), 0);
+        assert!(cb.mix[..16].iter().all(|&x| x == 0.0));
+        assert!(seek.in_progress());
+
+        // the new audio starts at ring index 200
+        push(&mut prod, &cb, 0.5, 100);
+        seek.publish(second, 200, 4_800, 1.0);
+        assert_eq!(cb.render_block(16), 0, "the stale audio is skipped, not played");
+        assert!(cb.mix[..16].iter().all(|&x| x == 0.0));
+        assert_eq!(seek.done.load(Ordering::Acquire), second);
+        assert!(!seek.in_progress());
+        assert_eq!(cb.frames_played.load(Ordering::Relaxed), 4_800);
+
+        // faded back in
+        assert_eq!(cb.render_block(16), 16);
+        assert_eq!(left(&cb, 16), [0.0, 0.125, 0.25, 0.375, 0.5, 0.5, 0.5, 0.5]);
+        assert_eq!(cb.read, 216);
+        assert_eq!(cb.frames_played.load(Ordering::Relaxed), 4_808);
+        assert_eq!(cb.queued_samples.load(Ordering::Relaxed), 84);
+    }
+
+    #[test]
+    fn seek_while_paused_drops_stale_audio_silently() {
+        let seek = Arc::new(SeekFlush::new());
+        let (mut prod, mut cb) = callback(&seek, Fader::new());
+        push(&mut prod, &cb, 1.0, 40);
+
+        let generation = seek.request(false);
+        assert_eq!(cb.render_block(16), 0, "nothing to fade out");
+        push(&mut prod, &cb, 0.5, 40);
+        seek.publish(generation, 40, 2_400, 1.0);
+        cb.render_block(16);
+        assert!(!seek.in_progress());
+        assert_eq!(cb.read, 40);
+        assert_eq!(cb.frames_played.load(Ordering::Relaxed), 2_400);
+
+        // still paused: whatever is read stays silent
+        cb.render_block(16);
+        assert!(cb.mix[..16].iter().all(|&x| x == 0.0));
+        assert!(cb.fader.silent());
+    }
+
+    #[test]
+    fn bit_perfect_formats_hold_the_source_exactly() {
+        use cpal::SampleFormat as F;
+        let fits = |depth: SampleDepth| {
+            [F::F32, F::I32, F::I24, F::I16].into_iter().filter(|&f| depth.fits(f)).collect::<Vec<_>>()
+        };
+        assert_eq!(fits(SampleDepth::Int(16)), [F::F32, F::I32, F::I24, F::I16]);
+        assert_eq!(fits(SampleDepth::Int(24)), [F::F32, F::I32, F::I24]);
+        assert_eq!(fits(SampleDepth::Int(32)), [F::I32]);
+        // integer formats would round floats, however wide
+        assert_eq!(fits(SampleDepth::Float(32)), [F::F32]);
+        assert!(fits(SampleDepth::Float(64)).is_empty());
+    }
+
+    #[test]
+    fn only_a_run_of_errors_fails_the_stream() {
+        let t = Instant::now();
+        let ms = |n| t + Duration::from_millis(n);
+        let mut errors = ErrorRun::default();
+        assert_eq!(errors.error(t), (true, false));
+        // a one-off, then another long after: neither fails the stream
+        assert_eq!(errors.error(ms(1_000)), (true, false));
+        // errors that keep coming do
+        for n in (1_010..1_250).step_by(10) { assert_eq!(errors.error(ms(n)), (false, false)); }
+        assert_eq!(errors.error(ms(1_250)), (false, true));
+        assert_eq!(errors.error(ms(1_260)), (false, true));
+    }
+
+    #[test]
+    fn settled_seek_is_not_flushed_by_a_new_stream() {
+        let seek = Arc::new(SeekFlush::new());
+        seek.request(true);
+        // the engine replaces the ring before the decoder published
+        seek.settle();
+        let (mut prod, mut cb) = callback(&seek, playing());
+        push(&mut prod, &cb, 0.5, 16);
+        assert_eq!(cb.render_block(16), 16);
+        assert!(cb.mix[..16].iter().all(|&x| x == 0.5));
+        assert!(!seek.in_progress());
+    }
+}
@@ -0,0 +1,235 @@
+//! Conversion of the f32 mix to the device's sample format.
+//!
+//! Reducing to an integer word length adds TPDF (triangular) dither of ±1 LSB, which makes the
+//! quantization error independent of the signal instead of harmonic distortion on quiet
+//! passages and fades. Noise shaping feeds the error back through `(1 - z⁻¹)²`, moving it
+//! away from the low and middle frequencies toward Nyquist at the cost of more noise overall.
+
+use cpal::I24;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    /// Plain rounding; exact for samples that already fit the word length.
+    Off,
+    Tpdf,
+    /// TPDF with second-order noise shaping.
+    Shaped,
+}
+
+/// Dither for a stream of `bits` per sample. Bit-perfect streams must reproduce the source
+/// samples, and word lengths above 24 bits are below what the f32 mix resolves anyway.
+pub fn dither_for(bits: u32, bit_perfect: bool, noise_shaping: bool) -> Dither {
+    if bit_perfect || bits > 24 { Dither::Off }
+    else if noise_shaping { Dither::Shaped }
+    else { Dither::Tpdf }
+}
+
+/// Turns `-1.0..1.0` into integers of a given word length, clipping at full scale.
+pub struct Quantizer {
+    scale: f64,
+    min: f64,
+    max: f64,
+    dither: Dither,
+    rng: u32,
+    /// Last two total errors (rounding + dither) per channel, for noise shaping.
+    err: Vec<[f64; 2]>,
+}
+
+impl Quantizer {
+    pub fn new(bits: u32, channels: usize, dither: Dither) -> Self {
+        let scale = (1u64 << (bits - 1)) as f64;
+        Self { scale, min: -scale, max: scale - 1.0, dither, rng: 0x9E37_79B9, err: vec![[0.0; 2]; channels.max(1)] }
+    }
+
+    pub fn set_dither(&mut self, dither: Dither) {
+        if dither == self.dither { return; }
+        self.dither = dither;
+        self.err.iter_mut().for_each(|e| *e = [0.0; 2]);
+    }
+
+    /// Sample `x` of channel `ch`, as an integer in the word length's range.
+    pub fn quantize(&mut self, x: f32, ch: usize) -> i32 {
+        let target = x as f64 * self.scale;
+        let wanted = match self.dither {
+            Dither::Shaped => { let e = self.err[ch]; target - 2.0 * e[0] + e[1] }
+            _ => target,
+        };
+        let y = match self.dither {
+            Dither::Off => wanted.round(),
+            Dither::Tpdf | Dither::Shaped => (wanted + self.tpdf()).round(),
+        };
+        if self.dither == Dither::Shaped {
+            // taken before clipping so the feedback stays bounded on overloads
+            let e = &mut self.err[ch];
+            *e = [y - wanted, e[0]];
+        }
+        y.clamp(self.min, self.max) as i32
+    }
+
+    /// Triangular noise in `(-1, 1)` LSB: the sum of two uniform values.
+    fn tpdf(&mut self) -> f64 {
+        self.uniform() + self.uniform() - 1.0
+    }
+
+    /// Uniform in `[0, 1)` (xorshift32; plenty for noise, and cheap in the audio callback).
+    fn uniform(&mut self) -> f64 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        x as f64 / 4_294_967_296.0
+    }
+}
+
+/// A device sample type the output callback can write.
+pub trait OutputSample: Copy + Send + 'static {
+    /// Word length. Float formats report their mantissa and take the mix as it is.
+    const BITS: u32;
+    fn convert(x: f32, q: &mut Quantizer, ch: usize) -> Self;
+}
+
+impl OutputSample for f32 {
+    const BITS: u32 = 24;
+    fn convert(x: f32, _: &mut Quantizer, _: usize) -> Self { x }
+}
+
+impl OutputSample for i16 {
+    const BITS: u32 = 16;
+    fn convert(x: f32, q: &mut Quantizer, ch: usize) -> Self { q.quantize(x, ch) as i16 }
+}
+
+impl OutputSample for u16 {
+    const BITS: u32 = 16;
+    fn convert(x: f32, q: &mut Quantizer, ch: usize) -> Self { (q.quantize(x, ch) + 0x8000) as u16 }
+}
+
+impl OutputSample for I24 {
+    const BITS: u32 = 24;
+    fn convert(x: f32, q: &mut Quantizer, ch: usize) -> Self { I24::new_unchecked(q.quantize(x, ch)) }
+}
+
+impl OutputSample for i32 {
+    const BITS: u32 = 32;
+    fn convert(x: f32, q: &mut Quantizer, ch: usize) -> Self { q.quantize(x, ch) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quantizer<T: OutputSample>(dither: Dither) -> Quantizer { Quantizer::new(T::BITS, 1, dither) }
+
+    #[test]
+    fn converts_without_dither() {
+        let mut q = quantizer::<i16>(Dither::Off);
+        assert_eq!(i16::convert(0.0, &mut q, 0), 0);
+        assert_eq!(i16::convert(0.5, &mut q, 0), 16384);
+        assert_eq!(i16::convert(-1.0, &mut q, 0), i16::MIN);
+        assert_eq!(i16::convert(1.0, &mut q, 0), i16::MAX);
+        assert_eq!(i16::convert(3.0, &mut q, 0), i16::MAX);
+        assert_eq!(i16::convert(-3.0, &mut q, 0), i16::MIN);
+
+        let mut q = quantizer::<u16>(Dither::Off);
+        assert_eq!(u16::convert(-1.0, &mut q, 0), 0);
+        assert_eq!(u16::convert(0.0, &mut q, 0), 0x8000);
+        assert_eq!(u16::convert(1.0, &mut q, 0), u16::MAX);
+
+        let mut q = quantizer::<I24>(Dither::Off);
+        assert_eq!(I24::convert(0.25, &mut q, 0).inner(), 1 << 21);
+        assert_eq!(I24::convert(-1.0, &mut q, 0).inner(), -(1 << 23));
+        assert_eq!(I24::convert(1.0, &mut q, 0).inner(), (1 << 23) - 1);
+
+        let mut q = quantizer::<i32>(Dither::Off);
+        assert_eq!(i32::convert(-1.0, &mut q, 0), i32::MIN);
+        assert_eq!(i32::convert(1.0, &mut q, 0), i32::MAX);
+        assert_eq!(i32::convert(-0.5, &mut q, 0), -(1 << 30));
+
+        let mut q = quantizer::<f32>(Dither::Off);
+        assert_eq!(f32::convert(0.123, &mut q, 0), 0.123);
+    }
+
+    #[test]
+    fn round_trips_samples_that_fit() {
+        let mut q = quantizer::<i16>(Dither::Off);
+        for v in i16::MIN..=i16::MAX {
+            assert_eq!(i16::convert(v as f32 / 32768.0, &mut q, 0), v);
+        }
+        let mut q = quantizer::<I24>(Dither::Off);
+        for v in (-(1 << 23)..(1 << 23)).step_by(997) {
+            assert_eq!(I24::convert(v as f32 / 8_388_608.0, &mut q, 0).inner(), v);
+        }
+    }
+
+    #[test]
+    fn dither_choice() {
+        assert_eq!(dither_for(16, false, false), Dither::Tpdf);
+        assert_eq!(dither_for(24, false, true), Dither::Shaped);
+        assert_eq!(dither_for(16, true, true), Dither::Off);
+        assert_eq!(dither_for(32, false, true), Dither::Off);
+    }
+
+    /// Error of each output against the input, in LSB.
+    fn errors(q: &mut Quantizer, x: f32, n: usize) -> Vec<f64> {
+        (0..n).map(|_| q.quantize(x, 0) as f64 - x as f64 * 32768.0).collect()
+    }
+
+    fn mean(v: &[f64]) -> f64 { v.iter().sum::<f64>() / v.len() as f64 }
+    fn variance(v: &[f64]) -> f64 { let m = mean(v); v.iter().map(|e| (e - m) * (e - m)).sum::<f64>() / v.len() as f64 }
+
+    #[test]
+    fn tpdf_statistics() {
+        const N: usize = 200_000;
+        // mid-LSB, quarter-LSB and on-grid inputs: the error is unbiased and its power doesn't
+        // depend on the signal (TPDF variance 1/6 plus rounding 1/12)
+        for x in [0.5 / 32768.0, 0.25 / 32768.0, 0.0, 1000.0 / 32768.0] {
+            let mut q = quantizer::<i16>(Dither::Tpdf);
+            let e = errors(&mut q, x, N);
+            assert!(mean(&e).abs() < 0.01, "mean {} at {x}", mean(&e));
+            assert!((variance(&e) - 0.25).abs() < 0.02, "variance {} at {x}", variance(&e));
+            assert!(e.iter().all(|e| e.abs() <= 1.5));
+        }
+    }
+
+    #[test]
+    fn tpdf_is_triangular() {
+        const N: usize = 200_000;
+        let mut q = quantizer::<i16>(Dither::Tpdf);
+        let mut counts = [0usize; 3];
+        for _ in 0..N {
+            let v = q.quantize(0.0, 0);
+            assert!((-1..=1).contains(&v));
+            counts[(v + 1) as usize] += 1;
+        }
+        // at 0 the output is -1, 0 or +1 with probabilities 1/8, 3/4, 1/8
+        let p: Vec<f64> = counts.iter().map(|&c| c as f64 / N as f64).collect();
+        assert!((p[0] - 0.125).abs() < 0.01 && (p[2] - 0.125).abs() < 0.01 && (p[1] - 0.75).abs() < 0.01, "{p:?}");
+    }
+
+    /// Power of the error after a 128-tap moving average (a crude low-pass).
+    fn low_band_power(e: &[f64]) -> f64 {
+        let smoothed: Vec<f64> = e.windows(128).map(|w| w.iter().sum::<f64>() / 128.0).collect();
+        smoothed.iter().map(|s| s * s).sum::<f64>() / smoothed.len() as f64
+    }
+
+    #[test]
+    fn noise_shaping_moves_error_out_of_the_low_band() {
+        const N: usize = 100_000;
+        let x = 0.3 / 32768.0;
+        let flat = errors(&mut quantizer::<i16>(Dither::Tpdf), x, N);
+        let shaped = errors(&mut quantizer::<i16>(Dither::Shaped), x, N);
+        assert!(mean(&shaped).abs() < 0.01);
+        // (1 - z⁻¹)² has a gain of 6 in power, all of it pushed up in frequency
+        assert!(variance(&shaped) > 4.0 * variance(&flat));
+        assert!(low_band_power(&shaped) < 0.1 * low_band_power(&flat));
+    }
+
+    #[test]
+    fn noise_shaping_survives_clipping() {
+        let mut q = quantizer::<i16>(Dither::Shaped);
+        for _ in 0..10_000 { assert_eq!(q.quantize(2.0, 0), i16::MAX as i32); }
+        // the feedback didn't wind up while clipped
+        let e = errors(&mut q, 0.0, 1000);
+        assert!(e.iter().all(|e| e.abs() <= 6.0));
+    }
+}
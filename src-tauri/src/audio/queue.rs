@@ -0,0 +1,452 @@
+//! The engine's play queue: the items as given, plus the order they are played in.
+//!
+//! Shuffling permutes `order` once (when the queue or mode is set) instead of picking a random
+//! item each time, so Prev walks back through exactly what was played and Next after Prev
+//! replays the same tracks.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayMode {
+    /// Play the queue once, in order.
+    #[default]
+    Off,
+    RepeatAll,
+    /// Loop the current track; Next/Prev still move through the queue.
+    RepeatOne,
+    /// Play every item once in a random order.
+    Shuffle,
+    /// Play albums in a random order, each album's tracks in queue order.
+    AlbumShuffle,
+}
+
+impl PlayMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::RepeatAll => "repeat_all",
+            Self::RepeatOne => "repeat_one",
+            Self::Shuffle => "shuffle",
+            Self::AlbumShuffle => "album_shuffle",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "repeat_all" => Some(Self::RepeatAll),
+            "repeat_one" => Some(Self::RepeatOne),
+            "shuffle" => Some(Self::Shuffle),
+            "album_shuffle" => Some(Self::AlbumShuffle),
+            _ => None,
+        }
+    }
+
+    pub fn is_shuffled(self) -> bool {
+        matches!(self, Self::Shuffle | Self::AlbumShuffle)
+    }
+}
+
+/// What the UI is shown (`get_queue`, `audio:queue`).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueueState {
+    pub items: Vec<String>,
+    /// Item indices in play order.
+    pub order: Vec<usize>,
+    /// Index into `items` of the current item.
+    pub current: Option<usize>,
+    pub mode: PlayMode,
+}
+
+/// Result of an edit: `remap[i]` is the new index of what was item `i`, `None` if it was removed.
+pub type Remap = Vec<Option<usize>>;
+
+/// splitmix64; good enough for shuffling and needs no extra dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn seeded() -> Self {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        Self(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0x9e37_79b9))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            v.swap(i, j);
+        }
+    }
+}
+
+pub struct PlayQueue {
+    items: Vec<String>,
+    /// Indices into `items` in play order; the identity unless shuffled.
+    order: Vec<usize>,
+    /// Position in `order` of the current item.
+    pos: Option<usize>,
+    mode: PlayMode,
+    rng: Rng,
+}
+
+impl Default for PlayQueue {
+    fn default() -> Self {
+        Self { items: Vec::new(), order: Vec::new(), pos: None, mode: PlayMode::Off, rng: Rng::seeded() }
+    }
+}
+
+impl PlayQueue {
+    pub fn items(&self) -> &[String] { &self.items }
+    pub fn len(&self) -> usize { self.items.len() }
+    pub fn is_empty(&self) -> bool { self.items.is_empty() }
+    pub fn mode(&self) -> PlayMode { self.mode }
+    pub fn path(&self, item: usize) -> &str { &self.items[item] }
+
+    /// Item indices in play order.
+    pub fn order(&self) -> &[usize] { &self.order }
+
+    /// Position of the current item in [`order`](Self::order).
+    pub fn pos(&self) -> Option<usize> { self.pos }
+
+    /// Index into [`items`](Self::items) of the current item.
+    pub fn current(&self) -> Option<usize> { self.pos.map(|p| self.order[p]) }
+
+    pub fn item_at(&self, pos: usize) -> Option<usize> { self.order.get(pos).copied() }
+
+    pub fn pos_of(&self, item: usize) -> Option<usize> { self.order.iter().position(|&i| i == item) }
+
+    pub fn set_pos(&mut self, pos: usize) {
+        if pos < self.order.len() { self.pos = Some(pos); }
+    }
+
+    /// Replace the queue. `start_at` (an item index) becomes current and, when shuffling,
+    /// plays first. `albums` is only consulted for album shuffle (see [`set_mode`](Self::set_mode)).
+    pub fn set_items(&mut self, items: Vec<String>, start_at: Option<usize>, albums: &[Option<i64>]) {
+        self.items = items;
+        let start = start_at.filter(|&i| i < self.items.len());
+        self.reorder(start, albums);
+        if start.is_none() { self.pos = None; }
+    }
+
+    /// Put back a saved queue: `order` as returned by [`order`](Self::order) and the current
+    /// position in it. An order that doesn't fit the items is replaced by the queue order.
+    pub fn restore(&mut self, items: Vec<String>, order: Vec<usize>, pos: Option<usize>, mode: PlayMode) {
+        let mut seen = vec![false; items.len()];
+        let valid = order.len() == items.len() && order.iter().all(|&i| i < seen.len() && !std::mem::replace(&mut seen[i], true));
+        self.order = if valid { order } else { (0..items.len()).collect() };
+        self.items = items;
+        self.mode = mode;
+        self.pos = pos.filter(|&p| p < self.order.len());
+    }
+
+    /// Switch modes, keeping the current item current. A new shuffle order starts at it.
+    /// `albums[i]` is the album of item `i`; items without one shuffle as albums of their own.
+    pub fn set_mode(&mut self, mode: PlayMode, albums: &[Option<i64>]) {
+        self.mode = mode;
+        let current = self.current();
+        self.reorder(current, albums);
+        if current.is_none() { self.pos = None; }
+    }
+
+    fn reorder(&mut self, first: Option<usize>, albums: &[Option<i64>]) {
+        let n = self.items.len();
+        match self.mode {
+            PlayMode::Shuffle => {
+                let mut rest: Vec<usize> = (0..n).filter(|&i| Some(i) != first).collect();
+                self.rng.shuffle(&mut rest);
+                self.order = first.into_iter().chain(rest).collect();
+                self.pos = first.map(|_| 0);
+            }
+            PlayMode::AlbumShuffle => {
+                // group by album in first-appearance order, tracks in queue order
+                let mut groups: Vec<(Option<i64>, Vec<usize>)> = Vec::new();
+                for i in 0..n {
+                    let album = albums.get(i).copied().flatten();
+                    match groups.iter_mut().find(|(a, _)| album.is_some() && *a == album) {
+                        Some((_, g)) => g.push(i),
+                        None => groups.push((album, vec![i])),
+                    }
+                }
+                let lead = first.and_then(|f| groups.iter().position(|(_, g)| g.contains(&f)));
+                let lead = lead.map(|p| groups.remove(p));
+                self.rng.shuffle(&mut groups);
+                self.order = lead.iter().chain(&groups).flat_map(|(_, g)| g.iter().copied()).collect();
+                self.pos = first.and_then(|f| self.order.iter().position(|&i| i == f));
+            }
+            _ => {
+                self.order = (0..n).collect();
+                self.pos = first;
+            }
+        }
+    }
+
+    /// Add items at the end of the queue. When shuffling they are dealt into random upcoming
+    /// slots (album shuffle keeps them together at the end of the play order).
+    pub fn append(&mut self, paths: Vec<String>) -> Remap {
+        let remap = (0..self.items.len()).map(Some).collect();
+        let first = self.items.len();
+        self.items.extend(paths);
+        let start = self.pos.map_or(0, |p| p + 1);
+        for item in first..self.items.len() {
+            match self.mode {
+                PlayMode::Shuffle => {
+                    let at = start + (self.rng.next() % (self.order.len() - start + 1) as u64) as usize;
+                    self.order.insert(at, item);
+                }
+                _ => self.order.push(item),
+            }
+        }
+        remap
+    }
+
+    /// Insert items right after item `after` ("play next": the current one, or a later one
+    /// that is already decoded), or at the front when `None`. They play after it in every mode.
+    pub fn insert_after(&mut self, after: Option<usize>, paths: Vec<String>) -> Remap {
+        let at = after.map_or(0, |a| a + 1);
+        let n = paths.len();
+        let remap: Remap = (0..self.items.len()).map(|i| Some(if i >= at { i + n } else { i })).collect();
+        self.items.splice(at..at, paths);
+        self.apply(&remap);
+        if self.mode.is_shuffled() {
+            let start = after.and_then(|a| remap[a]).and_then(|a| self.pos_of(a)).map_or(0, |p| p + 1);
+            self.order.splice(start..start, at..at + n);
+        }
+        remap
+    }
+
+    /// Move item `from` to index `to`; the play order follows unless shuffled.
+    pub fn move_item(&mut self, from: usize, to: usize) -> Option<Remap> {
+        if from >= self.items.len() || to >= self.items.len() { return None; }
+        let item = self.items.remove(from);
+        self.items.insert(to, item);
+        let remap: Remap = (0..self.items.len())
+            .map(|i| {
+                if i == from { return Some(to); }
+                let j = if i > from { i - 1 } else { i };
+                Some(if j >= to { j + 1 } else { j })
+            })
+            .collect();
+        self.apply(&remap);
+        Some(remap)
+    }
+
+    /// Drop the items for which `keep` is false. If that includes the current item, the one
+    /// that would have played next becomes current.
+    pub fn retain(&mut self, keep: impl Fn(usize) -> bool) -> Remap {
+        let mut next = 0;
+        let remap: Remap = (0..self.items.len())
+            .map(|i| keep(i).then(|| { next += 1; next - 1 }))
+            .collect();
+        let successor = self.pos.and_then(|p| self.order[p..].iter().find_map(|&i| remap[i]));
+        let mut i = 0;
+        self.items.retain(|_| { i += 1; remap[i - 1].is_some() });
+        self.apply(&remap);
+        if self.pos.is_none() { self.pos = successor.and_then(|s| self.pos_of(s)); }
+        remap
+    }
+
+    /// Items after the current one in play order (all of them when nothing is current).
+    pub fn upcoming(&self) -> &[usize] {
+        &self.order[self.pos.map_or(0, |p| p + 1)..]
+    }
+
+    /// Bring `order` and `pos` in line with items that were inserted, removed or moved.
+    /// New items are left for the caller to place when shuffling.
+    fn apply(&mut self, remap: &[Option<usize>]) {
+        let current = self.current().and_then(|i| remap[i]);
+        if self.mode.is_shuffled() {
+            self.order = self.order.iter().filter_map(|&i| remap[i]).collect();
+        } else {
+            self.order = (0..self.items.len()).collect();
+        }
+        self.pos = current.and_then(|c| self.pos_of(c));
+    }
+
+    pub fn state(&self) -> QueueState {
+        QueueState { items: self.items.clone(), order: self.order.clone(), current: self.current(), mode: self.mode }
+    }
+
+    /// Position to play after the current one. `auto` is true when the current track ended by
+    /// itself, which is when repeat-one repeats; `None` means playback stops.
+    pub fn next_pos(&self, auto: bool) -> Option<usize> {
+        self.next_after(self.pos?, auto)
+    }
+
+    /// Position to play after position `pos` (see [`next_pos`](Self::next_pos)).
+    pub fn next_after(&self, pos: usize, auto: bool) -> Option<usize> {
+        let len = self.order.len();
+        match self.mode {
+            PlayMode::RepeatOne if auto => Some(pos),
+            PlayMode::RepeatAll | PlayMode::RepeatOne => Some((pos + 1) % len),
+            _ => (pos + 1 < len).then_some(pos + 1),
+        }
+    }
+
+    /// Position to go back to; wraps only when repeating.
+    pub fn prev_pos(&self) -> Option<usize> {
+        let len = self.order.len();
+        let pos = self.pos?;
+        match self.mode {
+            _ if pos > 0 => Some(pos - 1),
+            PlayMode::RepeatAll | PlayMode::RepeatOne => Some(len - 1),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(n: usize, mode: PlayMode) -> PlayQueue {
+        let mut q = PlayQueue::default();
+        q.set_mode(mode, &[]);
+        q.set_items((0..n).map(|i| format!("{i}")).collect(), Some(0), &[]);
+        q
+    }
+
+    fn paths_in_order(q: &PlayQueue) -> Vec<&str> {
+        q.order().iter().map(|&i| q.path(i)).collect()
+    }
+
+    #[test]
+    fn play_next_goes_after_the_decoded_next_track() {
+        let mut q = queue(4, PlayMode::Off);
+        // item 1 is already chained in by the decoder
+        q.insert_after(Some(1), vec!["x".into()]);
+        assert_eq!(paths_in_order(&q), ["0", "1", "x", "2", "3"]);
+        assert_eq!(q.current(), Some(0));
+        let next = q.next_after(q.pos_of(1).unwrap(), true).unwrap();
+        assert_eq!(q.path(q.item_at(next).unwrap()), "x");
+    }
+
+    #[test]
+    fn play_next_when_shuffled() {
+        let mut q = queue(6, PlayMode::Shuffle);
+        let current = q.current().unwrap();
+        let anchor = q.item_at(q.pos().unwrap() + 1).unwrap();
+        let anchor_path = q.path(anchor).to_string();
+        q.insert_after(Some(anchor), vec!["x".into(), "y".into()]);
+
+        let order = paths_in_order(&q);
+        let at = order.iter().position(|p| *p == anchor_path).unwrap();
+        assert_eq!(&order[at + 1..at + 3], ["x", "y"]);
+        assert_eq!(q.path(q.current().unwrap()), format!("{current}"));
+    }
+
+    #[test]
+    fn play_next_with_nothing_current() {
+        let mut q = PlayQueue::default();
+        q.insert_after(None, vec!["x".into()]);
+        q.insert_after(None, vec!["y".into()]);
+        assert_eq!(paths_in_order(&q), ["y", "x"]);
+    }
+
+    fn current_path(q: &PlayQueue) -> Option<&str> { q.current().map(|i| q.path(i)) }
+
+    fn is_permutation(q: &PlayQueue) -> bool {
+        let mut order = q.order().to_vec();
+        order.sort_unstable();
+        order == (0..q.len()).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn append_in_order() {
+        let mut q = queue(3, PlayMode::Off);
+        q.set_pos(1);
+        let remap = q.append(vec!["3".into(), "4".into()]);
+        assert_eq!(remap, [Some(0), Some(1), Some(2)]);
+        assert_eq!(paths_in_order(&q), ["0", "1", "2", "3", "4"]);
+        assert_eq!((q.pos(), current_path(&q)), (Some(1), Some("1")));
+    }
+
+    #[test]
+    fn append_when_shuffled_deals_into_upcoming() {
+        let mut q = queue(6, PlayMode::Shuffle);
+        q.set_pos(2);
+        let played: Vec<String> = paths_in_order(&q)[..=2].iter().map(|p| p.to_string()).collect();
+        q.append((6..10).map(|i| format!("{i}")).collect());
+
+        assert!(is_permutation(&q));
+        assert_eq!(paths_in_order(&q)[..=2], played, "what was played stays put");
+        assert_eq!(q.pos(), Some(2));
+        let upcoming: Vec<&str> = q.upcoming().iter().map(|&i| q.path(i)).collect();
+        assert!(["6", "7", "8", "9"].iter().all(|p| upcoming.contains(p)));
+    }
+
+    #[test]
+    fn move_item_in_order() {
+        let mut q = queue(5, PlayMode::Off);
+        q.set_pos(1);
+        let remap = q.move_item(1, 3).unwrap();
+        assert_eq!(remap, [Some(0), Some(3), Some(1), Some(2), Some(4)]);
+        assert_eq!(paths_in_order(&q), ["0", "2", "3", "1", "4"]);
+        assert_eq!((q.pos(), current_path(&q)), (Some(3), Some("1")));
+
+        // moving another item across the current one shifts its position
+        q.move_item(4, 0).unwrap();
+        assert_eq!(paths_in_order(&q), ["4", "0", "2", "3", "1"]);
+        assert_eq!((q.pos(), current_path(&q)), (Some(4), Some("1")));
+
+        assert!(q.move_item(5, 0).is_none());
+        assert!(q.move_item(0, 5).is_none());
+    }
+
+    #[test]
+    fn move_item_when_shuffled_keeps_the_play_order() {
+        let mut q = queue(6, PlayMode::Shuffle);
+        q.set_pos(3);
+        let before: Vec<String> = paths_in_order(&q).iter().map(|p| p.to_string()).collect();
+        let current = current_path(&q).unwrap().to_string();
+        q.move_item(0, 5).unwrap();
+        assert_eq!(q.items(), ["1", "2", "3", "4", "5", "0"]);
+        assert_eq!(paths_in_order(&q), before);
+        assert_eq!((q.pos(), current_path(&q)), (Some(3), Some(current.as_str())));
+    }
+
+    #[test]
+    fn retain_in_order() {
+        let mut q = queue(5, PlayMode::Off);
+        q.set_pos(3);
+        let remap = q.retain(|i| i != 0 && i != 4);
+        assert_eq!(remap, [None, Some(0), Some(1), Some(2), None]);
+        assert_eq!(paths_in_order(&q), ["1", "2", "3"]);
+        assert_eq!((q.pos(), current_path(&q)), (Some(2), Some("3")));
+    }
+
+    #[test]
+    fn retain_the_current_item() {
+        let mut q = queue(5, PlayMode::Off);
+        q.set_pos(2);
+        // the next one in play order takes over
+        q.retain(|i| i != 2 && i != 3);
+        assert_eq!(paths_in_order(&q), ["0", "1", "4"]);
+        assert_eq!((q.pos(), current_path(&q)), (Some(2), Some("4")));
+        // nothing after it: nothing current
+        q.retain(|i| i != 2);
+        assert_eq!(q.pos(), None);
+    }
+
+    #[test]
+    fn retain_when_shuffled() {
+        let mut q = queue(6, PlayMode::Shuffle);
+        q.set_pos(2);
+        let order: Vec<String> = paths_in_order(&q).iter().map(|p| p.to_string()).collect();
+        let (current, next) = (order[2].clone(), order[3].clone());
+        // paths are the original indices
+        let gone: usize = current.parse().unwrap();
+        q.retain(|i| i != gone);
+        let expected: Vec<&String> = order.iter().filter(|p| **p != current).collect();
+        assert_eq!(paths_in_order(&q), expected);
+        assert!(is_permutation(&q));
+        assert_eq!((q.pos(), current_path(&q)), (Some(2), Some(next.as_str())));
+    }
+}
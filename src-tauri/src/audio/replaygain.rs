@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::repo::tracks::ReplayGain;
+
+/// Which stored gain the engine applies to each track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+    /// Album gain while the queue is a single album, track gain otherwise.
+    Auto,
+}
+
+impl ReplayGainMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Track => "track",
+            Self::Album => "album",
+            Self::Auto => "auto",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "track" => Some(Self::Track),
+            "album" => Some(Self::Album),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// Linear factor for one track. Album values fall back to track values (and vice versa) when
+/// a file only carries one set. The gain is capped so the tagged peak never exceeds full scale.
+pub fn linear_gain(rg: &ReplayGain, album: bool) -> f32 {
+    let (gain, peak) = if album {
+        (rg.album_gain.or(rg.track_gain), rg.album_peak.or(rg.track_peak))
+    } else {
+        (rg.track_gain.or(rg.album_gain), rg.track_peak.or(rg.album_peak))
+    };
+    let Some(db) = gain else { return 1.0 };
+    let mut g = 10f64.powf(db / 20.0);
+    if let Some(p) = peak.filter(|p| *p > 0.0) {
+        g = g.min(1.0 / p);
+    }
+    g as f32
+}
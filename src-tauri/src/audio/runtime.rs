@@ -1,10 +1,18 @@
 use std::{
-    sync::{mpsc, Arc},
+    sync::{mpsc, Arc, Mutex},
     thread,
+    time::Duration,
 };
 use tauri::AppHandle;
 use std::sync::atomic::{AtomicU32, AtomicU64};
 
+use super::crossfade::CrossfadeConfig;
+use super::resample::ResampleQuality;
+use super::replaygain::ReplayGainMode;
+use super::dsp::DspConfig;
+use super::channels::ChannelMatrix;
+use super::queue::{PlayMode, QueueState};
+use super::latency::{LatencyProfile, LatencyReport};
 use super::engine::AudioEngine;
 
 // Commands the UI can send into the runtime.
@@ -20,8 +28,45 @@ pub enum Cmd {
     SetVolume(f32),
     Next,
     Prev,
+    SetCrossfade(CrossfadeConfig),
+    SetResampleQuality(ResampleQuality),
+    SetReplayGainMode(ReplayGainMode),
+    /// Live DSP parameters (e.g. while dragging a slider); not persisted.
+    SetDsp(DspConfig),
+    /// Re-read the stored DSP profile for the current output device.
+    ReloadDspProfile,
+    SetPlayMode(PlayMode),
+    QueueAppend(Vec<String>),
+    /// Insert after the current item (or the next one, once the decoder has moved on to it).
+    QueuePlayNext(Vec<String>),
+    /// Remove queue item `n`; the result is sent back.
+    QueueRemove(usize, mpsc::Sender<Result<(), String>>),
+    QueueMove(usize, usize),
+    QueueClearUpcoming,
+    /// Start playing queue item `n`.
+    QueueJump(usize),
+    /// Output device by name; `None` = system default.
+    SetOutputDevice(Option<String>),
+    SetBitPerfect(bool),
+    SetChannelMatrix(ChannelMatrix),
+    SetNoiseShaping(bool),
+    /// Pause/resume/stop/skip fade length in milliseconds.
+    SetTransportFade(u32),
+    /// Playback speed (time-stretched, pitch kept).
+    SetSpeed(f32),
+    SetRememberSpeed(bool),
+    SetLatency(LatencyProfile),
+    /// From the device monitor: the default output device and all output device names.
+    DevicesChanged { default: Option<String>, devices: Vec<String> },
+    /// Load the session saved by the last run (paused).
+    RestoreSession,
+    /// Save the session now; the sender is signalled when done.
+    SaveSession(mpsc::Sender<()>),
 }
 
+/// How often the engine is polled for track changes / end of stream while idle.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
 // Metrics the UI reads (Arcs are clones of the engine’s atomics)
 #[derive(Clone)]
 pub struct Metrics {
@@ -30,6 +75,14 @@ pub struct Metrics {
     pub peak_l: Arc<AtomicU32>,
     pub peak_r: Arc<AtomicU32>,
     pub sample_rate: Arc<AtomicU32>,
+    /// `f32` bits
+    pub volume: Arc<AtomicU32>,
+    /// Output device in use.
+    pub device_name: Arc<Mutex<Option<String>>>,
+    pub queue: Arc<Mutex<QueueState>>,
+    /// How the stream was opened, and the device's measured delay in µs (0 = unknown).
+    pub latency: Arc<Mutex<LatencyReport>>,
+    pub device_latency_us: Arc<AtomicU32>,
 }
 
 pub struct RuntimeHandle {
@@ -48,17 +101,28 @@ pub fn spawn(app: AppHandle) -> RuntimeHandle {
 
     // Hand out clones of the engine’s metric atomics
     let (frames, duration, pk_l, pk_r) = engine.metrics_arcs();
+    let (latency, device_latency_us) = engine.latency_arcs();
     let metrics = Metrics {
         frames_played: frames,
         duration_frames: duration,
         peak_l: pk_l,
         peak_r: pk_r,
         sample_rate: sr,
+        volume: engine.volume_arc(),
+        device_name: engine.device_name_arc(),
+        queue: engine.queue_state_arc(),
+        latency,
+        device_latency_us,
     };
 
     // Drive the engine on a dedicated thread
     thread::spawn(move || {
-        while let Ok(cmd) = rx.recv() {
+        loop {
+            let cmd = match rx.recv_timeout(POLL_INTERVAL) {
+                Ok(cmd) => cmd,
+                Err(mpsc::RecvTimeoutError::Timeout) => { engine.poll(); continue; }
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            };
             match cmd {
                 Cmd::Load(p)                   => { let _ = engine.load(p); }
                 Cmd::SetQueue(items, start_at) => { let _ = engine.set_queue(items, start_at); }
@@ -74,9 +138,36 @@ pub fn spawn(app: AppHandle) -> RuntimeHandle {
                 Cmd::SetVolume(v)              => engine.set_volume(v),
                 Cmd::Next                      => { let _ = engine.next(); }
                 Cmd::Prev                      => { let _ = engine.prev(); }
+                Cmd::SetCrossfade(cfg)         => engine.set_crossfade(cfg),
+                Cmd::SetResampleQuality(q)     => engine.set_resample_quality(q),
+                Cmd::SetReplayGainMode(m)      => engine.set_replay_gain_mode(m),
+                Cmd::SetDsp(cfg)               => engine.set_dsp(cfg),
+                Cmd::ReloadDspProfile          => engine.reload_dsp_profile(),
+                Cmd::SetPlayMode(m)            => engine.set_play_mode(m),
+                Cmd::QueueAppend(items)        => engine.queue_append(items),
+                Cmd::QueuePlayNext(items)      => engine.queue_play_next(items),
+                Cmd::QueueRemove(i, reply)     => { let _ = reply.send(engine.queue_remove(i).map_err(|e| e.to_string())); }
+                Cmd::QueueMove(from, to)       => engine.queue_move(from, to),
+                Cmd::QueueClearUpcoming        => engine.queue_clear_upcoming(),
+                Cmd::QueueJump(i)              => { let _ = engine.queue_jump(i); }
+                Cmd::SetOutputDevice(name)     => { if let Err(e) = engine.set_output_device(name) { log::warn!("switching output device failed: {e}"); } }
+                Cmd::SetBitPerfect(on)         => { if let Err(e) = engine.set_bit_perfect(on) { log::warn!("bit-perfect switch failed: {e}"); } }
+                Cmd::SetChannelMatrix(m)       => engine.set_channel_matrix(m),
+                Cmd::SetNoiseShaping(on)       => engine.set_noise_shaping(on),
+                Cmd::SetTransportFade(ms)      => engine.set_transport_fade(ms),
+                Cmd::SetSpeed(s)               => { if let Err(e) = engine.set_speed(s) { log::warn!("changing the playback speed failed: {e}"); } }
+                Cmd::SetRememberSpeed(on)      => engine.set_remember_speed(on),
+                Cmd::SetLatency(p)             => { if let Err(e) = engine.set_latency(p) { log::warn!("changing the latency profile failed: {e}"); } }
+                Cmd::DevicesChanged { default, devices } => engine.devices_changed(default, devices),
+                Cmd::RestoreSession            => { if let Err(e) = engine.restore_session() { log::warn!("restoring session failed: {e}"); } }
+                Cmd::SaveSession(done)         => { engine.save_session(); let _ = done.send(()); }
             }
+            engine.poll();
         }
     });
 
+    // rebuild the stream when devices come and go
+    super::hotplug::start_monitor(tx.clone(), Arc::clone(&metrics.device_name));
+
     RuntimeHandle { tx, metrics }
 }
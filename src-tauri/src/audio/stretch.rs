@@ -0,0 +1,306 @@
+//! Playback speed without a change in pitch, by WSOLA (waveform-similarity overlap-add).
+//!
+//! The input is cut into Hann-windowed frames that are taken `speed` times further apart than
+//! they are laid down in the output. Each frame's start may move by a few milliseconds to where
+//! it best lines up with the natural continuation of the previous one, so the overlaps add up in
+//! phase instead of beating. Works on interleaved frames at the output rate; all channels share
+//! the same cut points, so the stereo image stays put.
+
+/// Slowest and fastest playback speed.
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+/// Frame length; long enough to hold a couple of periods of low voices, short enough not to
+/// smear transients.
+const FRAME_MS: u32 = 30;
+/// How far a frame may move each way to line up with the previous one.
+const TOLERANCE_MS: u32 = 8;
+/// Stride of the coarse alignment search (in frames and in samples compared).
+const COARSE: usize = 4;
+
+pub fn validate_speed(speed: f32) -> Result<f32, String> {
+    if (MIN_SPEED..=MAX_SPEED).contains(&speed) { Ok(speed) }
+    else { Err(format!("Speed must be between {MIN_SPEED} and {MAX_SPEED}")) }
+}
+
+pub struct TimeStretch {
+    channels: usize,
+    speed: f32,
+    /// Frame length and output hop (half a frame), in frames.
+    frame: usize,
+    hop: usize,
+    tolerance: usize,
+    /// Periodic Hann: overlapping at half a frame, it sums to exactly 1.
+    window: Vec<f32>,
+    /// Interleaved input not consumed yet, and its channel average for the alignment search.
+    input: Vec<f32>,
+    mono: Vec<f32>,
+    /// Where the next frame would start without alignment (in `input` frames).
+    pos: f64,
+    /// Where the previous frame naturally continues (its start plus `hop`), which the next one
+    /// lines up with; `None` until the first frame.
+    natural: Option<usize>,
+    /// A speed to change to once `pos` gets to that input frame (see `set_speed_after`).
+    switch: Option<(f64, f32)>,
+    /// Overlap-add of the frames laid down so far; its first `hop` frames are complete.
+    acc: Vec<f32>,
+}
+
+impl TimeStretch {
+    pub fn new(sample_rate: u32, channels: usize, speed: f32) -> Self {
+        let frame = (sample_rate * FRAME_MS / 1000) as usize & !1;
+        let window = (0..frame)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / frame as f32).cos())
+            .collect();
+        Self {
+            channels,
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+            frame,
+            hop: frame / 2,
+            tolerance: (sample_rate * TOLERANCE_MS / 1000) as usize,
+            window,
+            input: Vec::new(),
+            mono: Vec::new(),
+            pos: 0.0,
+            natural: None,
+            switch: None,
+            acc: vec![0.0; frame * channels],
+        }
+    }
+
+    /// Applies from the next frame, without a seam. Back at 1.0, the next `process` plays out
+    /// what is held back and samples pass through again after it.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.switch = None;
+    }
+
+    /// Applies from the input handed in after this call; what is held back still plays at the
+    /// old speed (the next track).
+    pub fn set_speed_after(&mut self, speed: f32) {
+        if self.natural.is_none() && self.input.is_empty() { return self.set_speed(speed); }
+        self.switch = Some((self.mono.len() as f64, speed.clamp(MIN_SPEED, MAX_SPEED)));
+    }
+
+    /// At normal speed with nothing held back, samples pass through untouched.
+    pub fn is_active(&self) -> bool { self.speed != 1.0 || self.natural.is_some() || !self.input.is_empty() }
+
+    /// Forget everything held back (seek).
+    pub fn reset(&mut self) {
+        self.input.clear();
+        self.mono.clear();
+        self.pos = 0.0;
+        self.natural = None;
+        self.switch = None;
+        self.acc.fill(0.0);
+    }
+
+    /// Output frames still to come from what is held back: output frame `k` stands for input
+    /// frame `k × speed`, and everything before `pos` has been emitted.
+    pub fn pending_frames(&self) -> usize {
+        if self.natural.is_none() && self.input.is_empty() { return 0; }
+        let end = self.mono.len() as f64;
+        let frames = match self.switch {
+            Some((at, speed)) => (at - self.pos).max(0.0) / self.speed as f64 + (end - at.max(self.pos)) / speed as f64,
+            None => (end - self.pos).max(0.0) / self.speed as f64,
+        };
+        frames.round() as usize
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if !self.is_active() { return input.to_vec(); }
+        let ch = self.channels;
+        self.input.extend_from_slice(input);
+        self.mono.extend(input.chunks_exact(ch).map(|f| f.iter().sum::<f32>() / ch as f32));
+
+        let mut out = Vec::with_capacity((input.len() as f64 / self.speed as f64) as usize + self.hop * ch);
+        while self.step(&mut out) {}
+        self.trim();
+        // back to normal speed: nothing to stretch any more
+        if self.speed == 1.0 && self.switch.is_none() { out.extend(self.flush()); }
+        out
+    }
+
+    /// Play out what is held back (end of stream), then start over.
+    pub fn flush(&mut self) -> Vec<f32> {
+        if self.natural.is_none() && self.input.is_empty() { return Vec::new(); }
+        let wanted = self.pending_frames() * self.channels;
+        let end = self.mono.len() as f64;
+        let pad = self.frame + 2 * self.tolerance + self.hop;
+        self.input.resize(self.input.len() + pad * self.channels, 0.0);
+        self.mono.resize(self.mono.len() + pad, 0.0);
+
+        let mut out = Vec::new();
+        while self.pos < end && self.step(&mut out) {}
+        // the second half of the last frame
+        out.extend_from_slice(&self.acc[..self.hop * self.channels]);
+        // not the padding, though
+        out.truncate(wanted);
+        if let Some((_, speed)) = self.switch { self.speed = speed; }
+        self.reset();
+        out
+    }
+
+    /// Lay down one frame and emit `hop` finished output frames, if enough input is there.
+    fn step(&mut self, out: &mut Vec<f32>) -> bool {
+        let ch = self.channels;
+        let avail = self.mono.len();
+        let nominal = self.pos.round() as usize;
+        if nominal + self.tolerance + self.frame > avail || self.natural.is_some_and(|n| n + self.frame > avail) {
+            return false;
+        }
+
+        let start = match self.natural {
+            Some(natural) => self.align(nominal, natural),
+            None => nominal,
+        };
+        let first = self.natural.is_none();
+        for j in 0..self.frame {
+            // the very first frame has nothing to overlap with: no fade-in
+            let w = if first && j < self.hop { 1.0 } else { self.window[j] };
+            let src = &self.input[(start + j) * ch..(start + j + 1) * ch];
+            for (a, x) in self.acc[j * ch..(j + 1) * ch].iter_mut().zip(src) { *a += w * x; }
+        }
+        out.extend_from_slice(&self.acc[..self.hop * ch]);
+        self.acc.copy_within(self.hop * ch.., 0);
+        let tail = self.acc.len() - self.hop * ch;
+        self.acc[tail..].fill(0.0);
+
+        self.natural = Some(start + self.hop);
+        self.pos += self.hop as f64 * self.speed as f64;
+        if let Some((at, speed)) = self.switch {
+            // the part of this hop past the switch counts at the new speed
+            if self.pos >= at {
+                self.pos = at + (self.pos - at) * speed as f64 / self.speed as f64;
+                self.speed = speed;
+                self.switch = None;
+            }
+        }
+        true
+    }
+
+    /// The start within `nominal ± tolerance` whose first half looks most like the first half
+    /// of the frame at `natural` (normalized cross-correlation): coarse, then refined.
+    fn align(&self, nominal: usize, natural: usize) -> usize {
+        let template = &self.mono[natural..natural + self.hop];
+        let score = |k: usize, stride: usize| {
+            let cand = &self.mono[k..k + self.hop];
+            let (mut dot, mut energy) = (0.0f32, 1e-9f32);
+            for i in (0..self.hop).step_by(stride) {
+                dot += template[i] * cand[i];
+                energy += cand[i] * cand[i];
+            }
+            dot / energy.sqrt()
+        };
+        let lo = nominal.saturating_sub(self.tolerance);
+        let hi = nominal + self.tolerance;
+        let best = |range: &mut dyn Iterator<Item = usize>, stride: usize| {
+            range.map(|k| (k, score(k, stride))).fold((nominal, f32::MIN), |b, c| if c.1 > b.1 { c } else { b }).0
+        };
+        let coarse = best(&mut (lo..=hi).step_by(COARSE), COARSE);
+        best(&mut (coarse.saturating_sub(COARSE - 1).max(lo)..=(coarse + COARSE - 1).min(hi)), 1)
+    }
+
+    /// Drop input no frame will read again.
+    fn trim(&mut self) {
+        let Some(natural) = self.natural else { return };
+        let used = (self.pos.floor() as usize).saturating_sub(self.tolerance).min(natural);
+        if used == 0 { return; }
+        self.input.drain(..used * self.channels);
+        self.mono.drain(..used);
+        self.pos -= used as f64;
+        self.natural = Some(natural - used);
+        if let Some((at, _)) = &mut self.switch { *at -= used as f64; }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 48_000;
+
+    /// `secs` of a stereo tone with some vibrato, so the alignment has something to chew on.
+    fn tone(secs: f32) -> Vec<f32> {
+        (0..(secs * SR as f32) as usize)
+            .flat_map(|i| {
+                let t = i as f32 / SR as f32;
+                let x = 0.5 * (2.0 * std::f32::consts::PI * (220.0 * t + 2.0 * (3.0 * t).sin())).sin();
+                [x, 0.5 * x]
+            })
+            .collect()
+    }
+
+    /// Feed `input` in uneven chunks; returns the output frames of each `process` call and of the
+    /// final `flush`, checking `pending_frames` along the way.
+    fn stretch(ts: &mut TimeStretch, input: &[f32]) -> (usize, usize) {
+        let mut played = 0;
+        for chunk in input.chunks(2 * 1_111) {
+            played += ts.process(chunk).len() / 2;
+        }
+        let pending = ts.pending_frames();
+        let rest = ts.flush().len() / 2;
+        assert_eq!(rest, pending, "flush plays what pending_frames promised");
+        assert_eq!(ts.pending_frames(), 0);
+        (played, rest)
+    }
+
+    #[test]
+    fn output_length_follows_speed() {
+        let input = tone(2.0);
+        let frames = input.len() / 2;
+        for speed in [0.5, 0.75, 1.0, 1.25, 1.5, 2.0, 2.5, 3.0] {
+            let mut ts = TimeStretch::new(SR, 2, speed);
+            let (played, rest) = stretch(&mut ts, &input);
+            let expected = (frames as f32 / speed).round() as usize;
+            assert!((played + rest).abs_diff(expected) <= 1, "{speed}×: {} frames, not {expected}", played + rest);
+        }
+    }
+
+    #[test]
+    fn speed_change_after_held_back_input() {
+        let input = tone(1.0);
+        let frames = input.len() / 2;
+        for (from, to) in [(1.5, 0.75), (0.5, 3.0), (3.0, 1.0)] {
+            let mut ts = TimeStretch::new(SR, 2, from);
+            let mut played = 0;
+            for chunk in input.chunks(2 * 1_111) { played += ts.process(chunk).len() / 2; }
+            // what is held back still plays at the old speed, the next second at the new one
+            ts.set_speed_after(to);
+            for chunk in input.chunks(2 * 1_111) { played += ts.process(chunk).len() / 2; }
+            let pending = ts.pending_frames();
+            let rest = ts.flush().len() / 2;
+            assert_eq!(rest, pending);
+            let expected = (frames as f32 / from + frames as f32 / to).round() as usize;
+            assert!((played + rest).abs_diff(expected) <= 2, "{from}× → {to}×: {} frames, not {expected}", played + rest);
+        }
+    }
+
+    #[test]
+    fn back_to_normal_speed_passes_through() {
+        let input = tone(1.0);
+        let frames = input.len() / 2;
+        let (first, rest) = input.split_at(input.len() / 2);
+        let mut ts = TimeStretch::new(SR, 2, 2.0);
+        let mut played = ts.process(first).len() / 2;
+        assert!(ts.is_active());
+        // input held back at 2× plays at 1× from now on
+        let expected = played + ts.pending_frames() * 2 + (frames - frames / 2);
+
+        ts.set_speed(1.0);
+        let (block, rest) = rest.split_at(2 * 1_000);
+        played += ts.process(block).len() / 2;
+        assert!(!ts.is_active(), "nothing held back");
+        assert_eq!(ts.process(rest), rest, "untouched");
+        played += rest.len() / 2;
+        assert!(played.abs_diff(expected) <= 2, "{played} frames, not {expected}");
+
+        // the same after a switch to 1.0 at a track change
+        let mut ts = TimeStretch::new(SR, 2, 1.5);
+        ts.process(first);
+        ts.set_speed_after(1.0);
+        ts.process(&input);
+        assert!(!ts.is_active());
+        assert_eq!(ts.process(first), first);
+    }
+}
@@ -0,0 +1,133 @@
+//! Numbered schema migrations tracked in `PRAGMA user_version`.
+//!
+//! `MIGRATIONS[i]` upgrades a database from version `i` to `i + 1`. Steps are append-only:
+//! never edit one that has shipped, add a new file instead.
+
+use rusqlite::{Connection, TransactionBehavior};
+
+pub const MIGRATIONS: &[&str] = &[
+    include_str!("migrations/0001_initial.sql"),
+    include_str!("migrations/0002_playlist_items.sql"),
+    include_str!("migrations/0003_tracks_fts.sql"),
+    include_str!("migrations/0004_track_numbers.sql"),
+    include_str!("migrations/0005_file_state.sql"),
+    include_str!("migrations/0006_crossfade.sql"),
+    include_str!("migrations/0007_resample_quality.sql"),
+    include_str!("migrations/0008_replaygain.sql"),
+    include_str!("migrations/0009_loudness.sql"),
+    include_str!("migrations/0010_dsp.sql"),
+    include_str!("migrations/0011_session.sql"),
+    include_str!("migrations/0012_output_device.sql"),
+    include_str!("migrations/0013_bit_perfect.sql"),
+    include_str!("migrations/0014_latency.sql"),
+    include_str!("migrations/0015_noise_shaping.sql"),
+    include_str!("migrations/0016_channel_matrix.sql"),
+    include_str!("migrations/0017_transport_fade.sql"),
+    include_str!("migrations/0018_playback_speed.sql"),
+];
+
+/// Schema version this binary expects.
+pub const LATEST_VERSION: u32 = MIGRATIONS.len() as u32;
+
+pub fn user_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.pragma_query_value(None, "user_version", |r| r.get(0))
+}
+
+/// Bring `conn` up to [`LATEST_VERSION`]. Each step runs in its own transaction together with
+/// the `user_version` bump, so a failed step leaves the database at the previous version.
+///
+/// Foreign keys are not enforced while migrating (as SQLite advises for schema changes): older
+/// databases may hold orphan rows, which v2 removes.
+pub fn migrate(conn: &mut Connection) -> anyhow::Result<()> {
+    let current = user_version(conn)?;
+    if current > LATEST_VERSION {
+        anyhow::bail!(
+            "database schema version {current} is newer than this build supports ({LATEST_VERSION}); \
+             refusing to open it"
+        );
+    }
+
+    let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |r| r.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = run_steps(conn, current);
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    result
+}
+
+fn run_steps(conn: &mut Connection, current: u32) -> anyhow::Result<()> {
+    for (idx, sql) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = idx as u32 + 1;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute_batch(sql)
+            .map_err(|e| anyhow::anyhow!("migration {version} failed: {e}"))?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        log::info!("db: migrated schema to version {version}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::repo::settings;
+
+    fn migrated() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn migrates_an_empty_database_to_latest() {
+        let mut conn = migrated();
+        assert_eq!(user_version(&conn).unwrap(), LATEST_VERSION);
+        // already current: nothing to do
+        migrate(&mut conn).unwrap();
+        assert_eq!(user_version(&conn).unwrap(), LATEST_VERSION);
+    }
+
+    #[test]
+    fn refuses_a_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", LATEST_VERSION + 1).unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+
+    #[test]
+    fn settings_defaults_after_seed() {
+        let conn = migrated();
+        settings::seed(&conn, "/music").unwrap();
+        let s = settings::get(&conn).unwrap();
+        assert_eq!(s.managed_root, "/music");
+        assert!(s.use_managed_dir);
+        assert_eq!(settings::transport_fade_ms(&conn).unwrap(), 30);
+        assert_eq!(settings::playback_speed(&conn).unwrap(), 1.0);
+        assert_eq!(settings::latency(&conn).unwrap(), ("safe".to_string(), None));
+    }
+
+    #[test]
+    fn orphans_are_cleaned_up() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // an unversioned database written with foreign keys off, as before v2
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.execute_batch(
+            "INSERT INTO artists (id, name) VALUES (1, 'a');
+             INSERT INTO tracks (id, title, file_path, file_hash, album_id) VALUES (1, 't', '/t', 'h', 99);
+             INSERT INTO track_artists (track_id, artist_id) VALUES (1, 1), (2, 1);
+             INSERT INTO playlists (id, title) VALUES (1, 'p');
+             INSERT INTO playlist_tracks (playlist_id, track_id, position) VALUES (1, 1, 0), (1, 7, 1), (5, 1, 0);",
+        ).unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let count = |sql: &str| conn.query_row(sql, [], |r| r.get::<_, i64>(0)).unwrap();
+        assert_eq!(count("SELECT COUNT(*) FROM track_artists"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM playlist_items"), 1);
+        assert_eq!(count("SELECT COUNT(*) FROM tracks WHERE album_id IS NULL"), 1);
+        let violations = conn.prepare("PRAGMA foreign_key_check").unwrap().query_map([], |_| Ok(())).unwrap().count();
+        assert_eq!(violations, 0);
+        assert!(conn.pragma_query_value(None, "foreign_keys", |r| r.get::<_, bool>(0)).unwrap());
+    }
+}
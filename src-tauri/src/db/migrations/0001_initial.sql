@@ -1,5 +1,5 @@
-PRAGMA journal_mode = WAL;
-PRAGMA foreign_keys = ON;
+-- v1: baseline schema. Kept idempotent (IF NOT EXISTS) so databases created before
+-- versioning was introduced (user_version = 0) adopt it without changes.
 
 -- SETTINGS
 CREATE TABLE IF NOT EXISTS settings (
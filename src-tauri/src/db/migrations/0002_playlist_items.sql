@@ -0,0 +1,39 @@
+-- v2: playlists are ordered lists of entries; the same track may appear more than once.
+-- Foreign keys are enforced from this version on, but older databases may hold rows pointing
+-- at parents that are gone: remove them (or clear the reference where it is optional) first.
+
+DELETE FROM album_artists   WHERE album_id    NOT IN (SELECT id FROM albums)
+                               OR artist_id   NOT IN (SELECT id FROM artists);
+DELETE FROM track_artists   WHERE track_id    NOT IN (SELECT id FROM tracks)
+                               OR artist_id   NOT IN (SELECT id FROM artists);
+DELETE FROM playlist_tracks WHERE playlist_id NOT IN (SELECT id FROM playlists)
+                               OR track_id    NOT IN (SELECT id FROM tracks);
+UPDATE tracks SET album_id = NULL WHERE album_id NOT IN (SELECT id FROM albums);
+
+ALTER TABLE playlists RENAME COLUMN title TO name;
+
+CREATE TABLE playlist_items (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    playlist_id INTEGER NOT NULL REFERENCES playlists(id) ON DELETE CASCADE,
+    track_id    INTEGER NOT NULL REFERENCES tracks(id)    ON DELETE CASCADE,
+    position    INTEGER NOT NULL,  -- 1-based, dense within a playlist
+    added_at    INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
+
+INSERT INTO playlist_items (playlist_id, track_id, position)
+SELECT playlist_id, track_id,
+       ROW_NUMBER() OVER (PARTITION BY playlist_id ORDER BY position, track_id)
+FROM playlist_tracks;
+
+DROP TABLE playlist_tracks;
+
+CREATE INDEX idx_playlist_items_pos ON playlist_items(playlist_id, position);
+CREATE INDEX idx_playlist_items_track ON playlist_items(track_id);
+
+-- Keep positions dense when an entry goes away, including via the tracks(id) cascade.
+CREATE TRIGGER trg_playlist_items_compact AFTER DELETE ON playlist_items
+BEGIN
+    UPDATE playlist_items
+       SET position = position - 1
+     WHERE playlist_id = OLD.playlist_id AND position > OLD.position;
+END;
@@ -0,0 +1,78 @@
+-- v3: full-text index over tracks (title, artist names, album title, file name).
+-- rowid = tracks.id. Kept in sync by the triggers below; see db::repo::search.
+
+CREATE VIRTUAL TABLE tracks_fts USING fts5(
+    title, artist, album, file,
+    tokenize = 'unicode61 remove_diacritics 2',
+    prefix = '2 3'
+);
+
+-- What gets indexed for a track. The file column is the base name of file_path.
+CREATE VIEW tracks_fts_source AS
+SELECT
+    t.id,
+    t.title,
+    IFNULL((
+      SELECT GROUP_CONCAT(ar.name, ' ')
+      FROM track_artists ta
+      JOIN artists ar ON ar.id = ta.artist_id
+      WHERE ta.track_id = t.id
+    ), '') AS artist,
+    IFNULL((SELECT al.title FROM albums al WHERE al.id = t.album_id), '') AS album,
+    substr(replace(t.file_path, '\', '/'),
+           length(rtrim(replace(t.file_path, '\', '/'),
+                        replace(replace(t.file_path, '\', '/'), '/', ''))) + 1) AS file
+FROM tracks t;
+
+INSERT INTO tracks_fts (rowid, title, artist, album, file)
+SELECT id, title, artist, album, file FROM tracks_fts_source;
+
+CREATE TRIGGER trg_tracks_fts_ai AFTER INSERT ON tracks
+BEGIN
+    INSERT INTO tracks_fts (rowid, title, artist, album, file)
+    SELECT id, title, artist, album, file FROM tracks_fts_source WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_tracks_fts_au AFTER UPDATE OF title, album_id, file_path ON tracks
+BEGIN
+    DELETE FROM tracks_fts WHERE rowid = OLD.id;
+    INSERT INTO tracks_fts (rowid, title, artist, album, file)
+    SELECT id, title, artist, album, file FROM tracks_fts_source WHERE id = NEW.id;
+END;
+
+CREATE TRIGGER trg_tracks_fts_ad AFTER DELETE ON tracks
+BEGIN
+    DELETE FROM tracks_fts WHERE rowid = OLD.id;
+END;
+
+CREATE TRIGGER trg_track_artists_fts_ai AFTER INSERT ON track_artists
+BEGIN
+    DELETE FROM tracks_fts WHERE rowid = NEW.track_id;
+    INSERT INTO tracks_fts (rowid, title, artist, album, file)
+    SELECT id, title, artist, album, file FROM tracks_fts_source WHERE id = NEW.track_id;
+END;
+
+CREATE TRIGGER trg_track_artists_fts_ad AFTER DELETE ON track_artists
+BEGIN
+    DELETE FROM tracks_fts WHERE rowid = OLD.track_id;
+    INSERT INTO tracks_fts (rowid, title, artist, album, file)
+    SELECT id, title, artist, album, file FROM tracks_fts_source WHERE id = OLD.track_id;
+END;
+
+CREATE TRIGGER trg_artists_fts_au AFTER UPDATE OF name ON artists
+BEGIN
+    DELETE FROM tracks_fts
+     WHERE rowid IN (SELECT track_id FROM track_artists WHERE artist_id = NEW.id);
+    INSERT INTO tracks_fts (rowid, title, artist, album, file)
+    SELECT id, title, artist, album, file FROM tracks_fts_source
+     WHERE id IN (SELECT track_id FROM track_artists WHERE artist_id = NEW.id);
+END;
+
+CREATE TRIGGER trg_albums_fts_au AFTER UPDATE OF title ON albums
+BEGIN
+    DELETE FROM tracks_fts
+     WHERE rowid IN (SELECT id FROM tracks WHERE album_id = NEW.id);
+    INSERT INTO tracks_fts (rowid, title, artist, album, file)
+    SELECT id, title, artist, album, file FROM tracks_fts_source
+     WHERE id IN (SELECT id FROM tracks WHERE album_id = NEW.id);
+END;
@@ -0,0 +1,6 @@
+-- v4: track/disc numbers read from tags during import.
+
+ALTER TABLE tracks ADD COLUMN track_no INTEGER;
+ALTER TABLE tracks ADD COLUMN disc_no  INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_tracks_album_order ON tracks(album_id, disc_no, track_no);
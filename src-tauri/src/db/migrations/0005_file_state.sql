@@ -0,0 +1,7 @@
+-- v5: on-disk state per track for incremental rescans.
+-- NULL size/mtime (rows from before v5) just means "re-check by hash on next rescan".
+
+ALTER TABLE tracks ADD COLUMN file_size     INTEGER;
+ALTER TABLE tracks ADD COLUMN file_mtime    INTEGER;            -- ms since the Unix epoch
+ALTER TABLE tracks ADD COLUMN available     INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE tracks ADD COLUMN missing_since INTEGER;            -- unix seconds, set when available = 0
@@ -0,0 +1,5 @@
+-- v6: crossfade between queue items. 0 seconds = off (plain gapless).
+
+ALTER TABLE settings ADD COLUMN crossfade_secs            REAL    NOT NULL DEFAULT 0;
+ALTER TABLE settings ADD COLUMN crossfade_curve           TEXT    NOT NULL DEFAULT 'equal_power';
+ALTER TABLE settings ADD COLUMN crossfade_skip_same_album INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,3 @@
+-- v7: resampler quality preset: 'fast', 'balanced' or 'high'.
+
+ALTER TABLE settings ADD COLUMN resample_quality TEXT NOT NULL DEFAULT 'balanced';
@@ -0,0 +1,9 @@
+-- v8: ReplayGain values read from tags (dB relative to the ReplayGain reference, linear peaks),
+-- and the playback normalization mode: 'off', 'track', 'album' or 'auto'.
+
+ALTER TABLE tracks ADD COLUMN rg_track_gain REAL;
+ALTER TABLE tracks ADD COLUMN rg_track_peak REAL;
+ALTER TABLE tracks ADD COLUMN rg_album_gain REAL;
+ALTER TABLE tracks ADD COLUMN rg_album_peak REAL;
+
+ALTER TABLE settings ADD COLUMN replaygain_mode TEXT NOT NULL DEFAULT 'off';
@@ -0,0 +1,38 @@
+-- v9: measured loudness (EBU R128 / BS.1770) for files without usable ReplayGain tags.
+-- Histograms hold the gated block loudness distribution so album values can be recomputed
+-- from their tracks without decoding them again. `file_hash` is the content that was measured.
+-- Tracks whose analysis failed are skipped (and left out of album values) until their content
+-- changes. `rg_written` marks ReplayGain values the analysis wrote into the file itself, as
+-- opposed to tags the file came with; only those are rewritten when an album value changes.
+
+CREATE TABLE IF NOT EXISTS track_loudness (
+    track_id         INTEGER PRIMARY KEY REFERENCES tracks(id) ON DELETE CASCADE,
+    file_hash        TEXT NOT NULL,
+    integrated_lufs  REAL,                 -- NULL for digital silence
+    true_peak        REAL NOT NULL,        -- linear, 1.0 = 0 dBTP
+    loudness_range   REAL,                 -- LU
+    momentary_hist   BLOB NOT NULL,
+    short_term_hist  BLOB NOT NULL,
+    analyzed_at      INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
+
+CREATE TABLE IF NOT EXISTS album_loudness (
+    album_id         INTEGER PRIMARY KEY REFERENCES albums(id) ON DELETE CASCADE,
+    integrated_lufs  REAL,
+    true_peak        REAL NOT NULL,
+    loudness_range   REAL,
+    analyzed_at      INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
+
+CREATE TABLE IF NOT EXISTS track_loudness_failures (
+    track_id   INTEGER PRIMARY KEY REFERENCES tracks(id) ON DELETE CASCADE,
+    file_hash  TEXT NOT NULL,
+    reason     TEXT NOT NULL,
+    failed_at  INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
+
+ALTER TABLE tracks ADD COLUMN rg_written INTEGER NOT NULL DEFAULT 0;
+
+-- A running analysis is resumed on the next start.
+ALTER TABLE settings ADD COLUMN loudness_job_active     INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE settings ADD COLUMN loudness_job_write_tags INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,16 @@
+-- v10: output DSP chain. Configs are JSON (see audio::dsp::DspConfig).
+-- settings.dsp_config applies to devices without a profile of their own; NULL = bypass.
+
+ALTER TABLE settings ADD COLUMN dsp_config TEXT;
+
+CREATE TABLE IF NOT EXISTS dsp_profiles (
+    device_name  TEXT PRIMARY KEY,
+    config       TEXT NOT NULL,
+    updated_at   INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
+
+CREATE TABLE IF NOT EXISTS dsp_presets (
+    name         TEXT PRIMARY KEY COLLATE NOCASE,
+    config       TEXT NOT NULL,
+    created_at   INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
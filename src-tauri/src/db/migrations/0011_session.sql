@@ -0,0 +1,15 @@
+-- v11: playback session, restored (paused) on the next start. session_queue holds the queue in
+-- display order; play_pos is each item's place in the play order, which only differs from idx
+-- when shuffled. track_id lets a track that was moved within the library be found again.
+
+ALTER TABLE settings ADD COLUMN volume REAL NOT NULL DEFAULT 1.0;
+ALTER TABLE settings ADD COLUMN play_mode TEXT NOT NULL DEFAULT 'off';
+ALTER TABLE settings ADD COLUMN session_current INTEGER;
+ALTER TABLE settings ADD COLUMN session_position REAL NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS session_queue (
+    idx        INTEGER PRIMARY KEY,
+    play_pos   INTEGER NOT NULL,
+    file_path  TEXT NOT NULL,
+    track_id   INTEGER REFERENCES tracks(id) ON DELETE SET NULL
+);
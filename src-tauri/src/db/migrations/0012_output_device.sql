@@ -0,0 +1,3 @@
+-- v12: chosen output device by name; NULL = follow the system default.
+
+ALTER TABLE settings ADD COLUMN output_device TEXT;
@@ -0,0 +1,3 @@
+-- v13: bit-perfect output (stream opened at each track's own format, no processing).
+
+ALTER TABLE settings ADD COLUMN bit_perfect INTEGER NOT NULL DEFAULT 0;
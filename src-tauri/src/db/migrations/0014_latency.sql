@@ -0,0 +1,5 @@
+-- v14: latency profile (device buffer, prebuffer and decoder read-ahead).
+
+ALTER TABLE settings ADD COLUMN latency_profile TEXT NOT NULL DEFAULT 'safe';
+-- device buffer in frames for the 'custom' profile
+ALTER TABLE settings ADD COLUMN latency_frames INTEGER;
@@ -0,0 +1,3 @@
+-- v15: noise-shaped dither when the output device takes integer samples.
+
+ALTER TABLE settings ADD COLUMN noise_shaping INTEGER NOT NULL DEFAULT 0;
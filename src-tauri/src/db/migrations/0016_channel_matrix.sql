@@ -0,0 +1,3 @@
+-- v16: down/upmix matrix between source and output channel layouts (JSON, NULL = standard).
+
+ALTER TABLE settings ADD COLUMN channel_matrix TEXT;
@@ -0,0 +1,3 @@
+-- v17: length of the fades on pause, resume, stop and skip.
+
+ALTER TABLE settings ADD COLUMN transport_fade_ms INTEGER NOT NULL DEFAULT 30;
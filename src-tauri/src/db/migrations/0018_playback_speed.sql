@@ -0,0 +1,10 @@
+-- v18: playback speed (time-stretched, pitch kept), optionally remembered per file.
+
+ALTER TABLE settings ADD COLUMN playback_speed       REAL    NOT NULL DEFAULT 1.0;
+ALTER TABLE settings ADD COLUMN remember_track_speed INTEGER NOT NULL DEFAULT 0;
+
+-- Keyed by path so files played from outside the library are remembered too.
+CREATE TABLE IF NOT EXISTS track_speeds (
+    file_path  TEXT PRIMARY KEY,
+    speed      REAL NOT NULL
+);
@@ -1,5 +1,5 @@
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 use anyhow::Context;
 use directories::ProjectDirs;
@@ -7,47 +7,44 @@ use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::Connection;
 
+pub mod migrate;
+pub mod repo;
+
 pub type DbPool = Pool<SqliteConnectionManager>;
 
 pub fn init_db() -> anyhow::Result<DbPool> {
     let proj = ProjectDirs::from("com", "Resonix", "Resonix")
         .ok_or_else(|| anyhow::anyhow!("ProjectDirs"))?;
-    std::fs::create_dir_all(proj.data_dir())?;
+    fs::create_dir_all(proj.data_dir())?;
 
     let db_path = proj.data_dir().join("resonix.db");
-    let manager = SqliteConnectionManager::file(db_path);
-    let pool = Pool::new(manager)?;
 
+    // Migrate on a dedicated connection before the pool hands any out.
     {
-        let conn = pool.get()?;
-        conn.execute_batch(include_str!("schema.sql"))?;
-
-        // Seed default settings row
-        let managed = default_managed_root();
-        std::fs::create_dir_all(&managed)?;
-        conn.execute(
-            "INSERT OR IGNORE INTO settings (id, library_root, use_managed_dir, managed_root)
-             VALUES (1, NULL, 1, ?1)",
-            [&managed.to_string_lossy().to_string()],
-        )?;
+        let mut conn = Connection::open(&db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        configure_connection(&mut conn)?;
+        migrate::migrate(&mut conn).context("schema migration")?;
+        ensure_defaults(&conn)?;
     }
 
+    let manager = SqliteConnectionManager::file(db_path).with_init(configure_connection);
+    let pool = Pool::new(manager)?;
     Ok(pool)
 }
 
-fn run_migrations(conn: &Connection) -> anyhow::Result<()> {
-    let schema = include_str!("schema.sql");
-    conn.execute_batch(schema)?;
-    Ok(())
+/// Per-connection settings; `foreign_keys` is not persisted in the file.
+fn configure_connection(conn: &mut Connection) -> rusqlite::Result<()> {
+    conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000;")
 }
 
 fn ensure_defaults(conn: &Connection) -> anyhow::Result<()> {
-    // If managed_root is NULL, set a sensible default
+    // Seed default settings row; if managed_root is NULL, set a sensible default
     let default_managed = default_managed_root();
-    conn.execute(
-        "UPDATE settings SET managed_root = COALESCE(managed_root, ?1) WHERE id=1",
-        [default_managed.to_string_lossy()],
-    )?;
+    fs::create_dir_all(&default_managed)?;
+    let default_managed = default_managed.to_string_lossy();
+    repo::settings::seed(conn, &default_managed)?;
+    repo::settings::ensure_managed_root(conn, &default_managed)?;
     Ok(())
 }
 
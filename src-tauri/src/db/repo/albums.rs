@@ -0,0 +1,52 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::{RepoError, RepoResult};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Album {
+    pub id: i64,
+    pub title: String,
+    pub year: Option<i32>,
+}
+
+pub fn list(conn: &Connection) -> RepoResult<Vec<Album>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, title, year FROM albums ORDER BY title COLLATE NOCASE, IFNULL(year,0)",
+    )?;
+    let rows = stmt.query_map([], |r| Ok(Album { id: r.get(0)?, title: r.get(1)?, year: r.get(2)? }))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Insert the album if `(title, year)` is new; returns its id. A missing year matches a missing year.
+pub fn upsert(conn: &Connection, title: &str, year: Option<i32>) -> RepoResult<i64> {
+    conn.execute("INSERT OR IGNORE INTO albums (title, year) VALUES (?1, ?2)", params![title, year])?;
+    if conn.changes() > 0 {
+        return Ok(conn.last_insert_rowid());
+    }
+    conn.query_row(
+        "SELECT id FROM albums WHERE title = ?1 AND COALESCE(year,0) = COALESCE(?2,0)",
+        params![title, year],
+        |r| r.get(0),
+    )
+    .optional()?
+    .ok_or(RepoError::NotFound("album"))
+}
+
+/// [`upsert`] plus linking the given artists. Run inside a transaction for atomicity.
+pub fn upsert_with_artists(conn: &Connection, title: &str, year: Option<i32>, artist_ids: &[i64]) -> RepoResult<i64> {
+    let album_id = upsert(conn, title, year)?;
+    let mut ins = conn.prepare_cached(
+        "INSERT OR IGNORE INTO album_artists (album_id, artist_id) VALUES (?1, ?2)",
+    )?;
+    for aid in artist_ids {
+        ins.execute(params![album_id, aid])?;
+    }
+    Ok(album_id)
+}
+
+pub fn title_of(conn: &Connection, id: i64) -> RepoResult<Option<String>> {
+    Ok(conn
+        .query_row("SELECT title FROM albums WHERE id = ?1", [id], |r| r.get(0))
+        .optional()?)
+}
@@ -0,0 +1,37 @@
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::{RepoError, RepoResult};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Artist {
+    pub id: i64,
+    pub name: String,
+}
+
+pub fn list(conn: &Connection) -> RepoResult<Vec<Artist>> {
+    let mut stmt = conn.prepare("SELECT id, name FROM artists ORDER BY name COLLATE NOCASE")?;
+    let rows = stmt.query_map([], |r| Ok(Artist { id: r.get(0)?, name: r.get(1)? }))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Insert the artist if no artist with that name (case-insensitive) exists; returns its id.
+pub fn upsert(conn: &Connection, name: &str) -> RepoResult<i64> {
+    conn.execute("INSERT OR IGNORE INTO artists (name) VALUES (?1)", [name])?;
+    if conn.changes() > 0 {
+        return Ok(conn.last_insert_rowid());
+    }
+    conn.query_row(
+        "SELECT id FROM artists WHERE name = ?1 COLLATE NOCASE",
+        [name],
+        |r| r.get(0),
+    )
+    .optional()?
+    .ok_or(RepoError::NotFound("artist"))
+}
+
+pub fn name_of(conn: &Connection, id: i64) -> RepoResult<Option<String>> {
+    Ok(conn
+        .query_row("SELECT name FROM artists WHERE id = ?1", [id], |r| r.get(0))
+        .optional()?)
+}
@@ -0,0 +1,62 @@
+//! Stored DSP chain configs. Values are opaque JSON strings owned by `audio::dsp`.
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::RepoResult;
+
+/// Config for output devices without a profile of their own.
+pub fn global_config(conn: &Connection) -> RepoResult<Option<String>> {
+    Ok(conn.query_row("SELECT dsp_config FROM settings WHERE id=1", [], |r| r.get(0))?)
+}
+
+pub fn set_global_config(conn: &Connection, json: &str) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET dsp_config = ?1 WHERE id=1", [json])?;
+    Ok(())
+}
+
+pub fn device_profile(conn: &Connection, device_name: &str) -> RepoResult<Option<String>> {
+    Ok(conn
+        .query_row("SELECT config FROM dsp_profiles WHERE device_name = ?1", [device_name], |r| r.get(0))
+        .optional()?)
+}
+
+pub fn set_device_profile(conn: &Connection, device_name: &str, json: &str) -> RepoResult<()> {
+    conn.execute(
+        "INSERT INTO dsp_profiles (device_name, config) VALUES (?1, ?2)
+         ON CONFLICT(device_name) DO UPDATE SET config = excluded.config, updated_at = strftime('%s','now')",
+        params![device_name, json],
+    )?;
+    Ok(())
+}
+
+pub fn delete_device_profile(conn: &Connection, device_name: &str) -> RepoResult<bool> {
+    Ok(conn.execute("DELETE FROM dsp_profiles WHERE device_name = ?1", [device_name])? > 0)
+}
+
+/// Devices that have a profile, by name.
+pub fn profile_devices(conn: &Connection) -> RepoResult<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT device_name FROM dsp_profiles ORDER BY device_name COLLATE NOCASE")?;
+    let rows = stmt.query_map([], |r| r.get(0))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// User presets as `(name, config)`, by name.
+pub fn presets(conn: &Connection) -> RepoResult<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT name, config FROM dsp_presets ORDER BY name COLLATE NOCASE")?;
+    let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Insert or overwrite a user preset.
+pub fn save_preset(conn: &Connection, name: &str, json: &str) -> RepoResult<()> {
+    conn.execute(
+        "INSERT INTO dsp_presets (name, config) VALUES (?1, ?2)
+         ON CONFLICT(name) DO UPDATE SET config = excluded.config",
+        params![name, json],
+    )?;
+    Ok(())
+}
+
+pub fn delete_preset(conn: &Connection, name: &str) -> RepoResult<bool> {
+    Ok(conn.execute("DELETE FROM dsp_presets WHERE name = ?1", [name])? > 0)
+}
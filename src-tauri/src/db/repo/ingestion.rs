@@ -0,0 +1,90 @@
+use rusqlite::{params, Connection};
+
+use super::tracks::{self, NewTrack, ReplayGain};
+use super::{albums, artists};
+use super::RepoResult;
+
+/// Register a track row and link its artists. Run inside a transaction for atomicity.
+pub fn register_track(conn: &Connection, track: &NewTrack<'_>, artist_ids: &[i64]) -> RepoResult<i64> {
+    let track_id = tracks::insert(conn, track)?;
+    tracks::link_artists(conn, track_id, artist_ids)?;
+    Ok(track_id)
+}
+
+/// A file's tag data, ready to be written. Artist and album names are upserted.
+#[derive(Debug, Clone)]
+pub struct TaggedTrack<'a> {
+    pub file_path: &'a str,
+    pub file_hash: &'a str,
+    pub title: &'a str,
+    pub duration_secs: f64,
+    pub artists: &'a [String],
+    /// Falls back to `artists` when empty.
+    pub album_artists: &'a [String],
+    pub album: Option<&'a str>,
+    pub year: Option<i32>,
+    pub track_no: Option<u32>,
+    pub disc_no: Option<u32>,
+    pub file_size: Option<i64>,
+    pub file_mtime: Option<i64>,
+    pub replay_gain: ReplayGain,
+}
+
+fn upsert_names(conn: &Connection, t: &TaggedTrack<'_>) -> RepoResult<(Vec<i64>, Option<i64>)> {
+    let artist_ids = t.artists.iter()
+        .map(|name| artists::upsert(conn, name))
+        .collect::<RepoResult<Vec<_>>>()?;
+
+    let album_id = match t.album {
+        Some(title) => {
+            let album_artist_ids = if t.album_artists.is_empty() {
+                artist_ids.clone()
+            } else {
+                t.album_artists.iter()
+                    .map(|name| artists::upsert(conn, name))
+                    .collect::<RepoResult<Vec<_>>>()?
+            };
+            Some(albums::upsert_with_artists(conn, title, t.year, &album_artist_ids)?)
+        }
+        None => None,
+    };
+    Ok((artist_ids, album_id))
+}
+
+/// Upsert the track's artists and album, then register it. Run inside a transaction.
+pub fn import_tagged(conn: &Connection, t: &TaggedTrack<'_>) -> RepoResult<i64> {
+    let (artist_ids, album_id) = upsert_names(conn, t)?;
+    let track_id = register_track(conn, &NewTrack {
+        title: t.title,
+        duration_secs: t.duration_secs,
+        file_path: t.file_path,
+        file_hash: t.file_hash,
+        album_id,
+        track_no: t.track_no,
+        disc_no: t.disc_no,
+        file_size: t.file_size,
+        file_mtime: t.file_mtime,
+    }, &artist_ids)?;
+    tracks::set_replay_gain(conn, track_id, &t.replay_gain)?;
+    Ok(track_id)
+}
+
+/// Overwrite an existing track with freshly read tags (after the file changed on disk).
+/// Artist links are replaced. Run inside a transaction.
+pub fn refresh_tagged(conn: &Connection, track_id: i64, t: &TaggedTrack<'_>) -> RepoResult<()> {
+    let (artist_ids, album_id) = upsert_names(conn, t)?;
+    conn.execute(
+        "UPDATE tracks
+            SET title=?2, duration_secs=?3, file_path=?4, file_hash=?5, album_id=?6,
+                track_no=?7, disc_no=?8, file_size=?9, file_mtime=?10,
+                available=1, missing_since=NULL
+          WHERE id=?1",
+        params![
+            track_id, t.title, t.duration_secs, t.file_path, t.file_hash, album_id,
+            t.track_no, t.disc_no, t.file_size, t.file_mtime
+        ],
+    )?;
+    tracks::set_replay_gain(conn, track_id, &t.replay_gain)?;
+    tracks::unlink_artists(conn, track_id)?;
+    tracks::link_artists(conn, track_id, &artist_ids)
+}
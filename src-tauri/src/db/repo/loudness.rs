@@ -0,0 +1,168 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::RepoResult;
+
+/// ReplayGain 2.0 reference level: a measured track gets `REFERENCE - integrated` dB of gain.
+pub const REPLAYGAIN_REFERENCE_LUFS: f64 = -18.0;
+
+/// Stored result of measuring one track. Histograms are opaque blobs written by
+/// `audio::loudness::Histogram::to_blob`.
+#[derive(Debug, Clone)]
+pub struct TrackLoudness {
+    pub integrated_lufs: Option<f64>,
+    pub true_peak: f64,
+    pub loudness_range: Option<f64>,
+    pub momentary_hist: Vec<u8>,
+    pub short_term_hist: Vec<u8>,
+}
+
+/// A track that has no measurement for its current content.
+#[derive(Debug, Clone)]
+pub struct PendingTrack {
+    pub id: i64,
+    pub file_path: String,
+    pub file_hash: String,
+    pub album_id: Option<i64>,
+}
+
+const PENDING_WHERE: &str = "
+    t.available = 1
+    AND NOT EXISTS (
+      SELECT 1 FROM track_loudness l WHERE l.track_id = t.id AND l.file_hash = t.file_hash
+    )
+    AND NOT EXISTS (
+      SELECT 1 FROM track_loudness_failures f WHERE f.track_id = t.id AND f.file_hash = t.file_hash
+    )";
+
+/// Next track to measure with an id above `after`, in id order.
+pub fn next_pending(conn: &Connection, after: i64) -> RepoResult<Option<PendingTrack>> {
+    Ok(conn
+        .query_row(
+            &format!(
+                "SELECT t.id, t.file_path, t.file_hash, t.album_id FROM tracks t
+                  WHERE t.id > ?1 AND {PENDING_WHERE} ORDER BY t.id LIMIT 1"
+            ),
+            [after],
+            |r| Ok(PendingTrack { id: r.get(0)?, file_path: r.get(1)?, file_hash: r.get(2)?, album_id: r.get(3)? }),
+        )
+        .optional()?)
+}
+
+pub fn pending_count(conn: &Connection) -> RepoResult<usize> {
+    Ok(conn.query_row(&format!("SELECT COUNT(*) FROM tracks t WHERE {PENDING_WHERE}"), [], |r| r.get::<_, i64>(0))? as usize)
+}
+
+/// Record the measurement of `file_hash`'s content for a track, replacing any older one.
+pub fn save_track(conn: &Connection, track_id: i64, file_hash: &str, m: &TrackLoudness) -> RepoResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO track_loudness
+           (track_id, file_hash, integrated_lufs, true_peak, loudness_range, momentary_hist, short_term_hist)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            track_id, file_hash, m.integrated_lufs, m.true_peak, m.loudness_range,
+            m.momentary_hist, m.short_term_hist
+        ],
+    )?;
+    conn.execute("DELETE FROM track_loudness_failures WHERE track_id=?1", [track_id])?;
+    Ok(())
+}
+
+/// Record that `file_hash`'s content could not be measured, so the track is no longer pending
+/// (and is left out of its album's value) until the file changes.
+pub fn save_failure(conn: &Connection, track_id: i64, file_hash: &str, reason: &str) -> RepoResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO track_loudness_failures (track_id, file_hash, reason) VALUES (?1, ?2, ?3)",
+        params![track_id, file_hash, reason],
+    )?;
+    Ok(())
+}
+
+/// After the file was rewritten without changing its audio (e.g. tags written back).
+pub fn set_measured_hash(conn: &Connection, track_id: i64, file_hash: &str) -> RepoResult<()> {
+    conn.execute("UPDATE track_loudness SET file_hash=?2 WHERE track_id=?1", params![track_id, file_hash])?;
+    Ok(())
+}
+
+/// Measurements of every available track on the album, or `None` while any is still pending.
+/// Tracks that failed to decode are left out.
+pub fn album_tracks(conn: &Connection, album_id: i64) -> RepoResult<Option<Vec<(i64, TrackLoudness)>>> {
+    let pending: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM tracks t WHERE t.album_id = ?1 AND {PENDING_WHERE}"),
+        [album_id],
+        |r| r.get(0),
+    )?;
+    if pending > 0 { return Ok(None); }
+
+    let mut stmt = conn.prepare(
+        "SELECT t.id, l.integrated_lufs, l.true_peak, l.loudness_range, l.momentary_hist, l.short_term_hist
+           FROM tracks t JOIN track_loudness l ON l.track_id = t.id AND l.file_hash = t.file_hash
+          WHERE t.album_id = ?1 AND t.available = 1
+          ORDER BY t.id",
+    )?;
+    let rows = stmt.query_map([album_id], |r| {
+        Ok((r.get(0)?, TrackLoudness {
+            integrated_lufs: r.get(1)?,
+            true_peak: r.get(2)?,
+            loudness_range: r.get(3)?,
+            momentary_hist: r.get(4)?,
+            short_term_hist: r.get(5)?,
+        }))
+    })?;
+    Ok(Some(rows.collect::<Result<_, _>>()?))
+}
+
+pub fn save_album(
+    conn: &Connection,
+    album_id: i64,
+    integrated_lufs: Option<f64>,
+    true_peak: f64,
+    loudness_range: Option<f64>,
+) -> RepoResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO album_loudness (album_id, integrated_lufs, true_peak, loudness_range)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![album_id, integrated_lufs, true_peak, loudness_range],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measured() -> TrackLoudness {
+        TrackLoudness {
+            integrated_lufs: Some(-20.0),
+            true_peak: 0.9,
+            loudness_range: None,
+            momentary_hist: Vec::new(),
+            short_term_hist: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn failed_tracks_are_not_pending_and_left_out_of_albums() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::migrate::migrate(&mut conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO albums (id, title) VALUES (1, 'a');
+             INSERT INTO tracks (id, title, file_path, file_hash, album_id)
+             VALUES (1, 'one', '/1', 'h1', 1), (2, 'two', '/2', 'h2', 1);",
+        ).unwrap();
+
+        save_track(&conn, 1, "h1", &measured()).unwrap();
+        assert!(album_tracks(&conn, 1).unwrap().is_none(), "track 2 still pending");
+
+        save_failure(&conn, 2, "h2", "unsupported codec").unwrap();
+        assert_eq!(pending_count(&conn).unwrap(), 0);
+        assert!(next_pending(&conn, 0).unwrap().is_none());
+        let rows = album_tracks(&conn, 1).unwrap().unwrap();
+        assert_eq!(rows.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [1]);
+
+        // retried once the file changes
+        conn.execute("UPDATE tracks SET file_hash='h2b' WHERE id=2", []).unwrap();
+        assert_eq!(next_pending(&conn, 0).unwrap().unwrap().id, 2);
+        save_track(&conn, 2, "h2b", &measured()).unwrap();
+        assert_eq!(album_tracks(&conn, 1).unwrap().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,58 @@
+//! Typed query layer over the SQLite schema.
+//!
+//! Every function takes a plain `&rusqlite::Connection` (a `Transaction` derefs to one),
+//! so the queries can be exercised against `Connection::open_in_memory()` without Tauri.
+//! Commands in `tauri_commands` are thin wrappers that map [`RepoError`] to a `String`.
+
+use std::fmt;
+
+pub mod albums;
+pub mod artists;
+pub mod dsp;
+pub mod ingestion;
+pub mod loudness;
+pub mod playlists;
+pub mod search;
+pub mod session;
+pub mod settings;
+pub mod speed;
+pub mod tracks;
+
+#[derive(Debug)]
+pub enum RepoError {
+    Sqlite(rusqlite::Error),
+    NotFound(&'static str),
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepoError::Sqlite(e) => write!(f, "database error: {e}"),
+            RepoError::NotFound(what) => write!(f, "{what} not found"),
+        }
+    }
+}
+
+impl std::error::Error for RepoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RepoError::Sqlite(e) => Some(e),
+            RepoError::NotFound(_) => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for RepoError {
+    fn from(e: rusqlite::Error) -> Self { RepoError::Sqlite(e) }
+}
+
+pub type RepoResult<T> = Result<T, RepoError>;
+
+/// Separator used when aggregating artist names with `GROUP_CONCAT`.
+/// A control character so names containing commas survive the round-trip.
+pub(crate) const ARTIST_SEP: &str = "\u{1f}";
+
+pub(crate) fn split_artists(csv: &str) -> Vec<String> {
+    if csv.is_empty() { return vec![]; }
+    csv.split(ARTIST_SEP).map(|s| s.to_string()).collect()
+}
@@ -0,0 +1,214 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use super::tracks::{self, Track, TRACK_COLUMNS, TRACK_COLUMN_COUNT};
+use super::{RepoError, RepoResult};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Playlist {
+    pub id: i64,
+    pub name: String,
+    pub track_count: i64,
+}
+
+/// One entry of a playlist. `entry_id` identifies the entry itself, so the same track can
+/// appear several times; `position` is 1-based and dense.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlaylistItem {
+    pub entry_id: i64,
+    pub position: i64,
+    #[serde(flatten)]
+    pub track: Track,
+}
+
+pub fn create(conn: &Connection, name: &str) -> RepoResult<i64> {
+    conn.execute("INSERT INTO playlists (name) VALUES (?1)", [name])?;
+    Ok(conn.last_insert_rowid())
+}
+
+pub fn rename(conn: &Connection, playlist_id: i64, name: &str) -> RepoResult<()> {
+    let n = conn.execute("UPDATE playlists SET name=?2 WHERE id=?1", params![playlist_id, name])?;
+    if n == 0 { return Err(RepoError::NotFound("playlist")); }
+    Ok(())
+}
+
+pub fn delete(conn: &Connection, playlist_id: i64) -> RepoResult<()> {
+    let n = conn.execute("DELETE FROM playlists WHERE id=?1", [playlist_id])?;
+    if n == 0 { return Err(RepoError::NotFound("playlist")); }
+    Ok(())
+}
+
+pub fn list(conn: &Connection) -> RepoResult<Vec<Playlist>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.id, p.name,
+                (SELECT COUNT(*) FROM playlist_items pi WHERE pi.playlist_id = p.id) AS track_count
+         FROM playlists p
+         ORDER BY p.name COLLATE NOCASE",
+    )?;
+    let rows = stmt.query_map([], |r| {
+        Ok(Playlist { id: r.get(0)?, name: r.get(1)?, track_count: r.get(2)? })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn len(conn: &Connection, playlist_id: i64) -> RepoResult<i64> {
+    Ok(conn.query_row(
+        "SELECT COUNT(*) FROM playlist_items WHERE playlist_id=?1",
+        [playlist_id],
+        |r| r.get(0),
+    )?)
+}
+
+/// Append a track; duplicates are allowed. Returns the new entry's position.
+pub fn add(conn: &Connection, playlist_id: i64, track_id: i64) -> RepoResult<i64> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM playlists WHERE id=?1)",
+        [playlist_id],
+        |r| r.get(0),
+    )?;
+    if !exists { return Err(RepoError::NotFound("playlist")); }
+
+    let pos = len(conn, playlist_id)? + 1;
+    conn.execute(
+        "INSERT INTO playlist_items (playlist_id, track_id, position) VALUES (?1, ?2, ?3)",
+        params![playlist_id, track_id, pos],
+    )?;
+    Ok(pos)
+}
+
+/// Remove the entry at `position`; later entries shift up (see `trg_playlist_items_compact`).
+pub fn remove(conn: &Connection, playlist_id: i64, position: i64) -> RepoResult<()> {
+    let n = conn.execute(
+        "DELETE FROM playlist_items WHERE playlist_id=?1 AND position=?2",
+        params![playlist_id, position],
+    )?;
+    if n == 0 { return Err(RepoError::NotFound("playlist entry")); }
+    Ok(())
+}
+
+/// Move the entry at `from` so it ends up at `to` (both 1-based). Run inside a transaction.
+pub fn move_item(conn: &Connection, playlist_id: i64, from: i64, to: i64) -> RepoResult<()> {
+    let entry_id: i64 = conn
+        .query_row(
+            "SELECT id FROM playlist_items WHERE playlist_id=?1 AND position=?2",
+            params![playlist_id, from],
+            |r| r.get(0),
+        )
+        .optional()?
+        .ok_or(RepoError::NotFound("playlist entry"))?;
+    let to = to.clamp(1, len(conn, playlist_id)?);
+    if to == from { return Ok(()); }
+
+    if from < to {
+        conn.execute(
+            "UPDATE playlist_items SET position = position - 1
+             WHERE playlist_id=?1 AND position > ?2 AND position <= ?3",
+            params![playlist_id, from, to],
+        )?;
+    } else {
+        conn.execute(
+            "UPDATE playlist_items SET position = position + 1
+             WHERE playlist_id=?1 AND position >= ?3 AND position < ?2",
+            params![playlist_id, from, to],
+        )?;
+    }
+    conn.execute("UPDATE playlist_items SET position=?2 WHERE id=?1", params![entry_id, to])?;
+    Ok(())
+}
+
+pub fn items(conn: &Connection, playlist_id: i64) -> RepoResult<Vec<PlaylistItem>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TRACK_COLUMNS}, pi.id, pi.position
+         FROM playlist_items pi
+         JOIN tracks t ON t.id = pi.track_id
+         WHERE pi.playlist_id = ?1
+         ORDER BY pi.position"
+    ))?;
+    let rows = stmt.query_map([playlist_id], |r| {
+        Ok(PlaylistItem {
+            entry_id: r.get(TRACK_COLUMN_COUNT)?,
+            position: r.get(TRACK_COLUMN_COUNT + 1)?,
+            track: tracks::from_row(r)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// File paths of the playlist in play order, for handing to the audio engine.
+/// Entries whose file is currently missing are left out.
+pub fn file_paths(conn: &Connection, playlist_id: i64) -> RepoResult<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.file_path
+         FROM playlist_items pi
+         JOIN tracks t ON t.id = pi.track_id
+         WHERE pi.playlist_id = ?1 AND t.available = 1
+         ORDER BY pi.position",
+    )?;
+    let rows = stmt.query_map([playlist_id], |r| r.get::<_, String>(0))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A playlist holding tracks 1..=5 in that order.
+    fn playlist() -> (Connection, i64) {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::migrate::migrate(&mut conn).unwrap();
+        for id in 1..=5 {
+            conn.execute(
+                "INSERT INTO tracks (id, title, file_path, file_hash) VALUES (?1, 't', '/' || ?1, 'h' || ?1)",
+                [id],
+            ).unwrap();
+        }
+        let playlist = create(&conn, "p").unwrap();
+        for id in 1..=5 { add(&conn, playlist, id).unwrap(); }
+        (conn, playlist)
+    }
+
+    /// (position, track) of every entry, in position order.
+    fn entries(conn: &Connection, playlist_id: i64) -> Vec<(i64, i64)> {
+        let mut stmt = conn
+            .prepare("SELECT position, track_id FROM playlist_items WHERE playlist_id=?1 ORDER BY position")
+            .unwrap();
+        stmt.query_map([playlist_id], |r| Ok((r.get(0)?, r.get(1)?))).unwrap().map(Result::unwrap).collect()
+    }
+
+    fn tracks(conn: &Connection, playlist_id: i64) -> Vec<i64> {
+        let entries = entries(conn, playlist_id);
+        let positions: Vec<i64> = entries.iter().map(|e| e.0).collect();
+        assert_eq!(positions, (1..=entries.len() as i64).collect::<Vec<_>>(), "positions stay dense");
+        entries.into_iter().map(|e| e.1).collect()
+    }
+
+    #[test]
+    fn move_item_keeps_positions_dense() {
+        let (conn, p) = playlist();
+        move_item(&conn, p, 2, 4).unwrap();
+        assert_eq!(tracks(&conn, p), [1, 3, 4, 2, 5]);
+        move_item(&conn, p, 5, 1).unwrap();
+        assert_eq!(tracks(&conn, p), [5, 1, 3, 4, 2]);
+        // past either end: clamped
+        move_item(&conn, p, 1, 99).unwrap();
+        assert_eq!(tracks(&conn, p), [1, 3, 4, 2, 5]);
+        move_item(&conn, p, 3, -1).unwrap();
+        assert_eq!(tracks(&conn, p), [4, 1, 3, 2, 5]);
+        move_item(&conn, p, 2, 2).unwrap();
+        assert_eq!(tracks(&conn, p), [4, 1, 3, 2, 5]);
+        assert!(matches!(move_item(&conn, p, 6, 1), Err(RepoError::NotFound(_))));
+    }
+
+    #[test]
+    fn removing_compacts_positions() {
+        let (conn, p) = playlist();
+        add(&conn, p, 2).unwrap();
+        remove(&conn, p, 2).unwrap();
+        assert_eq!(tracks(&conn, p), [1, 3, 4, 5, 2]);
+        // deleting a track drops its entries, and what follows moves up
+        conn.execute("DELETE FROM tracks WHERE id=3", []).unwrap();
+        assert_eq!(tracks(&conn, p), [1, 4, 5, 2]);
+        move_item(&conn, p, 4, 1).unwrap();
+        assert_eq!(tracks(&conn, p), [2, 1, 4, 5]);
+    }
+}
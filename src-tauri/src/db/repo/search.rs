@@ -0,0 +1,144 @@
+//! Ranked full-text search over `tracks_fts` (migration 0003).
+//!
+//! Query syntax: bare words match any field as prefixes (`beat` finds "Beatles"),
+//! `"quoted words"` match as a phrase, and `artist:`, `album:`, `title:`, `file:` restrict a
+//! word or quoted phrase to one field. Diacritics are folded by the tokenizer on both sides.
+
+use rusqlite::{params, Connection};
+
+use super::tracks::{self, Track, TRACK_COLUMNS};
+use super::RepoResult;
+
+/// Relative weights for `bm25()`, in `tracks_fts` column order: title, artist, album, file.
+const BM25_WEIGHTS: &str = "10.0, 6.0, 4.0, 1.0";
+
+pub fn tracks(conn: &Connection, q: &str, limit: u32) -> RepoResult<Vec<Track>> {
+    let Some(expr) = to_fts_query(q) else { return Ok(vec![]); };
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {TRACK_COLUMNS}
+         FROM tracks_fts f
+         JOIN tracks t ON t.id = f.rowid
+         WHERE tracks_fts MATCH ?1
+         ORDER BY bm25(tracks_fts, {BM25_WEIGHTS}), t.title COLLATE NOCASE
+         LIMIT ?2"
+    ))?;
+    let rows = stmt.query_map(params![expr, limit], tracks::from_row)?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Translate user input into an FTS5 MATCH expression; `None` if nothing searchable remains.
+/// Every term is emitted as a quoted prefix phrase so user input can't inject FTS5 syntax.
+fn to_fts_query(q: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut chars = q.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() { break; }
+
+        // optional `field:` prefix
+        let mut word = String::new();
+        let mut column = None;
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == '"' { break; }
+            chars.next();
+            if c == ':' && column.is_none() {
+                column = field_column(&word);
+                if column.is_some() { word.clear(); continue; }
+            }
+            word.push(c);
+        }
+
+        // value: a quoted phrase, or the bare word we just read
+        let value = if word.is_empty() && chars.peek() == Some(&'"') {
+            chars.next();
+            let mut phrase = String::new();
+            for c in chars.by_ref() {
+                if c == '"' { break; }
+                phrase.push(c);
+            }
+            phrase
+        } else {
+            word
+        };
+
+        if !value.chars().any(char::is_alphanumeric) { continue; }
+        let phrase = format!("\"{}\"*", value.replace('"', "\"\""));
+        terms.push(match column {
+            Some(col) => format!("{col} : {phrase}"),
+            None => phrase,
+        });
+    }
+
+    if terms.is_empty() { None } else { Some(terms.join(" ")) }
+}
+
+fn field_column(key: &str) -> Option<&'static str> {
+    match key.to_ascii_lowercase().as_str() {
+        "artist" | "by" => Some("artist"),
+        "album" => Some("album"),
+        "title" => Some("title"),
+        "file" => Some("file"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(q: &str) -> Option<String> { to_fts_query(q) }
+
+    #[test]
+    fn bare_words_are_prefix_phrases() {
+        assert_eq!(query("beat"), Some(r#""beat"*"#.into()));
+        assert_eq!(query("  abbey   road "), Some(r#""abbey"* "road"*"#.into()));
+    }
+
+    #[test]
+    fn field_filters() {
+        assert_eq!(query("artist:queen"), Some(r#"artist : "queen"*"#.into()));
+        assert_eq!(query("by:queen"), Some(r#"artist : "queen"*"#.into()));
+        assert_eq!(query("Album:opera title:love file:flac"), Some(r#"album : "opera"* title : "love"* file : "flac"*"#.into()));
+        // only the first colon names a field
+        assert_eq!(query("title:a:b"), Some(r#"title : "a:b"*"#.into()));
+    }
+
+    #[test]
+    fn quoted_phrases() {
+        assert_eq!(query(r#""night at the""#), Some(r#""night at the"*"#.into()));
+        assert_eq!(query(r#"album:"a night at""#), Some(r#"album : "a night at"*"#.into()));
+        // unterminated: runs to the end
+        assert_eq!(query(r#""bohemian rhap"#), Some(r#""bohemian rhap"*"#.into()));
+    }
+
+    #[test]
+    fn embedded_quotes_cannot_break_out() {
+        // a quote ends the word and starts a phrase
+        assert_eq!(query(r#"ab"cd ef""#), Some(r#""ab"* "cd ef"*"#.into()));
+        assert_eq!(query(r#"x" OR title:*"#), Some(r#""x"* " OR title:*"*"#.into()));
+    }
+
+    #[test]
+    fn nothing_searchable() {
+        assert_eq!(query(""), None);
+        assert_eq!(query("  - ** \"\" "), None);
+        assert_eq!(query("artist:"), None, "a bare field has no value");
+        assert_eq!(query("artist: queen"), Some(r#""queen"*"#.into()));
+    }
+
+    #[test]
+    fn unknown_fields_are_plain_words() {
+        assert_eq!(query("foo:bar"), Some(r#""foo:bar"*"#.into()));
+        assert_eq!(query("foo:"), Some(r#""foo:"*"#.into()));
+    }
+
+    #[test]
+    fn expressions_are_valid_fts5() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::migrate::migrate(&mut conn).unwrap();
+        for q in ["beat", r#"ab"cd ef""#, r#"x" OR title:*"#, "foo:bar", "title:a:b", "NOT AND ( ) ^"] {
+            assert!(tracks(&conn, q, 10).is_ok(), "{q}");
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! The playback session saved at exit (and periodically) and restored on the next start.
+
+use rusqlite::{params, Connection};
+
+use super::RepoResult;
+
+#[derive(Debug, Clone)]
+pub struct SessionItem {
+    pub file_path: String,
+    /// Place in the play order.
+    pub play_pos: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Queue in display order.
+    pub items: Vec<SessionItem>,
+    /// Index into `items` of the current track.
+    pub current: Option<usize>,
+    pub position_secs: f64,
+    pub volume: f64,
+    /// `audio::queue::PlayMode::as_str`
+    pub play_mode: String,
+}
+
+/// The saved session. Items that are library tracks come back with the track's current path,
+/// so files the library has seen being moved are still found.
+pub fn load(conn: &Connection) -> RepoResult<Session> {
+    let (current, position_secs, volume, play_mode) = conn.query_row(
+        "SELECT session_current, session_position, volume, play_mode FROM settings WHERE id=1",
+        [],
+        |r| Ok((r.get::<_, Option<i64>>(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(t.file_path, q.file_path), q.play_pos
+           FROM session_queue q LEFT JOIN tracks t ON t.id = q.track_id
+          ORDER BY q.idx",
+    )?;
+    let items = stmt
+        .query_map([], |r| Ok(SessionItem { file_path: r.get(0)?, play_pos: r.get::<_, i64>(1)? as usize }))?
+        .collect::<Result<_, _>>()?;
+    Ok(Session { items, current: current.map(|c| c as usize), position_secs, volume, play_mode })
+}
+
+/// Replace the saved session. Run inside a transaction.
+pub fn save(conn: &Connection, s: &Session) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE settings SET session_current = ?1, session_position = ?2, volume = ?3, play_mode = ?4 WHERE id=1",
+        params![s.current.map(|c| c as i64), s.position_secs, s.volume, s.play_mode],
+    )?;
+    conn.execute("DELETE FROM session_queue", [])?;
+    let mut stmt = conn.prepare(
+        "INSERT INTO session_queue (idx, play_pos, file_path, track_id)
+         VALUES (?1, ?2, ?3, (SELECT id FROM tracks WHERE file_path = ?3))",
+    )?;
+    for (i, item) in s.items.iter().enumerate() {
+        stmt.execute(params![i as i64, item.play_pos as i64, item.file_path])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use super::RepoResult;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub library_root: Option<String>,
+    pub use_managed_dir: bool,
+    pub managed_root: String,
+}
+
+/// Fill in `managed_root` if it was never set.
+pub fn ensure_managed_root(conn: &Connection, default_root: &str) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE settings SET managed_root = COALESCE(managed_root, ?1) WHERE id=1",
+        [default_root],
+    )?;
+    Ok(())
+}
+
+pub fn get(conn: &Connection) -> RepoResult<Settings> {
+    Ok(conn.query_row(
+        "SELECT library_root, use_managed_dir, managed_root FROM settings WHERE id=1",
+        [],
+        |row| {
+            let use_managed_dir: i64 = row.get(1)?;
+            Ok(Settings {
+                library_root: row.get(0)?,
+                use_managed_dir: use_managed_dir != 0,
+                managed_root: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            })
+        },
+    )?)
+}
+
+/// The folder the library lives in: the managed root when enabled, else the user's root.
+pub fn effective_root(conn: &Connection) -> RepoResult<Option<String>> {
+    Ok(conn.query_row(
+        "SELECT library_root, use_managed_dir, managed_root FROM settings WHERE id=1",
+        [],
+        |row| {
+            let library_root: Option<String> = row.get(0)?;
+            let use_managed: i64 = row.get(1)?;
+            let managed_root: Option<String> = row.get(2)?;
+            Ok(if use_managed != 0 { managed_root } else { library_root })
+        },
+    )?)
+}
+
+pub fn set_library_root(conn: &Connection, path: Option<&str>) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET library_root = ?1 WHERE id=1", [path])?;
+    Ok(())
+}
+
+pub fn set_use_managed_dir(conn: &Connection, value: bool) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET use_managed_dir = ?1 WHERE id=1", [value as i64])?;
+    Ok(())
+}
+
+pub fn set_managed_root(conn: &Connection, path: &str) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET managed_root = ?1 WHERE id=1", [path])?;
+    Ok(())
+}
+
+/// Persisted crossfade settings; `curve` is one of `equal_power`, `linear`, `s_curve`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossfadeSettings {
+    pub seconds: f64,
+    pub curve: String,
+    pub skip_same_album: bool,
+}
+
+pub fn crossfade(conn: &Connection) -> RepoResult<CrossfadeSettings> {
+    Ok(conn.query_row(
+        "SELECT crossfade_secs, crossfade_curve, crossfade_skip_same_album FROM settings WHERE id=1",
+        [],
+        |row| {
+            Ok(CrossfadeSettings {
+                seconds: row.get(0)?,
+                curve: row.get(1)?,
+                skip_same_album: row.get::<_, i64>(2)? != 0,
+            })
+        },
+    )?)
+}
+
+pub fn set_crossfade(conn: &Connection, cf: &CrossfadeSettings) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE settings SET crossfade_secs=?1, crossfade_curve=?2, crossfade_skip_same_album=?3 WHERE id=1",
+        rusqlite::params![cf.seconds, cf.curve, cf.skip_same_album as i64],
+    )?;
+    Ok(())
+}
+
+/// Resampler preset name (`fast`, `balanced`, `high`).
+pub fn resample_quality(conn: &Connection) -> RepoResult<String> {
+    Ok(conn.query_row("SELECT resample_quality FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_resample_quality(conn: &Connection, quality: &str) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET resample_quality = ?1 WHERE id=1", [quality])?;
+    Ok(())
+}
+
+pub fn replaygain_mode(conn: &Connection) -> RepoResult<String> {
+    Ok(conn.query_row("SELECT replaygain_mode FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_replaygain_mode(conn: &Connection, mode: &str) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET replaygain_mode = ?1 WHERE id=1", [mode])?;
+    Ok(())
+}
+
+/// Name of the chosen output device; `None` follows the system default.
+pub fn output_device(conn: &Connection) -> RepoResult<Option<String>> {
+    Ok(conn.query_row("SELECT output_device FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_output_device(conn: &Connection, name: Option<&str>) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET output_device = ?1 WHERE id=1", [name])?;
+    Ok(())
+}
+
+pub fn bit_perfect(conn: &Connection) -> RepoResult<bool> {
+    Ok(conn.query_row("SELECT bit_perfect FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_bit_perfect(conn: &Connection, on: bool) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET bit_perfect = ?1 WHERE id=1", [on])?;
+    Ok(())
+}
+
+pub fn noise_shaping(conn: &Connection) -> RepoResult<bool> {
+    Ok(conn.query_row("SELECT noise_shaping FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_noise_shaping(conn: &Connection, on: bool) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET noise_shaping = ?1 WHERE id=1", [on])?;
+    Ok(())
+}
+
+/// Fade length on pause, resume, stop and skip, in milliseconds.
+pub fn transport_fade_ms(conn: &Connection) -> RepoResult<u32> {
+    Ok(conn.query_row("SELECT transport_fade_ms FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_transport_fade_ms(conn: &Connection, ms: u32) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET transport_fade_ms = ?1 WHERE id=1", [ms])?;
+    Ok(())
+}
+
+/// Playback speed, 1.0 being normal.
+pub fn playback_speed(conn: &Connection) -> RepoResult<f64> {
+    Ok(conn.query_row("SELECT playback_speed FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_playback_speed(conn: &Connection, speed: f64) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET playback_speed = ?1 WHERE id=1", [speed])?;
+    Ok(())
+}
+
+/// Whether each file keeps the speed it was last played at (see `repo::speed`).
+pub fn remember_track_speed(conn: &Connection) -> RepoResult<bool> {
+    Ok(conn.query_row("SELECT remember_track_speed FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_remember_track_speed(conn: &Connection, on: bool) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET remember_track_speed = ?1 WHERE id=1", [on])?;
+    Ok(())
+}
+
+/// Channel matrix as JSON; `None` for the standard one.
+pub fn channel_matrix(conn: &Connection) -> RepoResult<Option<String>> {
+    Ok(conn.query_row("SELECT channel_matrix FROM settings WHERE id=1", [], |row| row.get(0))?)
+}
+
+pub fn set_channel_matrix(conn: &Connection, json: &str) -> RepoResult<()> {
+    conn.execute("UPDATE settings SET channel_matrix = ?1 WHERE id=1", [json])?;
+    Ok(())
+}
+
+/// Latency profile name and, for `custom`, its buffer size in frames.
+pub fn latency(conn: &Connection) -> RepoResult<(String, Option<u32>)> {
+    Ok(conn.query_row(
+        "SELECT latency_profile, latency_frames FROM settings WHERE id=1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
+
+pub fn set_latency(conn: &Connection, profile: &str, frames: Option<u32>) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE settings SET latency_profile = ?1, latency_frames = ?2 WHERE id=1",
+        rusqlite::params![profile, frames],
+    )?;
+    Ok(())
+}
+
+/// Whether a loudness analysis was running (and should be resumed), and if it writes tags.
+pub fn loudness_job(conn: &Connection) -> RepoResult<(bool, bool)> {
+    Ok(conn.query_row(
+        "SELECT loudness_job_active, loudness_job_write_tags FROM settings WHERE id=1",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?)
+}
+
+pub fn set_loudness_job(conn: &Connection, active: bool, write_tags: bool) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE settings SET loudness_job_active = ?1, loudness_job_write_tags = ?2 WHERE id=1",
+        rusqlite::params![active, write_tags],
+    )?;
+    Ok(())
+}
+
+/// Create the singleton settings row on first start.
+pub fn seed(conn: &Connection, managed_root: &str) -> RepoResult<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO settings (id, library_root, use_managed_dir, managed_root)
+         VALUES (1, NULL, 1, ?1)",
+        [managed_root],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::RepoResult;
+
+/// Speed remembered for a file, if any.
+pub fn track_speed(conn: &Connection, file_path: &str) -> RepoResult<Option<f64>> {
+    Ok(conn
+        .query_row("SELECT speed FROM track_speeds WHERE file_path = ?1", [file_path], |r| r.get(0))
+        .optional()?)
+}
+
+pub fn set_track_speed(conn: &Connection, file_path: &str, speed: f64) -> RepoResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO track_speeds (file_path, speed) VALUES (?1, ?2)",
+        params![file_path, speed],
+    )?;
+    Ok(())
+}
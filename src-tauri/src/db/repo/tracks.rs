@@ -0,0 +1,280 @@
+use std::collections::HashSet;
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::{split_artists, RepoError, RepoResult};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Track {
+    pub id: i64,
+    pub title: String,
+    pub duration_secs: f64,
+    pub file_path: String,
+    pub album: Option<String>,
+    pub artists: Vec<String>,
+    /// False once a rescan found the file missing; the row is kept so it can be re-linked.
+    pub available: bool,
+}
+
+/// Projection shared by every query that returns a [`Track`]; expects `tracks` aliased as `t`.
+/// Read the result with [`from_row`] starting at column 0.
+pub(crate) const TRACK_COLUMNS: &str = "
+    t.id,
+    t.title,
+    IFNULL(t.duration_secs, 0.0),
+    t.file_path,
+    (SELECT al.title FROM albums al WHERE al.id = t.album_id),
+    IFNULL((
+      SELECT GROUP_CONCAT(ar.name, char(31))
+      FROM track_artists ta
+      JOIN artists ar ON ar.id = ta.artist_id
+      WHERE ta.track_id = t.id
+    ), ''),
+    t.available";
+
+/// Number of columns in [`TRACK_COLUMNS`]; extra columns selected after it start here.
+pub(crate) const TRACK_COLUMN_COUNT: usize = 7;
+
+pub(crate) fn from_row(r: &Row<'_>) -> rusqlite::Result<Track> {
+    let artists_csv: String = r.get(5)?;
+    Ok(Track {
+        id: r.get(0)?,
+        title: r.get(1)?,
+        duration_secs: r.get(2)?,
+        file_path: r.get(3)?,
+        album: r.get(4)?,
+        artists: split_artists(&artists_csv),
+        available: r.get(6)?,
+    })
+}
+
+/// All registered tracks ordered by title.
+pub fn list(conn: &Connection) -> RepoResult<Vec<Track>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TRACK_COLUMNS} FROM tracks t ORDER BY t.title COLLATE NOCASE"
+    ))?;
+    let rows = stmt.query_map([], from_row)?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+pub fn by_id(conn: &Connection, id: i64) -> RepoResult<Track> {
+    conn.query_row(
+        &format!("SELECT {TRACK_COLUMNS} FROM tracks t WHERE t.id = ?1"),
+        [id],
+        from_row,
+    )
+    .optional()?
+    .ok_or(RepoError::NotFound("track"))
+}
+
+pub fn id_by_path(conn: &Connection, file_path: &str) -> RepoResult<Option<i64>> {
+    Ok(conn
+        .query_row("SELECT id FROM tracks WHERE file_path = ?1", [file_path], |r| r.get(0))
+        .optional()?)
+}
+
+/// Path of the track whose content hash is `file_hash`, if any.
+pub fn path_by_hash(conn: &Connection, file_hash: &str) -> RepoResult<Option<String>> {
+    Ok(conn
+        .query_row("SELECT file_path FROM tracks WHERE file_hash = ?1", [file_hash], |r| r.get(0))
+        .optional()?)
+}
+
+/// Every `file_path` currently in the table, for quick "is it registered?" checks.
+pub fn all_paths(conn: &Connection) -> RepoResult<HashSet<String>> {
+    let mut stmt = conn.prepare("SELECT file_path FROM tracks")?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+#[derive(Debug, Clone)]
+pub struct NewTrack<'a> {
+    pub title: &'a str,
+    pub duration_secs: f64,
+    pub file_path: &'a str,
+    pub file_hash: &'a str,
+    pub album_id: Option<i64>,
+    pub track_no: Option<u32>,
+    pub disc_no: Option<u32>,
+    pub file_size: Option<i64>,
+    pub file_mtime: Option<i64>,
+}
+
+/// Insert a track row. If the path or content hash is already known, the existing id is returned.
+pub fn insert(conn: &Connection, t: &NewTrack<'_>) -> RepoResult<i64> {
+    conn.execute(
+        "INSERT OR IGNORE INTO tracks
+           (title, duration_secs, file_path, file_hash, album_id, track_no, disc_no, file_size, file_mtime)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            t.title, t.duration_secs, t.file_path, t.file_hash, t.album_id,
+            t.track_no, t.disc_no, t.file_size, t.file_mtime
+        ],
+    )?;
+    if conn.changes() > 0 {
+        return Ok(conn.last_insert_rowid());
+    }
+    conn.query_row(
+        "SELECT id FROM tracks WHERE file_path = ?1 OR file_hash = ?2",
+        params![t.file_path, t.file_hash],
+        |r| r.get(0),
+    )
+    .optional()?
+    .ok_or(RepoError::NotFound("track"))
+}
+
+pub fn link_artists(conn: &Connection, track_id: i64, artist_ids: &[i64]) -> RepoResult<()> {
+    let mut ins = conn.prepare_cached(
+        "INSERT OR IGNORE INTO track_artists (track_id, artist_id, role) VALUES (?1, ?2, NULL)",
+    )?;
+    for aid in artist_ids {
+        ins.execute(params![track_id, aid])?;
+    }
+    Ok(())
+}
+
+/// Last known on-disk state of a track, for incremental rescans.
+#[derive(Debug, Clone)]
+pub struct FileState {
+    pub id: i64,
+    pub file_path: String,
+    pub file_hash: String,
+    pub file_size: Option<i64>,
+    pub file_mtime: Option<i64>,
+    pub available: bool,
+}
+
+pub fn file_states(conn: &Connection) -> RepoResult<Vec<FileState>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, file_path, file_hash, file_size, file_mtime, available FROM tracks",
+    )?;
+    let rows = stmt.query_map([], |r| {
+        Ok(FileState {
+            id: r.get(0)?,
+            file_path: r.get(1)?,
+            file_hash: r.get(2)?,
+            file_size: r.get(3)?,
+            file_mtime: r.get(4)?,
+            available: r.get(5)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+/// Point a track at a new path (a move/rename) and mark it available again.
+pub fn relocate(conn: &Connection, id: i64, file_path: &str, file_size: i64, file_mtime: i64) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE tracks
+            SET file_path=?2, file_size=?3, file_mtime=?4, available=1, missing_since=NULL
+          WHERE id=?1",
+        params![id, file_path, file_size, file_mtime],
+    )?;
+    Ok(())
+}
+
+/// Record the current size/mtime (and availability) without touching anything else.
+pub fn touch_file_state(conn: &Connection, id: i64, file_size: i64, file_mtime: i64) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE tracks SET file_size=?2, file_mtime=?3, available=1, missing_since=NULL WHERE id=?1",
+        params![id, file_size, file_mtime],
+    )?;
+    Ok(())
+}
+
+pub fn mark_missing(conn: &Connection, id: i64) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE tracks SET available=0, missing_since=strftime('%s','now') WHERE id=?1 AND available=1",
+        [id],
+    )?;
+    Ok(())
+}
+
+pub fn unlink_artists(conn: &Connection, track_id: i64) -> RepoResult<()> {
+    conn.execute("DELETE FROM track_artists WHERE track_id=?1", [track_id])?;
+    Ok(())
+}
+
+/// ReplayGain values from a file's tags: gains in dB relative to the ReplayGain reference
+/// level, peaks as linear sample amplitude (1.0 = full scale).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ReplayGain {
+    pub track_gain: Option<f64>,
+    pub track_peak: Option<f64>,
+    pub album_gain: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+/// Values read from the file's own tags.
+pub fn set_replay_gain(conn: &Connection, id: i64, rg: &ReplayGain) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE tracks SET rg_track_gain=?2, rg_track_peak=?3, rg_album_gain=?4, rg_album_peak=?5, rg_written=0
+          WHERE id=?1",
+        params![id, rg.track_gain, rg.track_peak, rg.album_gain, rg.album_peak],
+    )?;
+    Ok(())
+}
+
+/// Values the loudness analysis wrote into the file.
+pub fn set_written_replay_gain(conn: &Connection, id: i64, rg: &ReplayGain) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE tracks SET rg_track_gain=?2, rg_track_peak=?3, rg_album_gain=?4, rg_album_peak=?5, rg_written=1
+          WHERE id=?1",
+        params![id, rg.track_gain, rg.track_peak, rg.album_gain, rg.album_peak],
+    )?;
+    Ok(())
+}
+
+/// ReplayGain values and album of the track at `file_path`, if it is registered. Values missing
+/// from the tags are filled in from loudness measurements of the current file content.
+pub fn replay_gain_by_path(conn: &Connection, file_path: &str) -> RepoResult<Option<(ReplayGain, Option<i64>)>> {
+    Ok(conn
+        .prepare_cached(
+            "SELECT COALESCE(t.rg_track_gain, ?2 - tl.integrated_lufs),
+                    COALESCE(t.rg_track_peak, tl.true_peak),
+                    COALESCE(t.rg_album_gain, ?2 - al.integrated_lufs),
+                    COALESCE(t.rg_album_peak, al.true_peak),
+                    t.album_id
+               FROM tracks t
+               LEFT JOIN track_loudness tl ON tl.track_id = t.id AND tl.file_hash = t.file_hash
+               LEFT JOIN album_loudness al ON al.album_id = t.album_id
+              WHERE t.file_path = ?1",
+        )?
+        .query_row(params![file_path, super::loudness::REPLAYGAIN_REFERENCE_LUFS], |r| {
+            Ok((
+                ReplayGain {
+                    track_gain: r.get(0)?,
+                    track_peak: r.get(1)?,
+                    album_gain: r.get(2)?,
+                    album_peak: r.get(3)?,
+                },
+                r.get(4)?,
+            ))
+        })
+        .optional()?)
+}
+
+/// ReplayGain values stored for a track, and whether the loudness analysis wrote them (rather
+/// than them coming from the file's own tags).
+pub fn stored_replay_gain(conn: &Connection, id: i64) -> RepoResult<(ReplayGain, bool)> {
+    Ok(conn.query_row(
+        "SELECT rg_track_gain, rg_track_peak, rg_album_gain, rg_album_peak, rg_written FROM tracks WHERE id=?1",
+        [id],
+        |r| {
+            Ok((
+                ReplayGain { track_gain: r.get(0)?, track_peak: r.get(1)?, album_gain: r.get(2)?, album_peak: r.get(3)? },
+                r.get(4)?,
+            ))
+        },
+    )?)
+}
+
+/// The file was rewritten in place (e.g. tags updated by the app): record its new identity so
+/// the next rescan doesn't treat it as changed.
+pub fn record_rewrite(conn: &Connection, id: i64, file_hash: &str, file_size: i64, file_mtime: i64) -> RepoResult<()> {
+    conn.execute(
+        "UPDATE tracks SET file_hash=?2, file_size=?3, file_mtime=?4 WHERE id=?1",
+        params![id, file_hash, file_size, file_mtime],
+    )?;
+    Ok(())
+}
@@ -1,21 +1,36 @@
-use tauri::Manager;
+use tauri::{Manager, RunEvent};
 
 mod audio;
 pub mod tauri_commands;
 pub mod db;
 pub mod library;
+mod utils;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
+            let pool = db::init_db().map_err(|e| anyhow::anyhow!(e))?;
+            // managed before the audio engine starts: it looks up ReplayGain values per track
+            app.manage(pool.clone());
+
             // create Send+Sync manager that only holds atomics + command sender
             let mgr = tauri_commands::audio::AudioManager::new(&app.handle());
+            mgr.restore_settings(&*pool.get()?).map_err(|e| anyhow::anyhow!(e))?;
             app.manage(mgr); // this is now Send + Sync, OK
 
-            let pool = db::init_db().map_err(|e| anyhow::anyhow!(e))?;
-            app.manage(pool);
+            // watch the library root for changes made outside the app
+            let watcher = library::watch::LibraryWatcher::new(app.handle().clone(), pool.clone());
+            let conn = pool.get()?;
+            tauri_commands::settings::rewatch_effective_root(&conn, &watcher).map_err(|e| anyhow::anyhow!(e))?;
+            drop(conn);
+            app.manage(watcher);
+
+            // loudness analysis interrupted by the last quit carries on
+            let loudness = library::analyze::LoudnessJob::new(app.handle().clone(), pool.clone());
+            loudness.resume().map_err(|e| anyhow::anyhow!(e))?;
+            app.manage(loudness);
 
             #[cfg(debug_assertions)]
             {
@@ -34,12 +49,54 @@ pub fn run() {
             tauri_commands::audio::pause_audio,
             tauri_commands::audio::stop_audio,
             tauri_commands::audio::set_volume,
+            tauri_commands::audio::get_volume,
+            tauri_commands::audio::list_output_devices,
+            tauri_commands::audio::get_output_device,
+            tauri_commands::audio::set_output_device,
+            tauri_commands::audio::get_bit_perfect,
+            tauri_commands::audio::set_bit_perfect,
+            tauri_commands::audio::get_channel_matrix,
+            tauri_commands::audio::set_channel_matrix,
+            tauri_commands::audio::get_noise_shaping,
+            tauri_commands::audio::set_noise_shaping,
+            tauri_commands::audio::get_transport_fade,
+            tauri_commands::audio::set_transport_fade,
+            tauri_commands::audio::get_playback_speed,
+            tauri_commands::audio::set_playback_speed,
+            tauri_commands::audio::get_remember_track_speed,
+            tauri_commands::audio::set_remember_track_speed,
+            tauri_commands::audio::get_latency_profile,
+            tauri_commands::audio::set_latency_profile,
+            tauri_commands::audio::get_output_latency,
             tauri_commands::audio::get_duration,
             tauri_commands::audio::get_position,
             tauri_commands::audio::seek_to,
             tauri_commands::audio::next_track,
             tauri_commands::audio::prev_track,
+            tauri_commands::audio::get_play_mode,
+            tauri_commands::audio::set_play_mode,
+            tauri_commands::audio::get_queue,
+            tauri_commands::audio::queue_append,
+            tauri_commands::audio::queue_play_next,
+            tauri_commands::audio::queue_remove,
+            tauri_commands::audio::queue_move,
+            tauri_commands::audio::queue_clear_upcoming,
+            tauri_commands::audio::queue_jump,
             tauri_commands::audio::play_selection,
+            tauri_commands::audio::get_crossfade,
+            tauri_commands::audio::set_crossfade,
+            tauri_commands::audio::get_resample_quality,
+            tauri_commands::audio::set_resample_quality,
+            tauri_commands::audio::get_replaygain_mode,
+            tauri_commands::audio::set_replaygain_mode,
+            tauri_commands::audio::get_dsp_config,
+            tauri_commands::audio::set_dsp_config,
+            tauri_commands::audio::preview_dsp_config,
+            tauri_commands::audio::delete_dsp_profile,
+            tauri_commands::audio::list_dsp_profiles,
+            tauri_commands::audio::list_dsp_presets,
+            tauri_commands::audio::save_dsp_preset,
+            tauri_commands::audio::delete_dsp_preset,
 
             // --- library ---
             tauri_commands::library::choose_library_dir,
@@ -60,12 +117,38 @@ pub fn run() {
             tauri_commands::library::list_albums,
             tauri_commands::library::pick_audio_file,
 
+            // --- search ---
+            tauri_commands::search::search_library,
+
             // --- ingestion ---
             tauri_commands::ingestion::register_artist,
             tauri_commands::ingestion::register_album,
             tauri_commands::ingestion::register_track,
+            tauri_commands::ingestion::import_library,
+            tauri_commands::ingestion::rescan_library,
+            tauri_commands::ingestion::start_loudness_analysis,
+            tauri_commands::ingestion::cancel_loudness_analysis,
+            tauri_commands::ingestion::loudness_analysis_status,
+
+            // --- playlists ---
+            tauri_commands::playlists::create_playlist,
+            tauri_commands::playlists::rename_playlist,
+            tauri_commands::playlists::delete_playlist,
+            tauri_commands::playlists::list_playlists,
+            tauri_commands::playlists::add_to_playlist,
+            tauri_commands::playlists::remove_from_playlist,
+            tauri_commands::playlists::move_playlist_item,
+            tauri_commands::playlists::list_playlist_items,
+            tauri_commands::playlists::play_playlist,
 
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                if let Some(mgr) = app.try_state::<tauri_commands::audio::AudioManager>() {
+                    mgr.save_session();
+                }
+            }
+        });
 }
@@ -0,0 +1,284 @@
+//! Background loudness analysis (EBU R128) for tracks without usable ReplayGain tags.
+//!
+//! The database is the work queue: a track is pending until `track_loudness` holds a
+//! measurement of its current `file_hash` (or `track_loudness_failures` records that it could
+//! not be decoded), so a job interrupted by quitting the app just carries on when
+//! [`LoudnessJob::resume`] runs on the next start. Album values are computed as soon as the last
+//! track of an album has been measured, and again whenever a track is added to it later.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+use crate::audio::decoder::Source;
+use crate::audio::loudness::{Histogram, LoudnessMeter, Measurement};
+use crate::db::repo::loudness::{self, PendingTrack, TrackLoudness, REPLAYGAIN_REFERENCE_LUFS};
+use crate::db::repo::{self, settings, tracks::{self, ReplayGain}};
+use crate::db::DbPool;
+use crate::library::import::{file_stat, ImportIssue};
+use crate::library::tags::write_replay_gain;
+use crate::utils::hash::blake3_hex_of_file;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub failed: usize,
+    pub current: String,
+}
+
+/// Emitted as `library:analysis-done` when a job ends (including on cancel).
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AnalysisSummary {
+    pub analyzed: usize,
+    pub albums: usize,
+    pub tags_written: usize,
+    pub failed: Vec<ImportIssue>,
+    pub cancelled: bool,
+}
+
+struct Running {
+    cancel: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+/// Managed as Tauri state; at most one analysis runs at a time.
+pub struct LoudnessJob {
+    app: AppHandle,
+    pool: DbPool,
+    running: Mutex<Option<Running>>,
+}
+
+impl LoudnessJob {
+    pub fn new(app: AppHandle, pool: DbPool) -> Self {
+        Self { app, pool, running: Mutex::new(None) }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.lock().unwrap().as_ref().is_some_and(|r| !r.handle.is_finished())
+    }
+
+    /// Start measuring every pending track. With `write_tags`, results are also written into
+    /// files that have no ReplayGain tags of their own.
+    pub fn start(&self, write_tags: bool) -> Result<(), String> {
+        let mut running = self.running.lock().unwrap();
+        if running.as_ref().is_some_and(|r| !r.handle.is_finished()) {
+            return Err("Loudness analysis is already running".into());
+        }
+        let conn = self.pool.get().map_err(|e| e.to_string())?;
+        settings::set_loudness_job(&conn, true, write_tags).map_err(|e| e.to_string())?;
+        drop(conn);
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (app, pool, c) = (self.app.clone(), self.pool.clone(), Arc::clone(&cancel));
+        let handle = thread::Builder::new()
+            .name("loudness".into())
+            .spawn(move || {
+                lower_thread_priority();
+                let summary = match pool.get() {
+                    Ok(conn) => run(&conn, write_tags, &c, |p| { let _ = app.emit("library:analysis", p); }),
+                    Err(e) => Err(e.to_string()),
+                };
+                match summary {
+                    Ok(summary) => {
+                        if !summary.cancelled {
+                            if let Ok(conn) = pool.get() { let _ = settings::set_loudness_job(&conn, false, false); }
+                        }
+                        let _ = app.emit("library:analysis-done", &summary);
+                    }
+                    Err(e) => log::warn!("loudness analysis failed: {e}"),
+                }
+            })
+            .map_err(|e| e.to_string())?;
+        *running = Some(Running { cancel, handle });
+        Ok(())
+    }
+
+    /// Stop the running job (it won't be resumed on the next start). Returns whether one was running.
+    pub fn cancel(&self) -> Result<bool, String> {
+        let running = self.running.lock().unwrap().take();
+        let conn = self.pool.get().map_err(|e| e.to_string())?;
+        settings::set_loudness_job(&conn, false, false).map_err(|e| e.to_string())?;
+        let Some(r) = running else { return Ok(false) };
+        r.cancel.store(true, Ordering::Relaxed);
+        let was_running = !r.handle.is_finished();
+        let _ = r.handle.join();
+        Ok(was_running)
+    }
+
+    /// Pick up a job that was still running when the app last quit.
+    pub fn resume(&self) -> Result<(), String> {
+        let conn = self.pool.get().map_err(|e| e.to_string())?;
+        let (active, write_tags) = settings::loudness_job(&conn).map_err(|e| e.to_string())?;
+        drop(conn);
+        if active { self.start(write_tags) } else { Ok(()) }
+    }
+}
+
+/// Keep the analysis out of the way of playback and the UI.
+#[cfg(target_os = "linux")]
+fn lower_thread_priority() {
+    extern "C" { fn nice(inc: std::os::raw::c_int) -> std::os::raw::c_int; }
+    // Linux applies nice values per thread, so this only affects the analysis thread.
+    unsafe { nice(10); }
+}
+
+#[cfg(target_os = "macos")]
+fn lower_thread_priority() {
+    // `nice` would apply to the whole process here; a QoS class is per thread.
+    const QOS_CLASS_UTILITY: u32 = 0x11;
+    extern "C" { fn pthread_set_qos_class_self_np(class: u32, relative: std::os::raw::c_int) -> std::os::raw::c_int; }
+    unsafe { pthread_set_qos_class_self_np(QOS_CLASS_UTILITY, 0); }
+}
+
+#[cfg(windows)]
+fn lower_thread_priority() {
+    const THREAD_PRIORITY_BELOW_NORMAL: std::os::raw::c_int = -1;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut std::ffi::c_void;
+        fn SetThreadPriority(thread: *mut std::ffi::c_void, priority: std::os::raw::c_int) -> i32;
+    }
+    unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_BELOW_NORMAL); }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn lower_thread_priority() {}
+
+/// Decode `path` and measure it. `Ok(None)` if cancelled midway.
+pub fn measure_file(path: &str, cancel: &AtomicBool) -> anyhow::Result<Option<Measurement>> {
+    let mut source = Source::open(path)?;
+    let mut meter = LoudnessMeter::new(source.sample_rate, source.channels);
+    while let Some(block) = source.next_block()? {
+        if cancel.load(Ordering::Relaxed) { return Ok(None); }
+        meter.add(block);
+    }
+    Ok(Some(meter.finish()))
+}
+
+fn gain_of(integrated: Option<f64>) -> Option<f64> {
+    integrated.map(|l| REPLAYGAIN_REFERENCE_LUFS - l)
+}
+
+/// Measure pending tracks until none are left or `cancel` is set.
+pub fn run(
+    conn: &Connection,
+    write_tags: bool,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&AnalysisProgress),
+) -> Result<AnalysisSummary, String> {
+    let mut summary = AnalysisSummary::default();
+    let total = loudness::pending_count(conn).map_err(|e| e.to_string())?;
+    let mut processed = 0;
+    let mut after = 0;
+
+    while let Some(t) = loudness::next_pending(conn, after).map_err(|e| e.to_string())? {
+        after = t.id;
+        match measure_file(&t.file_path, cancel) {
+            Ok(None) => break,
+            Ok(Some(m)) => {
+                save_track(conn, &t, &m, write_tags, &mut summary).map_err(|e| e.to_string())?;
+                summary.analyzed += 1;
+            }
+            Err(e) => {
+                let reason = e.to_string();
+                loudness::save_failure(conn, t.id, &t.file_hash, &reason).map_err(|e| e.to_string())?;
+                summary.failed.push(ImportIssue { file_path: t.file_path.clone(), reason });
+            }
+        }
+        if let Some(album_id) = t.album_id {
+            if finish_album(conn, album_id, write_tags, &mut summary).map_err(|e| e.to_string())? {
+                summary.albums += 1;
+            }
+        }
+
+        processed += 1;
+        on_progress(&AnalysisProgress {
+            processed,
+            total: total.max(processed),
+            failed: summary.failed.len(),
+            current: t.file_path,
+        });
+    }
+
+    summary.cancelled = cancel.load(Ordering::Relaxed);
+    Ok(summary)
+}
+
+fn save_track(
+    conn: &Connection,
+    t: &PendingTrack,
+    m: &Measurement,
+    write_tags: bool,
+    summary: &mut AnalysisSummary,
+) -> repo::RepoResult<()> {
+    loudness::save_track(conn, t.id, &t.file_hash, &TrackLoudness {
+        integrated_lufs: m.integrated,
+        true_peak: m.true_peak,
+        loudness_range: m.range,
+        momentary_hist: m.momentary.to_blob(),
+        short_term_hist: m.short_term.to_blob(),
+    })?;
+
+    // Album tracks are written once the album value is known.
+    if write_tags && t.album_id.is_none() {
+        let rg = ReplayGain { track_gain: gain_of(m.integrated), track_peak: Some(m.true_peak), ..Default::default() };
+        write_back(conn, t.id, &t.file_path, &rg, summary)?;
+    }
+    Ok(())
+}
+
+/// Compute the album value if all its tracks are measured. Returns whether it was.
+fn finish_album(conn: &Connection, album_id: i64, write_tags: bool, summary: &mut AnalysisSummary) -> repo::RepoResult<bool> {
+    let Some(rows) = loudness::album_tracks(conn, album_id)? else { return Ok(false) };
+    if rows.is_empty() { return Ok(false); }
+
+    let (mut momentary, mut short_term, mut peak) = (Histogram::default(), Histogram::default(), 0.0f64);
+    for (_, l) in &rows {
+        momentary.merge(&Histogram::from_blob(&l.momentary_hist));
+        short_term.merge(&Histogram::from_blob(&l.short_term_hist));
+        peak = peak.max(l.true_peak);
+    }
+    let album = Measurement::from_histograms(momentary, short_term, peak);
+    loudness::save_album(conn, album_id, album.integrated, album.true_peak, album.range)?;
+
+    if write_tags {
+        for (id, l) in &rows {
+            let rg = ReplayGain {
+                track_gain: gain_of(l.integrated_lufs),
+                track_peak: Some(l.true_peak),
+                album_gain: gain_of(album.integrated),
+                album_peak: Some(album.true_peak),
+            };
+            let path = tracks::by_id(conn, *id)?.file_path;
+            write_back(conn, *id, &path, &rg, summary)?;
+        }
+    }
+    Ok(true)
+}
+
+/// Write measured values into a file that has no ReplayGain tags of its own (or only ones
+/// written here before, which may carry an outdated album value), and record the rewritten
+/// file so it isn't picked up as changed (or re-measured). Tag errors are reported, not fatal.
+fn write_back(conn: &Connection, id: i64, path: &str, rg: &ReplayGain, summary: &mut AnalysisSummary) -> repo::RepoResult<()> {
+    let (stored, written) = tracks::stored_replay_gain(conn, id)?;
+    if (stored.track_gain.is_some() && !written) || stored == *rg { return Ok(()); }
+    let p = Path::new(path);
+    let rewritten = write_replay_gain(p, rg)
+        .and_then(|_| Ok((file_stat(p)?, blake3_hex_of_file(p)?)));
+    match rewritten {
+        Ok(((size, mtime), hash)) => {
+            tracks::record_rewrite(conn, id, &hash, size, mtime)?;
+            loudness::set_measured_hash(conn, id, &hash)?;
+            tracks::set_written_replay_gain(conn, id, rg)?;
+            summary.tags_written += 1;
+        }
+        Err(e) => summary.failed.push(ImportIssue { file_path: path.to_string(), reason: format!("writing tags failed: {e}") }),
+    }
+    Ok(())
+}
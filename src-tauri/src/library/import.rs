@@ -0,0 +1,175 @@
+//! Bulk import: read tags from audio files and register them in place.
+//!
+//! Tag reading and hashing happen outside the database; each batch of files is then written
+//! in one transaction, with a savepoint per file so one bad row doesn't sink the batch.
+
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::db::repo::{self, ingestion::TaggedTrack, tracks};
+use crate::library::is_audio;
+use crate::library::tags::{read_track_tags, TrackTags};
+use crate::utils::hash::blake3_hex_of_file;
+
+const BATCH_SIZE: usize = 200;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportIssue {
+    pub file_path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportSummary {
+    pub total: usize,
+    pub imported: usize,
+    /// Files whose path was already registered; not listed individually.
+    pub already_registered: usize,
+    pub skipped: Vec<ImportIssue>,
+    pub failed: Vec<ImportIssue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgress {
+    pub processed: usize,
+    pub total: usize,
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub current: String,
+}
+
+/// Every audio file under `root`, sorted so imports run in a stable order.
+pub fn collect_audio_files(root: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = WalkDir::new(root)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file() && is_audio(e.path()))
+        .map(|e| e.into_path())
+        .collect();
+    files.sort();
+    files
+}
+
+/// Size in bytes and modification time in ms since the Unix epoch (`tracks.file_size/file_mtime`).
+pub fn file_stat(p: &Path) -> std::io::Result<(i64, i64)> {
+    use std::time::UNIX_EPOCH;
+    let md = std::fs::metadata(p)?;
+    let mtime = md.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0);
+    Ok((md.len() as i64, mtime))
+}
+
+/// A file with its tags read and content hashed, ready to be written.
+pub(crate) struct Prepared {
+    pub path: String,
+    pub tags: TrackTags,
+    pub hash: String,
+    pub size: i64,
+    pub mtime: i64,
+}
+
+pub(crate) fn prepare(p: &Path) -> Result<Prepared, String> {
+    let (size, mtime) = file_stat(p).map_err(|e| format!("stat failed: {e}"))?;
+    let tags = read_track_tags(p).map_err(|e| format!("unreadable tags: {e}"))?;
+    let hash = blake3_hex_of_file(p).map_err(|e| format!("hash failed: {e}"))?;
+    Ok(Prepared { path: p.to_string_lossy().to_string(), tags, hash, size, mtime })
+}
+
+impl Prepared {
+    fn title(&self) -> String {
+        self.tags.title.clone().unwrap_or_else(|| {
+            Path::new(&self.path).file_stem().and_then(|s| s.to_str()).unwrap_or("Track").to_string()
+        })
+    }
+
+    fn tagged<'a>(&'a self, title: &'a str) -> TaggedTrack<'a> {
+        TaggedTrack {
+            file_path: &self.path,
+            file_hash: &self.hash,
+            title,
+            duration_secs: self.tags.duration_secs,
+            artists: &self.tags.artists,
+            album_artists: &self.tags.album_artists,
+            album: self.tags.album.as_deref(),
+            year: self.tags.year,
+            track_no: self.tags.track_no,
+            disc_no: self.tags.disc_no,
+            file_size: Some(self.size),
+            file_mtime: Some(self.mtime),
+            replay_gain: self.tags.replay_gain,
+        }
+    }
+
+    /// Register as a new track.
+    pub(crate) fn insert(&self, conn: &Connection) -> repo::RepoResult<i64> {
+        repo::ingestion::import_tagged(conn, &self.tagged(&self.title()))
+    }
+
+    /// Overwrite the existing track `track_id` with this file's data.
+    pub(crate) fn refresh(&self, conn: &Connection, track_id: i64) -> repo::RepoResult<()> {
+        repo::ingestion::refresh_tagged(conn, track_id, &self.tagged(&self.title()))
+    }
+}
+
+/// Import `files`, calling `on_progress` after each one.
+pub fn import_files(
+    conn: &mut Connection,
+    files: &[PathBuf],
+    mut on_progress: impl FnMut(&ImportProgress),
+) -> repo::RepoResult<ImportSummary> {
+    let mut summary = ImportSummary { total: files.len(), ..Default::default() };
+    let mut known = tracks::all_paths(conn)?;
+    let mut processed = 0;
+
+    for batch in files.chunks(BATCH_SIZE) {
+        let prepared: Vec<(&PathBuf, Option<Result<Prepared, String>>)> = batch.iter()
+            .map(|p| {
+                let registered = known.contains(p.to_string_lossy().as_ref());
+                (p, (!registered).then(|| prepare(p)))
+            })
+            .collect();
+
+        let mut tx = conn.transaction()?;
+        for (p, item) in prepared {
+            let path = p.to_string_lossy().to_string();
+            match item {
+                None => summary.already_registered += 1,
+                Some(Err(reason)) => summary.failed.push(ImportIssue { file_path: path.clone(), reason }),
+                Some(Ok(prep)) => {
+                    if let Some(existing) = tracks::path_by_hash(&tx, &prep.hash)? {
+                        summary.skipped.push(ImportIssue {
+                            file_path: path.clone(),
+                            reason: format!("same content as {existing}"),
+                        });
+                    } else {
+                        let sp = tx.savepoint()?;
+                        match prep.insert(&sp) {
+                            Ok(_) => {
+                                sp.commit()?;
+                                summary.imported += 1;
+                                known.insert(path.clone());
+                            }
+                            Err(e) => summary.failed.push(ImportIssue { file_path: path.clone(), reason: e.to_string() }),
+                        }
+                    }
+                }
+            }
+
+            processed += 1;
+            on_progress(&ImportProgress {
+                processed,
+                total: summary.total,
+                imported: summary.imported,
+                skipped: summary.skipped.len(),
+                failed: summary.failed.len(),
+                current: path,
+            });
+        }
+        tx.commit()?;
+    }
+
+    Ok(summary)
+}
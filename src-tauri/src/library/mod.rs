@@ -22,3 +22,8 @@ pub(crate) fn is_audio(path: &std::path::Path) -> bool {
 pub mod scan;
 pub mod art;
 pub mod thumbs;
+pub mod tags;
+pub mod import;
+pub mod rescan;
+pub mod watch;
+pub mod analyze;
@@ -0,0 +1,161 @@
+//! Incremental rescan of a library root against what the database already knows.
+//!
+//! Files whose size and mtime match the stored values are not read at all. Changed files are
+//! re-hashed and re-tagged. A new path whose content hash matches a track whose file is gone is
+//! treated as a move and the row's `file_path` is rewritten. Tracks under the root whose file
+//! has disappeared are marked unavailable rather than deleted, so playlists survive a remount.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use rusqlite::Connection;
+use serde::Serialize;
+
+use crate::db::repo::{self, tracks::{self, FileState}};
+use crate::library::import::{collect_audio_files, file_stat, import_files, prepare, ImportIssue, ImportProgress};
+use crate::utils::hash::blake3_hex_of_file;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MovedFile {
+    pub from: String,
+    pub to: String,
+}
+
+/// What changed on disk since the last scan. Emitted to the UI as `library:rescan`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RescanDiff {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub moved: Vec<MovedFile>,
+    pub missing: Vec<String>,
+    pub restored: Vec<String>,
+    pub unchanged: usize,
+    pub skipped: Vec<ImportIssue>,
+    pub failed: Vec<ImportIssue>,
+}
+
+impl RescanDiff {
+    pub fn has_changes(&self) -> bool {
+        !(self.added.is_empty() && self.modified.is_empty() && self.moved.is_empty()
+            && self.missing.is_empty() && self.restored.is_empty())
+    }
+}
+
+fn is_under(path: &str, root: &Path) -> bool {
+    Path::new(path).starts_with(root)
+}
+
+/// Rescan all audio files under `root`. `on_progress` reports the import of new files.
+pub fn rescan_root(
+    conn: &mut Connection,
+    root: &Path,
+    on_progress: impl FnMut(&ImportProgress),
+) -> repo::RepoResult<RescanDiff> {
+    let on_disk = collect_audio_files(root);
+    let mut diff = RescanDiff::default();
+    let states = tracks::file_states(conn)?;
+    let by_path: HashMap<&str, &FileState> = states.iter().map(|s| (s.file_path.as_str(), s)).collect();
+
+    let mut seen = HashSet::new();
+    let mut changed: Vec<(&FileState, &PathBuf)> = Vec::new();
+    let mut restored: Vec<(&FileState, i64, i64)> = Vec::new();
+    let mut new_paths: Vec<(&PathBuf, i64, i64)> = Vec::new();
+
+    for p in &on_disk {
+        let stat = match file_stat(p) {
+            Ok(s) => s,
+            Err(e) => {
+                diff.failed.push(ImportIssue { file_path: p.to_string_lossy().to_string(), reason: format!("stat failed: {e}") });
+                continue;
+            }
+        };
+        match by_path.get(p.to_string_lossy().as_ref()) {
+            Some(st) => {
+                seen.insert(st.id);
+                if st.file_size == Some(stat.0) && st.file_mtime == Some(stat.1) {
+                    if st.available { diff.unchanged += 1; } else { restored.push((st, stat.0, stat.1)); }
+                } else {
+                    changed.push((st, p));
+                }
+            }
+            None => new_paths.push((p, stat.0, stat.1)),
+        }
+    }
+
+    // Rows whose file is gone (anywhere): candidates for move detection.
+    // Those under `root` that aren't claimed by a move get marked missing below.
+    let mut gone: Vec<&FileState> = states.iter()
+        .filter(|s| !seen.contains(&s.id) && !Path::new(&s.file_path).exists())
+        .collect();
+
+    // Match new paths to gone rows by hash, only hashing when the size could match.
+    let mut moves: Vec<(&FileState, &PathBuf, i64, i64)> = Vec::new();
+    let mut to_import: Vec<PathBuf> = Vec::new();
+    for (p, size, mtime) in new_paths {
+        let candidate = gone.iter().any(|g| g.file_size.map_or(true, |s| s == size));
+        let hit = if candidate {
+            match blake3_hex_of_file(p) {
+                Ok(hash) => gone.iter().position(|g| g.file_hash == hash),
+                Err(_) => None,
+            }
+        } else {
+            None
+        };
+        match hit {
+            Some(i) => moves.push((gone.swap_remove(i), p, size, mtime)),
+            None => to_import.push(p.clone()),
+        }
+    }
+
+    // Re-read changed files outside the transaction.
+    let changed: Vec<_> = changed.into_iter().map(|(st, p)| (st, p, prepare(p))).collect();
+
+    let mut tx = conn.transaction()?;
+    for (st, p, size, mtime) in moves {
+        let to = p.to_string_lossy().to_string();
+        tracks::relocate(&tx, st.id, &to, size, mtime)?;
+        diff.moved.push(MovedFile { from: st.file_path.clone(), to });
+    }
+    for (st, size, mtime) in restored {
+        tracks::touch_file_state(&tx, st.id, size, mtime)?;
+        diff.restored.push(st.file_path.clone());
+    }
+    for (st, p, prepared) in changed {
+        let path = p.to_string_lossy().to_string();
+        match prepared {
+            Err(reason) => diff.failed.push(ImportIssue { file_path: path, reason }),
+            Ok(prep) if prep.hash == st.file_hash => {
+                tracks::touch_file_state(&tx, st.id, prep.size, prep.mtime)?;
+                if st.available { diff.unchanged += 1; } else { diff.restored.push(path); }
+            }
+            Ok(prep) => {
+                let sp = tx.savepoint()?;
+                match prep.refresh(&sp, st.id) {
+                    Ok(()) => { sp.commit()?; diff.modified.push(path); }
+                    Err(e) => diff.failed.push(ImportIssue { file_path: path, reason: e.to_string() }),
+                }
+            }
+        }
+    }
+    for st in gone {
+        if st.available && is_under(&st.file_path, root) {
+            tracks::mark_missing(&tx, st.id)?;
+            diff.missing.push(st.file_path.clone());
+        }
+    }
+    tx.commit()?;
+
+    // Genuinely new files go through the normal importer.
+    let summary = import_files(conn, &to_import, on_progress)?;
+    let rejected: HashSet<&str> = summary.skipped.iter().chain(&summary.failed)
+        .map(|i| i.file_path.as_str())
+        .collect();
+    diff.added = to_import.iter()
+        .map(|p| p.to_string_lossy().to_string())
+        .filter(|p| !rejected.contains(p.as_str()))
+        .collect();
+    diff.skipped = summary.skipped;
+    diff.failed.extend(summary.failed);
+
+    Ok(diff)
+}
@@ -0,0 +1,119 @@
+use std::path::Path;
+use lofty::{config::WriteOptions, prelude::*, probe::Probe, tag::{ItemKey, ItemValue, Tag, TagItem}};
+
+use crate::db::repo::tracks::ReplayGain;
+
+/// What the importer needs from a file's tags. Missing values stay `None`/empty.
+#[derive(Debug, Clone, Default)]
+pub struct TrackTags {
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub album_artists: Vec<String>,
+    pub album: Option<String>,
+    pub year: Option<i32>,
+    pub track_no: Option<u32>,
+    pub disc_no: Option<u32>,
+    pub duration_secs: f64,
+    pub replay_gain: ReplayGain,
+}
+
+pub fn read_track_tags(p: &Path) -> anyhow::Result<TrackTags> {
+    let tagged = Probe::open(p)?.read()?;
+    let mut out = TrackTags {
+        duration_secs: tagged.properties().duration().as_secs_f64(),
+        ..Default::default()
+    };
+
+    let Some(tag) = tagged.primary_tag().or_else(|| tagged.first_tag()) else {
+        return Ok(out);
+    };
+
+    out.title = non_empty(tag.title().as_deref());
+    out.album = non_empty(tag.album().as_deref());
+    out.year = tag.year().map(|y| y as i32);
+    out.track_no = tag.track();
+    out.disc_no = tag.disk();
+
+    // Prefer the explicit multi-artist field (ARTISTS) over the display string (ARTIST).
+    out.artists = split_names(tag.get_strings(&ItemKey::TrackArtists));
+    if out.artists.is_empty() {
+        out.artists = split_names(tag.get_strings(&ItemKey::TrackArtist));
+    }
+    out.album_artists = split_names(tag.get_strings(&ItemKey::AlbumArtist));
+    out.replay_gain = replay_gain(tag);
+
+    Ok(out)
+}
+
+/// REPLAYGAIN_* values, falling back to Opus' R128_* gains. Those are Q7.8 integers relative
+/// to -23 LUFS; ReplayGain's reference is 5 dB louder, so they're shifted to match.
+fn replay_gain(tag: &Tag) -> ReplayGain {
+    let num = |key: &ItemKey| tag.get_string(key).and_then(parse_number);
+    let r128 = |name: &str| {
+        let v: i32 = tag.get_string(&ItemKey::Unknown(name.into()))?.trim().parse().ok()?;
+        Some(v as f64 / 256.0 + 5.0)
+    };
+    ReplayGain {
+        track_gain: num(&ItemKey::ReplayGainTrackGain).or_else(|| r128("R128_TRACK_GAIN")),
+        track_peak: num(&ItemKey::ReplayGainTrackPeak),
+        album_gain: num(&ItemKey::ReplayGainAlbumGain).or_else(|| r128("R128_ALBUM_GAIN")),
+        album_peak: num(&ItemKey::ReplayGainAlbumPeak),
+    }
+}
+
+/// Write ReplayGain values into the file's primary tag (creating it if needed); `None`
+/// values are left alone. Opus files get R128_* gains instead, as their spec requires.
+pub fn write_replay_gain(p: &Path, rg: &ReplayGain) -> anyhow::Result<()> {
+    let mut tagged = Probe::open(p)?.read()?;
+    let tag_type = tagged.primary_tag_type();
+    if tagged.primary_tag().is_none() {
+        tagged.insert_tag(Tag::new(tag_type));
+    }
+    let tag = tagged.primary_tag_mut().ok_or_else(|| anyhow::anyhow!("no writable tag"))?;
+    let opus = p.extension().is_some_and(|e| e.eq_ignore_ascii_case("opus"));
+
+    let mut set = |key: ItemKey, value: String| {
+        tag.remove_key(&key);
+        tag.insert_unchecked(TagItem::new(key, ItemValue::Text(value)));
+    };
+    let gains = [
+        (rg.track_gain, ItemKey::ReplayGainTrackGain, "R128_TRACK_GAIN"),
+        (rg.album_gain, ItemKey::ReplayGainAlbumGain, "R128_ALBUM_GAIN"),
+    ];
+    for (gain, key, r128) in gains {
+        let Some(gain) = gain else { continue };
+        if opus {
+            set(ItemKey::Unknown(r128.into()), (((gain - 5.0) * 256.0).round() as i32).to_string());
+        } else {
+            set(key, format!("{gain:.2} dB"));
+        }
+    }
+    if !opus {
+        if let Some(peak) = rg.track_peak { set(ItemKey::ReplayGainTrackPeak, format!("{peak:.6}")); }
+        if let Some(peak) = rg.album_peak { set(ItemKey::ReplayGainAlbumPeak, format!("{peak:.6}")); }
+    }
+
+    tag.save_to_path(p, WriteOptions::default())?;
+    Ok(())
+}
+
+/// "-6.48 dB", "+1.2 dB", "0.988831" -> the number.
+fn parse_number(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let s = s.strip_suffix("dB").or_else(|| s.strip_suffix("db")).unwrap_or(s);
+    s.trim().parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+fn non_empty(s: Option<&str>) -> Option<String> {
+    s.map(str::trim).filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+/// Multi-value tags arrive either as repeated items or as one `;`/NUL-separated string.
+fn split_names<'a>(values: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for name in values.flat_map(|v| v.split([';', '\0'])).map(str::trim) {
+        if name.is_empty() || out.iter().any(|n| n.eq_ignore_ascii_case(name)) { continue; }
+        out.push(name.to_string());
+    }
+    out
+}
@@ -0,0 +1,127 @@
+//! Background watcher on the library root.
+//!
+//! Filesystem events (inotify on Linux) are debounced: once a burst goes quiet, the root is
+//! rescanned with [`rescan_root`], which picks up new, changed, moved and removed files by
+//! size/mtime without re-reading untouched ones. Real changes are emitted as `library:changed`.
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tauri::{AppHandle, Emitter};
+
+use crate::db::DbPool;
+use crate::library::is_audio;
+use crate::library::rescan::rescan_root;
+
+/// Rescan once no event has arrived for this long...
+const QUIET_PERIOD: Duration = Duration::from_millis(1500);
+/// ...or at the latest this long after the first event of a burst (e.g. a large copy).
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+struct Active {
+    root: PathBuf,
+    // Dropping the watcher closes the event channel, which ends the debounce thread.
+    _watcher: RecommendedWatcher,
+}
+
+/// Managed as Tauri state. Call [`LibraryWatcher::watch`] whenever the effective root may
+/// have changed; it is a no-op if the root is the one already watched.
+pub struct LibraryWatcher {
+    app: AppHandle,
+    pool: DbPool,
+    active: Mutex<Option<Active>>,
+}
+
+impl LibraryWatcher {
+    pub fn new(app: AppHandle, pool: DbPool) -> Self {
+        Self { app, pool, active: Mutex::new(None) }
+    }
+
+    /// Watch `root` (recursively), replacing any previous watch. `None` or a path that isn't
+    /// a directory just stops watching.
+    pub fn watch(&self, root: Option<PathBuf>) -> notify::Result<()> {
+        let root = root.filter(|r| r.is_dir());
+        let mut active = self.active.lock().unwrap();
+        if active.as_ref().map(|a| &a.root) == root.as_ref() {
+            return Ok(());
+        }
+        *active = None;
+
+        let Some(root) = root else {
+            log::info!("library watcher: stopped");
+            return Ok(());
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+
+        let (app, pool, r) = (self.app.clone(), self.pool.clone(), root.clone());
+        thread::Builder::new()
+            .name("library-watch".into())
+            .spawn(move || debounce_loop(rx, &r, &app, &pool))?;
+
+        log::info!("library watcher: watching {}", root.display());
+        *active = Some(Active { root, _watcher: watcher });
+        Ok(())
+    }
+}
+
+/// Whether an event could affect the library. Directories are let through because renaming
+/// or deleting one moves/removes every file below it; pure reads are ignored.
+fn is_relevant(ev: &notify::Result<Event>) -> bool {
+    match ev {
+        Err(e) => {
+            log::warn!("library watcher: {e}");
+            false
+        }
+        Ok(ev) if ev.need_rescan() => true,
+        Ok(ev) if matches!(ev.kind, EventKind::Access(_)) => false,
+        Ok(ev) => ev.paths.iter().any(|p| is_audio(p) || p.is_dir() || p.extension().is_none()),
+    }
+}
+
+fn debounce_loop(rx: Receiver<notify::Result<Event>>, root: &Path, app: &AppHandle, pool: &DbPool) {
+    loop {
+        // Wait for the start of a burst.
+        loop {
+            match rx.recv() {
+                Ok(ev) if is_relevant(&ev) => break,
+                Ok(_) => continue,
+                Err(_) => return,
+            }
+        }
+
+        // Let it settle.
+        let started = Instant::now();
+        loop {
+            let wait = QUIET_PERIOD.min(MAX_DELAY.saturating_sub(started.elapsed()));
+            if wait.is_zero() { break; }
+            match rx.recv_timeout(wait) {
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+
+        if !root.is_dir() { continue; }
+        let mut conn = match pool.get() {
+            Ok(c) => c,
+            Err(e) => {
+                log::warn!("library watcher: {e}");
+                continue;
+            }
+        };
+        match rescan_root(&mut conn, root, |_| {}) {
+            Ok(diff) if diff.has_changes() => {
+                let _ = app.emit("library:changed", &diff);
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("library watcher: rescan of {} failed: {e}", root.display()),
+        }
+    }
+}
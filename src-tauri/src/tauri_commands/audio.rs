@@ -1,6 +1,19 @@
-use std::sync::{Arc, atomic::{AtomicU64, AtomicU32}};
+use std::sync::{Arc, Mutex, atomic::{AtomicU64, AtomicU32}};
 use tauri::{AppHandle, State};
+use crate::audio::crossfade::{CrossfadeConfig, FadeCurve};
+use crate::audio::resample::ResampleQuality;
+use crate::audio::replaygain::ReplayGainMode;
+use crate::audio::device::{self, OutputDeviceInfo};
+use crate::audio::dsp::{builtin_presets, DspConfig, DspPreset};
+use crate::audio::queue::{PlayMode, QueueState};
+use crate::audio::channels::ChannelMatrix;
+use crate::audio::gain::{DEFAULT_FADE_MS, MAX_FADE_MS};
+use crate::audio::latency::{LatencyProfile, LatencyReport};
+use crate::audio::stretch::validate_speed;
 use crate::audio::runtime::{self, Cmd};
+use crate::db::DbPool;
+use crate::db::repo::dsp as dsp_repo;
+use crate::db::repo::settings::{self as settings_repo, CrossfadeSettings};
 
 pub struct AudioManager {
     pub tx: std::sync::mpsc::Sender<Cmd>,
@@ -9,6 +22,11 @@ pub struct AudioManager {
     pub peak_l: Arc<AtomicU32>,
     pub peak_r: Arc<AtomicU32>,
     pub sample_rate: Arc<AtomicU32>,
+    pub volume: Arc<AtomicU32>,
+    pub device_name: Arc<Mutex<Option<String>>>,
+    pub queue: Arc<Mutex<QueueState>>,
+    pub latency: Arc<Mutex<LatencyReport>>,
+    pub device_latency_us: Arc<AtomicU32>,
 }
 
 impl AudioManager {
@@ -21,6 +39,11 @@ impl AudioManager {
             peak_l: rt.metrics.peak_l,
             peak_r: rt.metrics.peak_r,
             sample_rate: rt.metrics.sample_rate,
+            volume: rt.metrics.volume,
+            device_name: rt.metrics.device_name,
+            queue: rt.metrics.queue,
+            latency: rt.metrics.latency,
+            device_latency_us: rt.metrics.device_latency_us,
         }
     }
 
@@ -34,6 +57,74 @@ impl AudioManager {
             / sample_rate as f64
     }
 
+    /// Push persisted playback settings into the freshly spawned engine.
+    pub fn restore_settings(&self, conn: &rusqlite::Connection) -> Result<(), String> {
+        let device = settings_repo::output_device(conn).map_err(|e| e.to_string())?;
+        if device.is_some() { self.tx.send(Cmd::SetOutputDevice(device)).map_err(|e| e.to_string())?; }
+
+        let latency = latency_profile(conn)?;
+        if latency != LatencyProfile::default() { self.tx.send(Cmd::SetLatency(latency)).map_err(|e| e.to_string())?; }
+
+        let cf = settings_repo::crossfade(conn).map_err(|e| e.to_string())?;
+        self.tx.send(Cmd::SetCrossfade(crossfade_config(&cf))).map_err(|e| e.to_string())?;
+
+        let quality = settings_repo::resample_quality(conn).map_err(|e| e.to_string())?;
+        let quality = ResampleQuality::parse(&quality).unwrap_or_default();
+        self.tx.send(Cmd::SetResampleQuality(quality)).map_err(|e| e.to_string())?;
+
+        let mode = settings_repo::replaygain_mode(conn).map_err(|e| e.to_string())?;
+        let mode = ReplayGainMode::parse(&mode).unwrap_or_default();
+        self.tx.send(Cmd::SetReplayGainMode(mode)).map_err(|e| e.to_string())?;
+
+        // the engine knows the device, so it picks the DSP profile itself
+        self.tx.send(Cmd::ReloadDspProfile).map_err(|e| e.to_string())?;
+
+        if settings_repo::bit_perfect(conn).map_err(|e| e.to_string())? {
+            self.tx.send(Cmd::SetBitPerfect(true)).map_err(|e| e.to_string())?;
+        }
+        let matrix = channel_matrix(conn)?;
+        if matrix != ChannelMatrix::default() { self.tx.send(Cmd::SetChannelMatrix(matrix)).map_err(|e| e.to_string())?; }
+
+        if settings_repo::noise_shaping(conn).map_err(|e| e.to_string())? {
+            self.tx.send(Cmd::SetNoiseShaping(true)).map_err(|e| e.to_string())?;
+        }
+        let fade = settings_repo::transport_fade_ms(conn).map_err(|e| e.to_string())?;
+        if fade != DEFAULT_FADE_MS { self.tx.send(Cmd::SetTransportFade(fade)).map_err(|e| e.to_string())?; }
+        if settings_repo::remember_track_speed(conn).map_err(|e| e.to_string())? {
+            self.tx.send(Cmd::SetRememberSpeed(true)).map_err(|e| e.to_string())?;
+        }
+        let speed = settings_repo::playback_speed(conn).map_err(|e| e.to_string())? as f32;
+        if speed != 1.0 { self.tx.send(Cmd::SetSpeed(speed)).map_err(|e| e.to_string())?; }
+
+        // queue, position, volume and play mode from the last run
+        self.tx.send(Cmd::RestoreSession).map_err(|e| e.to_string())
+    }
+
+    /// Save the playback session and wait (briefly) for it to be written; used at exit.
+    pub fn save_session(&self) {
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        if self.tx.send(Cmd::SaveSession(done_tx)).is_ok() {
+            let _ = done_rx.recv_timeout(std::time::Duration::from_secs(2));
+        }
+    }
+}
+
+fn channel_matrix(conn: &rusqlite::Connection) -> Result<ChannelMatrix, String> {
+    let json = settings_repo::channel_matrix(conn).map_err(|e| e.to_string())?;
+    Ok(json.as_deref().and_then(ChannelMatrix::from_json).unwrap_or_default())
+}
+
+fn latency_profile(conn: &rusqlite::Connection) -> Result<LatencyProfile, String> {
+    let (name, frames) = settings_repo::latency(conn).map_err(|e| e.to_string())?;
+    Ok(LatencyProfile::parse(&name, frames).unwrap_or_default())
+}
+
+fn crossfade_config(cf: &CrossfadeSettings) -> CrossfadeConfig {
+    CrossfadeConfig {
+        seconds: cf.seconds as f32,
+        curve: FadeCurve::parse(&cf.curve).unwrap_or(FadeCurve::EqualPower),
+        skip_same_album: cf.skip_same_album,
+    }
 }
 
 // ===== Commands =====
@@ -102,8 +193,354 @@ pub async fn prev_track(state: State<'_, AudioManager>) -> Result<String, String
     Ok("Prev".into())
 }
 
+#[tauri::command]
+pub async fn get_volume(state: State<'_, AudioManager>) -> Result<f32, String> {
+    Ok(f32::from_bits(state.inner().volume.load(std::sync::atomic::Ordering::Relaxed)))
+}
+
+#[tauri::command]
+pub async fn list_output_devices() -> Result<Vec<OutputDeviceInfo>, String> {
+    tauri::async_runtime::spawn_blocking(device::list_output_devices)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[derive(serde::Serialize)]
+pub struct OutputDeviceStatus {
+    /// The stored choice; `None` follows the system default.
+    pub selected: Option<String>,
+    /// The device actually playing (the default when the chosen one is missing).
+    pub active: Option<String>,
+}
+
+#[tauri::command]
+pub async fn get_output_device(db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<OutputDeviceStatus, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let selected = settings_repo::output_device(&conn).map_err(|e| e.to_string())?;
+    Ok(OutputDeviceStatus { selected, active: state.inner().device_name.lock().unwrap().clone() })
+}
+
+/// Persist the output device choice (`None`: system default) and move playback to it.
+#[tauri::command]
+pub async fn set_output_device(name: Option<String>, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_output_device(&conn, name.as_deref()).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetOutputDevice(name)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_bit_perfect(db: State<'_, DbPool>) -> Result<bool, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::bit_perfect(&conn).map_err(|e| e.to_string())
+}
+
+/// Persist and apply bit-perfect output. The engine reports the resulting stream format with
+/// `audio:format` (its `bit_perfect` is false when the device can't play a track's format).
+#[tauri::command]
+pub async fn set_bit_perfect(enabled: bool, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_bit_perfect(&conn, enabled).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetBitPerfect(enabled)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_channel_matrix(db: State<'_, DbPool>) -> Result<ChannelMatrix, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    channel_matrix(&conn)
+}
+
+/// Persist and apply the down/upmix matrix; the playing track switches over right away.
+#[tauri::command]
+pub async fn set_channel_matrix(matrix: ChannelMatrix, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    matrix.validate()?;
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_channel_matrix(&conn, &matrix.to_json()).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetChannelMatrix(matrix)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_noise_shaping(db: State<'_, DbPool>) -> Result<bool, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::noise_shaping(&conn).map_err(|e| e.to_string())
+}
+
+/// Persist and apply noise-shaped dither. It only matters when the device takes 16- or 24-bit
+/// integer samples (see `sample_format` in `audio:format`).
+#[tauri::command]
+pub async fn set_noise_shaping(enabled: bool, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_noise_shaping(&conn, enabled).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetNoiseShaping(enabled)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_transport_fade(db: State<'_, DbPool>) -> Result<u32, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::transport_fade_ms(&conn).map_err(|e| e.to_string())
+}
+
+/// Persist and apply the fade length (ms) on pause, resume, stop and skip; 0 turns them off.
+#[tauri::command]
+pub async fn set_transport_fade(ms: u32, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    if ms > MAX_FADE_MS { return Err(format!("Fades can be at most {MAX_FADE_MS} ms")); }
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_transport_fade_ms(&conn, ms).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetTransportFade(ms)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_playback_speed(db: State<'_, DbPool>) -> Result<f32, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::playback_speed(&conn).map(|s| s as f32).map_err(|e| e.to_string())
+}
+
+/// Persist and apply the playback speed (0.5 to 3); the pitch stays as it is. The playing
+/// track changes speed right away, and keeps it if per-file speeds are remembered.
+#[tauri::command]
+pub async fn set_playback_speed(speed: f32, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    let speed = validate_speed(speed)?;
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_playback_speed(&conn, speed as f64).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetSpeed(speed)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_remember_track_speed(db: State<'_, DbPool>) -> Result<bool, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::remember_track_speed(&conn).map_err(|e| e.to_string())
+}
+
+/// Persist and apply whether each file keeps the speed it was last played at. The current
+/// speed (`audio:speed`) then follows the track.
+#[tauri::command]
+pub async fn set_remember_track_speed(on: bool, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_remember_track_speed(&conn, on).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetRememberSpeed(on)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_latency_profile(db: State<'_, DbPool>) -> Result<LatencyProfile, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    latency_profile(&conn)
+}
+
+/// Persist and apply a latency profile; the stream is reopened and `audio:latency` reports
+/// what the device accepted.
+#[tauri::command]
+pub async fn set_latency_profile(profile: LatencyProfile, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    let profile = profile.validate()?;
+    let frames = match profile { LatencyProfile::Custom { frames } => Some(frames), _ => None };
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_latency(&conn, profile.as_str(), frames).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetLatency(profile)).map_err(|e| e.to_string())
+}
+
+/// The stream's buffer and prebuffer as opened, plus the device's own delay once it is playing.
+#[tauri::command]
+pub async fn get_output_latency(state: State<'_, AudioManager>) -> Result<LatencyReport, String> {
+    let mgr = state.inner();
+    let mut report = mgr.latency.lock().unwrap().clone();
+    let us = mgr.device_latency_us.load(std::sync::atomic::Ordering::Relaxed);
+    report.device_ms = (us > 0).then(|| us as f64 / 1000.0);
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn get_play_mode(state: State<'_, AudioManager>) -> Result<PlayMode, String> {
+    Ok(state.inner().queue.lock().unwrap().mode)
+}
+
+/// Off, repeat-all, repeat-one, shuffle or album shuffle; the engine emits `audio:mode` once applied.
+#[tauri::command]
+pub async fn set_play_mode(mode: PlayMode, state: State<'_, AudioManager>) -> Result<(), String> {
+    state.inner().tx.send(Cmd::SetPlayMode(mode)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_queue(state: State<'_, AudioManager>) -> Result<QueueState, String> {
+    Ok(state.inner().queue.lock().unwrap().clone())
+}
+
+// Queue edits leave the playing track alone; the engine emits `audio:queue` after each.
+
+#[tauri::command]
+pub async fn queue_append(items: Vec<String>, state: State<'_, AudioManager>) -> Result<(), String> {
+    state.inner().tx.send(Cmd::QueueAppend(items)).map_err(|e| e.to_string())
+}
+
+/// Insert items to play right after the current track.
+#[tauri::command]
+pub async fn queue_play_next(items: Vec<String>, state: State<'_, AudioManager>) -> Result<(), String> {
+    state.inner().tx.send(Cmd::QueuePlayNext(items)).map_err(|e| e.to_string())
+}
+
+/// Fails for an index out of range and for the item that is playing (or already decoded next).
+#[tauri::command]
+pub async fn queue_remove(index: usize, state: State<'_, AudioManager>) -> Result<(), String> {
+    let (reply_tx, reply_rx) = std::sync::mpsc::channel();
+    state.inner().tx.send(Cmd::QueueRemove(index, reply_tx)).map_err(|e| e.to_string())?;
+    // the runtime may be busy (fading, prebuffering): wait off the async workers
+    tauri::async_runtime::spawn_blocking(move || reply_rx.recv())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?
+}
+
+/// Move item `from` to index `to`.
+#[tauri::command]
+pub async fn queue_move(from: usize, to: usize, state: State<'_, AudioManager>) -> Result<(), String> {
+    state.inner().tx.send(Cmd::QueueMove(from, to)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn queue_clear_upcoming(state: State<'_, AudioManager>) -> Result<(), String> {
+    state.inner().tx.send(Cmd::QueueClearUpcoming).map_err(|e| e.to_string())
+}
+
+/// Start playing queue item `index`.
+#[tauri::command]
+pub async fn queue_jump(index: usize, state: State<'_, AudioManager>) -> Result<(), String> {
+    state.inner().tx.send(Cmd::QueueJump(index)).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn play_selection(items: Vec<String>, start_at: usize, state: State<'_, AudioManager>) -> Result<String, String> {
     state.inner().tx.send(Cmd::SetQueueAndPlay(items, start_at)).map_err(|e| e.to_string())?;
     Ok("OK".into())
-}
\ No newline at end of file
+}
+#[tauri::command]
+pub async fn get_crossfade(db: State<'_, DbPool>) -> Result<CrossfadeConfig, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let cf = settings_repo::crossfade(&conn).map_err(|e| e.to_string())?;
+    Ok(crossfade_config(&cf))
+}
+
+/// Persist and apply crossfade settings. `seconds` of 0 turns crossfading off.
+#[tauri::command]
+pub async fn set_crossfade(config: CrossfadeConfig, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    if !(0.0..=CrossfadeConfig::MAX_SECONDS).contains(&config.seconds) {
+        return Err(format!("Crossfade must be between 0 and {} seconds", CrossfadeConfig::MAX_SECONDS));
+    }
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_crossfade(&conn, &CrossfadeSettings {
+        seconds: config.seconds as f64,
+        curve: config.curve.as_str().to_string(),
+        skip_same_album: config.skip_same_album,
+    }).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetCrossfade(config)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_resample_quality(db: State<'_, DbPool>) -> Result<ResampleQuality, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let quality = settings_repo::resample_quality(&conn).map_err(|e| e.to_string())?;
+    Ok(ResampleQuality::parse(&quality).unwrap_or_default())
+}
+
+/// Persist and apply the resampler preset. Takes effect from the next seek or format change.
+#[tauri::command]
+pub async fn set_resample_quality(quality: ResampleQuality, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_resample_quality(&conn, quality.as_str()).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetResampleQuality(quality)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_replaygain_mode(db: State<'_, DbPool>) -> Result<ReplayGainMode, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let mode = settings_repo::replaygain_mode(&conn).map_err(|e| e.to_string())?;
+    Ok(ReplayGainMode::parse(&mode).unwrap_or_default())
+}
+
+/// Persist and apply the loudness normalization mode; the playing track is adjusted right away.
+#[tauri::command]
+pub async fn set_replaygain_mode(mode: ReplayGainMode, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    settings_repo::set_replaygain_mode(&conn, mode.as_str()).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::SetReplayGainMode(mode)).map_err(|e| e.to_string())
+}
+
+/// The stored DSP config for `device` (its profile, falling back to the global config), or
+/// the global config when `device` is `None`.
+#[tauri::command]
+pub async fn get_dsp_config(device: Option<String>, db: State<'_, DbPool>) -> Result<DspConfig, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let profile = match &device {
+        Some(d) => dsp_repo::device_profile(&conn, d).map_err(|e| e.to_string())?,
+        None => None,
+    };
+    let json = match profile {
+        Some(json) => Some(json),
+        None => dsp_repo::global_config(&conn).map_err(|e| e.to_string())?,
+    };
+    Ok(json.as_deref().and_then(DspConfig::from_json).unwrap_or_default())
+}
+
+/// Persist a DSP config as the profile of `device`, or as the global config when `None`, and
+/// re-apply whichever config the current output device uses.
+#[tauri::command]
+pub async fn set_dsp_config(config: DspConfig, device: Option<String>, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<(), String> {
+    config.validate()?;
+    let conn = db.get().map_err(|e| e.to_string())?;
+    match &device {
+        Some(d) => dsp_repo::set_device_profile(&conn, d, &config.to_json()),
+        None => dsp_repo::set_global_config(&conn, &config.to_json()),
+    }.map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::ReloadDspProfile).map_err(|e| e.to_string())
+}
+
+/// Apply a DSP config right away without saving it, for live adjustment.
+#[tauri::command]
+pub async fn preview_dsp_config(config: DspConfig, state: State<'_, AudioManager>) -> Result<(), String> {
+    config.validate()?;
+    state.inner().tx.send(Cmd::SetDsp(config)).map_err(|e| e.to_string())
+}
+
+/// Remove a device's profile; it falls back to the global config.
+#[tauri::command]
+pub async fn delete_dsp_profile(device: String, db: State<'_, DbPool>, state: State<'_, AudioManager>) -> Result<bool, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let deleted = dsp_repo::delete_device_profile(&conn, &device).map_err(|e| e.to_string())?;
+    state.inner().tx.send(Cmd::ReloadDspProfile).map_err(|e| e.to_string())?;
+    Ok(deleted)
+}
+
+#[tauri::command]
+pub async fn list_dsp_profiles(db: State<'_, DbPool>) -> Result<Vec<String>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    dsp_repo::profile_devices(&conn).map_err(|e| e.to_string())
+}
+
+/// Built-in presets followed by the user's own.
+#[tauri::command]
+pub async fn list_dsp_presets(db: State<'_, DbPool>) -> Result<Vec<DspPreset>, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let mut presets = builtin_presets();
+    for (name, json) in dsp_repo::presets(&conn).map_err(|e| e.to_string())? {
+        match DspConfig::from_json(&json) {
+            Some(config) => presets.push(DspPreset { name, builtin: false, config }),
+            None => log::warn!("dsp preset {name}: unreadable config"),
+        }
+    }
+    Ok(presets)
+}
+
+#[tauri::command]
+pub async fn save_dsp_preset(name: String, config: DspConfig, db: State<'_, DbPool>) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() { return Err("Preset name is empty".into()); }
+    if builtin_presets().iter().any(|p| p.name.eq_ignore_ascii_case(name)) {
+        return Err(format!("\"{name}\" is a built-in preset"));
+    }
+    config.validate()?;
+    let conn = db.get().map_err(|e| e.to_string())?;
+    dsp_repo::save_preset(&conn, name, &config.to_json()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_dsp_preset(name: String, db: State<'_, DbPool>) -> Result<bool, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    dsp_repo::delete_preset(&conn, &name).map_err(|e| e.to_string())
+}
@@ -1,5 +1,6 @@
-use std::{fs, io::Read, path::{Path, PathBuf}};
+use std::path::{Path, PathBuf};
 use rusqlite::Connection;
+use crate::db::repo::RepoError;
 
 pub fn sanitize_component(s: &str) -> String {
     let mut out = s.trim().to_string();
@@ -9,28 +10,10 @@ pub fn sanitize_component(s: &str) -> String {
     out
 }
 
-pub fn blake3_hex_of_file(p: &Path) -> anyhow::Result<String> {
-    let mut f = fs::File::open(p)?;
-    let mut hasher = blake3::Hasher::new();
-    let mut buf = [0u8; 128 * 1024];
-    loop {
-        let n = f.read(&mut buf)?;
-        if n == 0 { break; }
-        hasher.update(&buf[..n]);
-    }
-    Ok(hasher.finalize().to_hex().to_string())
-}
+pub use crate::utils::hash::blake3_hex_of_file;
 
-pub fn resolve_effective_root(conn: &Connection) -> rusqlite::Result<Option<String>> {
-    let mut stmt = conn.prepare(
-        "SELECT library_root, use_managed_dir, managed_root FROM settings WHERE id=1"
-    )?;
-    stmt.query_row([], |row| {
-        let library_root: Option<String> = row.get(0)?;
-        let use_managed: i64 = row.get(1)?;
-        let managed_root: String = row.get(2)?;
-        Ok(if use_managed != 0 { Some(managed_root) } else { library_root })
-    })
+pub fn resolve_effective_root(conn: &Connection) -> Result<Option<String>, RepoError> {
+    crate::db::repo::settings::effective_root(conn)
 }
 
 pub fn is_audio(p: &Path) -> bool {
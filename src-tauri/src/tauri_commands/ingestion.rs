@@ -1,9 +1,13 @@
 use std::{fs, path::PathBuf};
-use rusqlite::params;
-use serde::Deserialize;
-use tauri::State;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
 
 use crate::db::{DbPool, default_managed_root};
+use crate::db::repo::{albums, artists, ingestion, tracks::NewTrack};
+use crate::db::repo::loudness;
+use crate::library::analyze::LoudnessJob;
+use crate::library::import::{collect_audio_files, import_files, ImportProgress, ImportSummary};
+use crate::library::rescan::{rescan_root, RescanDiff};
 use super::common::{sanitize_component, blake3_hex_of_file, resolve_effective_root};
 
 #[derive(Debug, Deserialize)]
@@ -14,15 +18,7 @@ pub struct RegisterArtistArgs {
 #[tauri::command]
 pub async fn register_artist(args: RegisterArtistArgs, db: State<'_, DbPool>) -> Result<i64, String> {
     let conn = db.get().map_err(|e| e.to_string())?;
-    conn.execute("INSERT OR IGNORE INTO artists (name) VALUES (?1)", [&args.name])
-        .map_err(|e| e.to_string())?;
-    let id: i64 = if conn.changes() == 0 {
-        conn.prepare("SELECT id FROM artists WHERE name=?1").map_err(|e| e.to_string())?
-            .query_row([&args.name], |r| r.get(0)).map_err(|e| e.to_string())?
-    } else {
-        conn.last_insert_rowid()
-    };
-    Ok(id)
+    artists::upsert(&conn, &args.name).map_err(|e| e.to_string())
 }
 
 #[derive(Debug, Deserialize)]
@@ -37,25 +33,8 @@ pub async fn register_album(args: RegisterAlbumArgs, db: State<'_, DbPool>) -> R
     let mut conn = db.get().map_err(|e| e.to_string())?;
     let tx = conn.transaction().map_err(|e| e.to_string())?;
 
-    tx.execute("INSERT OR IGNORE INTO albums (title, year) VALUES (?1, ?2)",
-               params![args.title, args.year]).map_err(|e| e.to_string())?;
-
-    let album_id: i64 = if tx.changes() == 0 {
-        tx.prepare("SELECT id FROM albums WHERE title=?1 AND COALESCE(year,0)=COALESCE(?2,0)")
-            .map_err(|e| e.to_string())?
-            .query_row(params![args.title, args.year], |r| r.get(0))
-            .map_err(|e| e.to_string())?
-    } else { tx.last_insert_rowid() };
-
-    // Scope the statement so it drops before commit
-    {
-        let mut ins = tx.prepare(
-            "INSERT OR IGNORE INTO album_artists (album_id, artist_id) VALUES (?1, ?2)"
-        ).map_err(|e| e.to_string())?;
-        for aid in args.artist_ids {
-            ins.execute(params![album_id, aid]).map_err(|e| e.to_string())?;
-        }
-    }
+    let album_id = albums::upsert_with_artists(&tx, &args.title, args.year, &args.artist_ids)
+        .map_err(|e| e.to_string())?;
 
     tx.commit().map_err(|e| e.to_string())?;
     Ok(album_id)
@@ -85,15 +64,15 @@ pub async fn register_track(args: RegisterTrackArgs, db: State<'_, DbPool>) -> R
     let should_move = args.move_into_managed.unwrap_or(true);
     let (final_path, file_hash) = if should_move {
         // primary artist = first id
-        let primary_artist: String = if let Some(aid) = args.artist_ids.first() {
-            tx.prepare("SELECT name FROM artists WHERE id=?1").map_err(|e| e.to_string())?
-                .query_row([aid], |r| r.get::<_, String>(0)).unwrap_or("Unknown Artist".into())
-        } else { "Unknown Artist".into() };
+        let primary_artist: String = match args.artist_ids.first() {
+            Some(&aid) => artists::name_of(&tx, aid).map_err(|e| e.to_string())?,
+            None => None,
+        }.unwrap_or_else(|| "Unknown Artist".into());
 
-        let album_title: String = if let Some(id) = args.album_id {
-            tx.prepare("SELECT title FROM albums WHERE id=?1").map_err(|e| e.to_string())?
-                .query_row([id], |r| r.get::<_, String>(0)).unwrap_or("Singles".into())
-        } else { "Singles".into() };
+        let album_title: String = match args.album_id {
+            Some(id) => albums::title_of(&tx, id).map_err(|e| e.to_string())?,
+            None => None,
+        }.unwrap_or_else(|| "Singles".into());
 
         let ext = src.extension().and_then(|e| e.to_str()).unwrap_or("flac");
         let fname = args.title.clone()
@@ -114,34 +93,102 @@ pub async fn register_track(args: RegisterTrackArgs, db: State<'_, DbPool>) -> R
         (src, hash)
     };
 
-    tx.execute(
-        "INSERT OR IGNORE INTO tracks (title, duration_secs, file_path, file_hash, album_id)
-         VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![
-            args.title.clone().unwrap_or_else(|| final_path.file_stem().and_then(|s| s.to_str()).unwrap_or("Track").to_string()),
-            args.duration_secs.unwrap_or(0.0),
-            final_path.to_string_lossy().to_string(),
-            file_hash,
-            args.album_id
-        ],
-    ).map_err(|e| e.to_string())?;
-
-    let track_id: i64 = if tx.changes() == 0 {
-        tx.prepare("SELECT id FROM tracks WHERE file_path=?1").map_err(|e| e.to_string())?
-            .query_row([final_path.to_string_lossy().to_string()], |r| r.get(0))
-            .map_err(|e| e.to_string())?
-    } else { tx.last_insert_rowid() };
-
-    // Scope stmt to drop before commit
-    {
-        let mut ins = tx.prepare(
-            "INSERT OR IGNORE INTO track_artists (track_id, artist_id, role) VALUES (?1, ?2, NULL)"
-        ).map_err(|e| e.to_string())?;
-        for aid in args.artist_ids {
-            ins.execute(params![track_id, aid]).map_err(|e| e.to_string())?;
-        }
-    }
+    let title = args.title.clone()
+        .unwrap_or_else(|| final_path.file_stem().and_then(|s| s.to_str()).unwrap_or("Track").to_string());
+    let final_path = final_path.to_string_lossy().to_string();
+    let track_id = ingestion::register_track(&tx, &NewTrack {
+        title: &title,
+        duration_secs: args.duration_secs.unwrap_or(0.0),
+        file_path: &final_path,
+        file_hash: &file_hash,
+        album_id: args.album_id,
+        track_no: None,
+        disc_no: None,
+        file_size: None,
+        file_mtime: None,
+    }, &args.artist_ids).map_err(|e| e.to_string())?;
 
     tx.commit().map_err(|e| e.to_string())?;
     Ok(track_id)
 }
+
+/// Emit `library:import` progress every this many files (and on the last one).
+const IMPORT_PROGRESS_EVERY: usize = 25;
+
+fn emit_import_progress(app: &AppHandle, p: &ImportProgress) {
+    if p.processed % IMPORT_PROGRESS_EVERY == 0 || p.processed == p.total {
+        let _ = app.emit("library:import", p);
+    }
+}
+
+/// `root` if given, else the effective library root from settings.
+fn import_root(conn: &rusqlite::Connection, root: Option<String>) -> Result<PathBuf, String> {
+    let root = match root {
+        Some(r) => r,
+        None => resolve_effective_root(conn).map_err(|e| e.to_string())?
+            .ok_or_else(|| "No library root configured".to_string())?,
+    };
+    let root = PathBuf::from(root);
+    if !root.is_dir() { return Err("Not a directory".into()); }
+    Ok(root)
+}
+
+/// Walk `root` (default: the effective library root), read tags and register every new audio
+/// file in place. Emits `library:import` progress events; returns what happened per file.
+#[tauri::command]
+pub async fn import_library(root: Option<String>, app: AppHandle, db: State<'_, DbPool>) -> Result<ImportSummary, String> {
+    let pool = db.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut conn = pool.get().map_err(|e| e.to_string())?;
+        let root = import_root(&conn, root)?;
+        let files = collect_audio_files(&root);
+        import_files(&mut conn, &files, |p| emit_import_progress(&app, p)).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Incremental rescan of `root` (default: the effective library root): picks up new, changed,
+/// moved and missing files. Emits `library:rescan` with the diff when anything changed.
+#[tauri::command]
+pub async fn rescan_library(root: Option<String>, app: AppHandle, db: State<'_, DbPool>) -> Result<RescanDiff, String> {
+    let pool = db.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut conn = pool.get().map_err(|e| e.to_string())?;
+        let root = import_root(&conn, root)?;
+        let diff = rescan_root(&mut conn, &root, |p| emit_import_progress(&app, p))
+            .map_err(|e| e.to_string())?;
+        if diff.has_changes() {
+            let _ = app.emit("library:rescan", &diff);
+        }
+        Ok(diff)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Serialize)]
+pub struct LoudnessStatus {
+    pub running: bool,
+    /// Tracks without a measurement of their current content.
+    pub pending: usize,
+}
+
+/// Measure integrated loudness, true peak and loudness range of every pending track in the
+/// background. Emits `library:analysis` progress and `library:analysis-done` at the end.
+#[tauri::command]
+pub async fn start_loudness_analysis(write_tags: Option<bool>, job: State<'_, LoudnessJob>) -> Result<(), String> {
+    job.start(write_tags.unwrap_or(false))
+}
+
+#[tauri::command]
+pub async fn cancel_loudness_analysis(job: State<'_, LoudnessJob>) -> Result<bool, String> {
+    job.cancel()
+}
+
+#[tauri::command]
+pub async fn loudness_analysis_status(db: State<'_, DbPool>, job: State<'_, LoudnessJob>) -> Result<LoudnessStatus, String> {
+    let conn = db.get().map_err(|e| e.to_string())?;
+    let pending = loudness::pending_count(&conn).map_err(|e| e.to_string())?;
+    Ok(LoudnessStatus { running: job.is_running(), pending })
+}
@@ -1,6 +1,5 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::collections::HashSet;
 
 use base64::Engine;
 use image::ImageFormat;
@@ -14,6 +13,7 @@ use serde::Serialize;
 use tauri::State;
 
 use crate::db::DbPool;
+use crate::db::repo::{albums, artists, tracks};
 
 // Reuse your existing library helpers/types
 use crate::library::{is_audio, TrackInfo};
@@ -23,18 +23,8 @@ use crate::library::thumbs::{
     crop_center_square, file_fingerprint, load_embedded_or_sidecar_bytes, thumb_cache_dir,
 };
 
-#[derive(Debug, Serialize)]
-pub struct ArtistRow {
-    pub id: i64,
-    pub name: String,
-}
-
-#[derive(Debug, Serialize)]
-pub struct AlbumRow {
-    pub id: i64,
-    pub title: String,
-    pub year: Option<i32>,
-}
+pub use crate::db::repo::albums::Album as AlbumRow;
+pub use crate::db::repo::artists::Artist as ArtistRow;
 
 fn file_path_to_string(fp: FilePath) -> String {
     if let Some(p) = fp.as_path() {
@@ -217,6 +207,7 @@ pub struct DbTrack {
     pub album: Option<String>,
     pub artists: Vec<String>,
     pub has_art: bool,
+    pub available: bool,
 }
 
 /// List registered tracks from the DB (artists aggregated, optional album title).
@@ -224,48 +215,16 @@ pub struct DbTrack {
 pub async fn list_tracks(db: State<'_, DbPool>) -> Result<Vec<DbTrack>, String> {
     let conn = db.get().map_err(|e| e.to_string())?;
 
-    let mut stmt = conn.prepare(
-        "SELECT
-           t.id,
-           t.title,
-           IFNULL(t.duration_secs, 0.0),
-           t.file_path,
-           (SELECT al.title FROM albums al WHERE al.id = t.album_id) AS album,
-           IFNULL((
-             SELECT GROUP_CONCAT(ar.name, ', ')
-             FROM track_artists ta
-             JOIN artists ar ON ar.id = ta.artist_id
-             WHERE ta.track_id = t.id
-           ), '') AS artists_csv
-         FROM tracks t
-         ORDER BY t.title COLLATE NOCASE"
-    ).map_err(|e| e.to_string())?;
-
-    let rows = stmt.query_map([], |r| {
-        let artists_csv: String = r.get(5)?;
-        let artists = if artists_csv.is_empty() {
-            vec![]
-        } else {
-            artists_csv.split(',').map(|s| s.trim().to_string()).collect()
-        };
-        Ok((
-            r.get::<_, i64>(0)?,
-            r.get::<_, String>(1)?,
-            r.get::<_, f64>(2)?,
-            r.get::<_, String>(3)?,
-            r.get::<_, Option<String>>(4)?,
-            artists,
-        ))
-    }).map_err(|e| e.to_string())?;
+    let rows = tracks::list(&conn).map_err(|e| e.to_string())?;
 
-    let mut out = Vec::new();
-    for row in rows {
-        let (id, title, duration_secs, file_path, album, artists) = row.map_err(|e| e.to_string())?;
-        let has_art = quick_has_embedded_or_sidecar_art(Path::new(&file_path));
-        out.push(DbTrack {
-            id, title, duration_secs, file_path, album, artists, has_art
-        });
-    }
+    let out = rows.into_iter().map(|t| {
+        let has_art = t.available && quick_has_embedded_or_sidecar_art(Path::new(&t.file_path));
+        DbTrack {
+            id: t.id, title: t.title, duration_secs: t.duration_secs,
+            file_path: t.file_path, album: t.album, artists: t.artists, has_art,
+            available: t.available,
+        }
+    }).collect();
     Ok(out)
 }
 
@@ -285,15 +244,7 @@ pub async fn list_unregistered(root: String, db: State<'_, DbPool>) -> Result<Ve
     let conn = db.get().map_err(|e| e.to_string())?;
 
     // Gather known file paths
-    let mut known = HashSet::<String>::new();
-    {
-        let mut stmt = conn.prepare("SELECT file_path FROM tracks").map_err(|e| e.to_string())?;
-        let mut rows = stmt.query([]).map_err(|e| e.to_string())?;
-        while let Some(r) = rows.next().map_err(|e| e.to_string())? {
-            let p: String = r.get(0).map_err(|e| e.to_string())?;
-            known.insert(p);
-        }
-    }
+    let known = tracks::all_paths(&conn).map_err(|e| e.to_string())?;
 
     let mut out = Vec::new();
     for entry in WalkDir::new(&root_pb).into_iter().filter_map(Result::ok) {
@@ -314,29 +265,13 @@ pub async fn list_unregistered(root: String, db: State<'_, DbPool>) -> Result<Ve
 #[tauri::command]
 pub async fn list_artists(db: State<'_, DbPool>) -> Result<Vec<ArtistRow>, String> {
     let conn = db.get().map_err(|e| e.to_string())?;
-    let mut stmt = conn.prepare("SELECT id, name FROM artists ORDER BY name COLLATE NOCASE")
-        .map_err(|e| e.to_string())?;
-    let rows = stmt.query_map([], |r| {
-        Ok(ArtistRow { id: r.get(0)?, name: r.get(1)? })
-    }).map_err(|e| e.to_string())?;
-
-    let mut out = Vec::new();
-    for r in rows { out.push(r.map_err(|e| e.to_string())?); }
-    Ok(out)
+    artists::list(&conn).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
 pub async fn list_albums(db: State<'_, DbPool>) -> Result<Vec<AlbumRow>, String> {
     let conn = db.get().map_err(|e| e.to_string())?;
-    let mut stmt = conn.prepare("SELECT id, title, year FROM albums ORDER BY title COLLATE NOCASE, IFNULL(year,0)")
-        .map_err(|e| e.to_string())?;
-    let rows = stmt.query_map([], |r| {
-        Ok(AlbumRow { id: r.get(0)?, title: r.get(1)?, year: r.get(2)? })
-    }).map_err(|e| e.to_string())?;
-
-    let mut out = Vec::new();
-    for r in rows { out.push(r.map_err(|e| e.to_string())?); }
-    Ok(out)
+    albums::list(&conn).map_err(|e| e.to_string())
 }
 
 /// Open a single audio file picker and return its absolute path (or `None` if cancelled).
@@ -1,16 +1,15 @@
-use serde::{Deserialize, Serialize};
 use tauri::State;
 
 use crate::db::DbPool;
 use crate::db::default_managed_root;
+use crate::db::repo::settings as repo;
+use crate::library::watch::LibraryWatcher;
 use std::fs;
+use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Settings {
-    pub library_root: Option<String>,
-    pub use_managed_dir: bool,
-    pub managed_root: String,
-}
+use super::common::resolve_effective_root;
+
+pub use crate::db::repo::settings::Settings;
 
 #[tauri::command]
 pub async fn get_settings(db: State<'_, DbPool>) -> Result<Settings, String> {
@@ -18,64 +17,53 @@ pub async fn get_settings(db: State<'_, DbPool>) -> Result<Settings, String> {
 
     // Ensure managed_root is set at least to default if missing
     let def_managed = default_managed_root();
-    conn.execute(
-        "UPDATE settings SET managed_root = COALESCE(managed_root, ?1) WHERE id=1",
-        [def_managed.to_string_lossy().to_string()],
-    )
-        .map_err(|e| e.to_string())?;
+    repo::ensure_managed_root(&conn, &def_managed.to_string_lossy()).map_err(|e| e.to_string())?;
 
-    let mut stmt = conn
-        .prepare("SELECT library_root, use_managed_dir, managed_root FROM settings WHERE id=1")
-        .map_err(|e| e.to_string())?;
-
-    let settings: Settings = stmt
-        .query_row([], |row| {
-            let library_root: Option<String> = row.get(0)?;
-            let use_managed_dir: i64 = row.get(1)?;
-            let managed_root: String = row.get(2)?;
-            Ok(Settings {
-                library_root,
-                use_managed_dir: use_managed_dir != 0,
-                managed_root,
-            })
-        })
-        .map_err(|e| e.to_string())?;
+    repo::get(&conn).map_err(|e| e.to_string())
+}
 
-    Ok(settings)
+/// Point the library watcher at the (possibly changed) effective root.
+/// A failing watch is logged, not returned: the setting itself was saved.
+pub(crate) fn rewatch_effective_root(conn: &rusqlite::Connection, watcher: &LibraryWatcher) -> Result<(), String> {
+    let root = resolve_effective_root(conn).map_err(|e| e.to_string())?;
+    if let Err(e) = watcher.watch(root.map(PathBuf::from)) {
+        log::warn!("library watcher: {e}");
+    }
+    Ok(())
 }
 
 #[tauri::command]
-pub async fn set_library_root(path: Option<String>, db: State<'_, DbPool>) -> Result<(), String> {
+pub async fn set_library_root(
+    path: Option<String>,
+    db: State<'_, DbPool>,
+    watcher: State<'_, LibraryWatcher>,
+) -> Result<(), String> {
     let conn = db.get().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE settings SET library_root = ?1 WHERE id=1",
-        [path],
-    )
-        .map_err(|e| e.to_string())?;
-    Ok(())
+    repo::set_library_root(&conn, path.as_deref()).map_err(|e| e.to_string())?;
+    rewatch_effective_root(&conn, &watcher)
 }
 
 #[tauri::command]
-pub async fn set_use_managed_dir(value: bool, db: State<'_, DbPool>) -> Result<(), String> {
+pub async fn set_use_managed_dir(
+    value: bool,
+    db: State<'_, DbPool>,
+    watcher: State<'_, LibraryWatcher>,
+) -> Result<(), String> {
     let conn = db.get().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE settings SET use_managed_dir = ?1 WHERE id=1",
-        [if value { 1 } else { 0 }],
-    )
-        .map_err(|e| e.to_string())?;
-    Ok(())
+    repo::set_use_managed_dir(&conn, value).map_err(|e| e.to_string())?;
+    rewatch_effective_root(&conn, &watcher)
 }
 
 #[tauri::command]
-pub async fn set_managed_root(path: String, db: State<'_, DbPool>) -> Result<(), String> {
+pub async fn set_managed_root(
+    path: String,
+    db: State<'_, DbPool>,
+    watcher: State<'_, LibraryWatcher>,
+) -> Result<(), String> {
     // Make sure it exists on disk
     fs::create_dir_all(&path).map_err(|e| e.to_string())?;
 
     let conn = db.get().map_err(|e| e.to_string())?;
-    conn.execute(
-        "UPDATE settings SET managed_root = ?1 WHERE id=1",
-        [path],
-    )
-        .map_err(|e| e.to_string())?;
-    Ok(())
+    repo::set_managed_root(&conn, &path).map_err(|e| e.to_string())?;
+    rewatch_effective_root(&conn, &watcher)
 }
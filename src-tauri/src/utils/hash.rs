@@ -0,0 +1,14 @@
+use std::{fs, io::Read, path::Path};
+
+/// Content hash used for `tracks.file_hash`.
+pub fn blake3_hex_of_file(p: &Path) -> anyhow::Result<String> {
+    let mut f = fs::File::open(p)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buf = [0u8; 128 * 1024];
+    loop {
+        let n = f.read(&mut buf)?;
+        if n == 0 { break; }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}